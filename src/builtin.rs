@@ -0,0 +1,207 @@
+use crate::color::Color;
+
+/// Marker of the built-in shaders replaced by their parameters.
+const MARKER: &str = "// PARAMETERS";
+
+/// Helpers of the shaders drawing a list of colors, following their parameters.
+const PRELUDE: &str = include_str!("shaders/builtin/prelude.glsl");
+
+/// Sources drawn by a fragment program, without any texture to upload.
+/// They are written like paths:
+///
+/// - `solid:#RRGGBB`
+/// - `linear-gradient:[<degrees>,]<color>,<color>[,...]`, the angle going
+///   clockwise from the top as in CSS (180 by default, top to bottom)
+/// - `radial-gradient:<center color>,[...,]<corner color>`
+/// - `palette:[<seconds>,]<color>,<color>[,...]`, a gradient cycling through
+///   the colors every given number of seconds (60 by default)
+#[derive(Debug, Clone, PartialEq)]
+pub enum Builtin {
+    Solid(Color),
+    LinearGradient { angle: f32, colors: Vec<Color> },
+    RadialGradient(Vec<Color>),
+    Palette { period: f32, colors: Vec<Color> },
+}
+
+/// Linear components, to be written as is to the sRGB render target.
+fn linear_vec4(color: Color) -> String {
+    let [red, green, blue, alpha] = color.to_linear();
+    format!("vec4({:.6}, {:.6}, {:.6}, {:.6})", red, green, blue, alpha)
+}
+
+/// sRGB components, for shaders interpolating colors before converting them.
+fn srgb_vec4(color: Color) -> String {
+    format!(
+        "vec4({:.6}, {:.6}, {:.6}, {:.6})",
+        f32::from(color.red) / 255.0,
+        f32::from(color.green) / 255.0,
+        f32::from(color.blue) / 255.0,
+        f32::from(color.alpha) / 255.0
+    )
+}
+
+/// The colors and the prelude using them.
+fn colors_glsl(colors: &[Color]) -> String {
+    let values: Vec<_> = colors.iter().map(|color| srgb_vec4(*color)).collect();
+    format!(
+        "const int count = {};\nconst vec4 colors[{}] = vec4[{}]({});\n\n{}",
+        colors.len(),
+        colors.len(),
+        colors.len(),
+        values.join(", "),
+        PRELUDE
+    )
+}
+
+/// Parses `[<number>,]<color>,<color>[,...]`, with at least two colors.
+fn parse_list(list: &str) -> Option<(Option<f32>, Vec<Color>)> {
+    let mut items: Vec<&str> = list.split(',').map(str::trim).collect();
+    // Colors may omit their `#`, `123456` is one rather than a number.
+    let first = items.first()?;
+    let number = match first.parse::<f32>() {
+        Ok(number) if first.parse::<Color>().is_err() => {
+            items.remove(0);
+            Some(number)
+        }
+        _ => None,
+    };
+
+    let colors: Result<Vec<Color>, _> = items.iter().map(|item| item.parse()).collect();
+    match colors {
+        Ok(colors) if colors.len() >= 2 => Some((number, colors)),
+        _ => None,
+    }
+}
+
+impl Builtin {
+    /// `None` when `spec` is not a built-in source.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let mut parts = spec.splitn(2, ':');
+        match (parts.next()?, parts.next()?) {
+            ("solid", color) => color.parse().ok().map(Builtin::Solid),
+            ("linear-gradient", list) => {
+                let (angle, colors) = parse_list(list)?;
+                Some(Builtin::LinearGradient {
+                    angle: angle.unwrap_or(180.0),
+                    colors,
+                })
+            }
+            ("radial-gradient", list) => match parse_list(list)? {
+                (None, colors) => Some(Builtin::RadialGradient(colors)),
+                (Some(_), _) => None,
+            },
+            ("palette", list) => {
+                let (period, colors) = parse_list(list)?;
+                Some(Builtin::Palette {
+                    period: period.filter(|period| *period > 0.0).unwrap_or(60.0),
+                    colors,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn fragment(&self) -> String {
+        let (code, parameters) = match self {
+            Builtin::Solid(color) => (
+                include_str!("shaders/builtin/solid.glsl"),
+                format!("const vec4 color = {};", linear_vec4(*color)),
+            ),
+            Builtin::LinearGradient { angle, colors } => (
+                include_str!("shaders/builtin/linear_gradient.glsl"),
+                format!(
+                    "const float angle = {:.6};\n{}",
+                    angle.to_radians(),
+                    colors_glsl(colors)
+                ),
+            ),
+            Builtin::RadialGradient(colors) => (
+                include_str!("shaders/builtin/radial_gradient.glsl"),
+                colors_glsl(colors),
+            ),
+            Builtin::Palette { period, colors } => (
+                include_str!("shaders/builtin/palette.glsl"),
+                format!(
+                    "const float period = {:.6};\n{}",
+                    period,
+                    colors_glsl(colors)
+                ),
+            ),
+        };
+
+        code.replacen(MARKER, &parameters, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn colors(colors: &[&str]) -> Vec<Color> {
+        colors.iter().map(|color| color.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn list_with_and_without_number() {
+        assert_eq!(
+            parse_list("90, #000000, #ffffff"),
+            Some((Some(90.0), colors(&["#000000", "#ffffff"])))
+        );
+        assert_eq!(
+            parse_list("#000000,#ff0000,#ffffff"),
+            Some((None, colors(&["#000000", "#ff0000", "#ffffff"])))
+        );
+    }
+
+    #[test]
+    fn colors_without_hash_are_not_numbers() {
+        assert_eq!(
+            parse_list("123456,654321"),
+            Some((None, colors(&["#123456", "#654321"])))
+        );
+        assert_eq!(
+            parse_list("1.5,123456,654321"),
+            Some((Some(1.5), colors(&["#123456", "#654321"])))
+        );
+    }
+
+    #[test]
+    fn invalid_lists() {
+        for list in &[
+            "",
+            "90",
+            "#000000",
+            "90,#000000",
+            "#000000,red",
+            "90,,#000000,#ffffff",
+            "a,#000000,#ffffff",
+        ] {
+            assert_eq!(parse_list(list), None, "{}", list);
+        }
+    }
+
+    #[test]
+    fn sources() {
+        assert_eq!(
+            Builtin::parse("solid:#ff0000"),
+            Some(Builtin::Solid("#ff0000".parse().unwrap()))
+        );
+        assert_eq!(
+            Builtin::parse("linear-gradient:#000000,#ffffff"),
+            Some(Builtin::LinearGradient {
+                angle: 180.0,
+                colors: colors(&["#000000", "#ffffff"]),
+            })
+        );
+        assert_eq!(
+            Builtin::parse("palette:0,#000000,#ffffff"),
+            Some(Builtin::Palette {
+                period: 60.0,
+                colors: colors(&["#000000", "#ffffff"]),
+            })
+        );
+        assert_eq!(Builtin::parse("radial-gradient:30,#000000,#ffffff"), None);
+        assert_eq!(Builtin::parse("solid"), None);
+        assert_eq!(Builtin::parse("conic-gradient:#000000,#ffffff"), None);
+    }
+}
@@ -0,0 +1,288 @@
+use crate::{config::FitMode, interpolation::Interpolation, source::SourceError};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap, convert::TryFrom, fs::File, io::Read, num::NonZeroU32, path::Path,
+};
+use zip::ZipArchive;
+
+/// Entry of a bundle describing it.
+const MANIFEST: &str = "manifest.toml";
+/// Entries are read in memory, larger ones are refused.
+const MAX_ENTRY_SIZE: u64 = 256 << 20;
+
+/// What a bundle displays.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Gif,
+    /// Images of a directory, in natural order.
+    Frames,
+    Image,
+    /// The shader alone.
+    Fragment,
+}
+
+/// `manifest.toml` of a bundle. Entries are named by their path in the
+/// archive, the settings apply unless the configuration or the flags say
+/// otherwise.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Manifest {
+    #[serde(rename = "type")]
+    pub kind: Kind,
+    /// The GIF or the image, or the directory of the frames.
+    pub frames: Option<String>,
+    pub shader: Option<String>,
+    pub fps: Option<NonZeroU32>,
+    pub rendered_fps: Option<NonZeroU32>,
+    /// A built-in interpolation, or the entry of a GLSL one.
+    pub interpolation: Option<String>,
+    pub fit: Option<FitMode>,
+    /// Constants defined for the shader.
+    #[serde(default)]
+    pub uniforms: BTreeMap<String, toml::Value>,
+    /// Title, author, license and so on, shown when the bundle is loaded.
+    #[serde(default)]
+    pub credits: BTreeMap<String, String>,
+}
+
+/// Bundles are zip archives with this extension.
+pub fn is_bundle(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |extension| extension == "swaynim")
+}
+
+/// A `.swaynim` archive, its entries being read without extracting them.
+pub struct Bundle {
+    archive: ZipArchive<File>,
+    pub manifest: Manifest,
+}
+
+impl Bundle {
+    pub fn open(path: &Path) -> Result<Self, SourceError> {
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+        let text = read_string(&mut archive, MANIFEST)?;
+        let manifest: Manifest =
+            toml::from_str(&text).map_err(|e| format!("{}: {}", MANIFEST, e))?;
+
+        if manifest.kind == Kind::Fragment && manifest.shader.is_none() {
+            return Err("the bundle has neither frames nor a shader".into());
+        }
+        if manifest.kind != Kind::Fragment && manifest.frames.is_none() {
+            return Err("the manifest does not name the frames".into());
+        }
+
+        Ok(Self { archive, manifest })
+    }
+
+    pub fn has_frames(&self) -> bool {
+        self.manifest.kind != Kind::Fragment
+    }
+
+    /// Errors name the entry, so that a broken bundle can be fixed.
+    pub fn read(&mut self, name: &str) -> Result<Vec<u8>, SourceError> {
+        read(&mut self.archive, name)
+    }
+
+    fn read_string(&mut self, name: &str) -> Result<String, SourceError> {
+        read_string(&mut self.archive, name)
+    }
+
+    /// Files of directory `dir`, subdirectories included, in natural order.
+    pub fn entries(&self, dir: &str) -> Vec<String> {
+        let prefix = match dir.trim_end_matches('/') {
+            "" => String::new(),
+            dir => format!("{}/", dir),
+        };
+        let mut entries: Vec<String> = self
+            .archive
+            .file_names()
+            .filter(|name| name.starts_with(&prefix) && !name.ends_with('/'))
+            .map(String::from)
+            .collect();
+        entries.sort_by(|a, b| natord::compare(a, b));
+        entries
+    }
+
+    /// The shader, the uniforms being defined right after its `#version`.
+    pub fn fragment(&mut self) -> Result<Option<String>, SourceError> {
+        let name = match self.manifest.shader.clone() {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        let shader = self.read_string(&name)?;
+
+        let defines = self
+            .manifest
+            .uniforms
+            .iter()
+            .map(|(name, value)| match glsl_value(value) {
+                _ if !is_identifier(name) => {
+                    Err(format!("uniform '{}' is not a GLSL identifier", name))
+                }
+                Some(value) => Ok(format!("#define {} {}\n", name, value)),
+                None => Err(format!(
+                    "uniform {} is not a finite number, a boolean or a vector",
+                    name
+                )),
+            })
+            .collect::<Result<String, _>>()?;
+        let at = if shader.trim_start().starts_with("#version") {
+            shader.find('\n').map_or(shader.len(), |end| end + 1)
+        } else {
+            0
+        };
+
+        Ok(Some(format!(
+            "{}{}{}",
+            &shader[..at],
+            defines,
+            &shader[at..]
+        )))
+    }
+
+    pub fn interpolation(&mut self) -> Result<Option<Interpolation>, SourceError> {
+        let name = match self.manifest.interpolation.clone() {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+
+        match Interpolation::builtin(&name) {
+            Some(interpolation) => Ok(Some(interpolation)),
+            None => Ok(Some(Interpolation::Snippet(self.read_string(&name)?))),
+        }
+    }
+
+    /// The credits on one line, `None` without any.
+    pub fn credits(&self) -> Option<String> {
+        if self.manifest.credits.is_empty() {
+            return None;
+        }
+
+        let credits: Vec<String> = self
+            .manifest
+            .credits
+            .iter()
+            .map(|(key, value)| format!("{}: {}", key, value))
+            .collect();
+        Some(credits.join(", "))
+    }
+}
+
+/// Entry `name` of `archive`, at most `MAX_ENTRY_SIZE` bytes whatever the
+/// archive claims.
+fn read(archive: &mut ZipArchive<File>, name: &str) -> Result<Vec<u8>, SourceError> {
+    let entry = archive
+        .by_name(name)
+        .map_err(|e| format!("{}: {}", name, e))?;
+    if entry.size() > MAX_ENTRY_SIZE {
+        return Err(format!("{}: larger than {} MiB", name, MAX_ENTRY_SIZE >> 20).into());
+    }
+
+    let mut bytes = Vec::new();
+    entry.take(MAX_ENTRY_SIZE + 1).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > MAX_ENTRY_SIZE {
+        return Err(format!("{}: larger than {} MiB", name, MAX_ENTRY_SIZE >> 20).into());
+    }
+    Ok(bytes)
+}
+
+fn read_string(archive: &mut ZipArchive<File>, name: &str) -> Result<String, SourceError> {
+    String::from_utf8(read(archive, name)?).map_err(|e| format!("{}: {}", name, e).into())
+}
+
+/// Names that can be defined without breaking the shader: letters, digits
+/// and underscores not starting with a digit, outside of the reserved `gl_`
+/// prefix and `__`.
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_well = chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_');
+
+    starts_well
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with("gl_")
+        && !name.contains("__")
+}
+
+/// A float GLSL can read, `None` for NaN and infinities.
+fn glsl_float(value: f64) -> Option<String> {
+    Some(format!("{:?}", value)).filter(|_| value.is_finite())
+}
+
+/// Finite numbers, booleans, and vectors of 2 to 4 finite numbers.
+fn glsl_value(value: &toml::Value) -> Option<String> {
+    match value {
+        // GLSL integers are 32-bit.
+        toml::Value::Integer(value) => i32::try_from(*value).ok().map(|value| value.to_string()),
+        toml::Value::Float(value) => glsl_float(*value),
+        toml::Value::Boolean(value) => Some(value.to_string()),
+        toml::Value::Array(values) if (2..=4).contains(&values.len()) => {
+            let components: Option<Vec<String>> = values
+                .iter()
+                .map(|value| match value {
+                    toml::Value::Integer(value) => glsl_float(*value as f64),
+                    toml::Value::Float(value) => glsl_float(*value),
+                    _ => None,
+                })
+                .collect();
+            Some(format!("vec{}({})", values.len(), components?.join(", ")))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_fps_refused() {
+        let manifest = |fps| format!("type = \"gif\"\nframes = \"a.gif\"\n{}", fps);
+        assert!(toml::from_str::<Manifest>(&manifest("fps = 0")).is_err());
+        assert!(toml::from_str::<Manifest>(&manifest("rendered_fps = 0")).is_err());
+        let manifest: Manifest = toml::from_str(&manifest("fps = 12")).unwrap();
+        assert_eq!(manifest.fps, NonZeroU32::new(12));
+    }
+
+    #[test]
+    fn identifiers() {
+        for valid in &["speed", "_tint", "colorA2"] {
+            assert!(is_identifier(valid), "{}", valid);
+        }
+        for invalid in &[
+            "",
+            "2speed",
+            "gl_Position",
+            "a__b",
+            "speed 1\n#define x",
+            "é",
+            "a-b",
+        ] {
+            assert!(!is_identifier(invalid), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn values() {
+        let value = |toml: &str| {
+            glsl_value(&toml::from_str::<toml::Value>(&format!("v = {}", toml)).unwrap()["v"])
+        };
+        assert_eq!(value("3"), Some(String::from("3")));
+        assert_eq!(value("1.5"), Some(String::from("1.5")));
+        assert_eq!(value("true"), Some(String::from("true")));
+        assert_eq!(value("[1, 0.5]"), Some(String::from("vec2(1.0, 0.5)")));
+        for invalid in &[
+            "nan",
+            "inf",
+            "-inf",
+            "[1.0, nan]",
+            "[1.0]",
+            "[1, 2, 3, 4, 5]",
+            "\"a\"",
+            "4294967296",
+        ] {
+            assert_eq!(value(invalid), None, "{}", invalid);
+        }
+    }
+}
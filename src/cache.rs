@@ -0,0 +1,586 @@
+use crate::{
+    dedup::{self, StableHasher},
+    dirs,
+    resize::Target,
+};
+use image::RgbaImage;
+use log::{info, warn};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    fs::{self, File, OpenOptions},
+    hash::{Hash, Hasher},
+    io::{self, Seek, SeekFrom, Write},
+    os::unix::{ffi::OsStrExt, fs::FileExt, io::AsRawFd},
+    path::{Path, PathBuf},
+    ptr, slice,
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+
+/// Start of every cache file, followed by the format version.
+const MAGIC: &[u8; 8] = b"SWNMCACH";
+const VERSION: u32 = 2;
+
+/// Magic, then version, width, height, frame count, layer count, GIF loop
+/// count (`FOREVER` when looping forever) and flags, as little-endian
+/// `u32`s. The layer of every frame comes next, then the layers as raw RGBA
+/// pixels.
+const HEADER_SIZE: usize = 8 + 7 * 4;
+const FLAG_PALETTED: u32 = 1;
+const FOREVER: u32 = u32::MAX;
+
+/// Sources larger than this once decoded are not cached.
+const MAX_ENTRY_SIZE: u64 = 1 << 30;
+/// The oldest entries are removed past this size.
+const MAX_CACHE_SIZE: u64 = 4 << 30;
+
+/// Paths are hashed as bytes, the way `Path` hashes itself changes with
+/// Rust releases.
+fn hash_path(path: &Path, hasher: &mut StableHasher) {
+    hasher.write(path.as_os_str().as_bytes());
+    hasher.write_u8(0xff);
+}
+
+fn hash_metadata(path: &Path, hasher: &mut StableHasher) -> io::Result<()> {
+    let metadata = fs::metadata(path)?;
+    hash_path(path, hasher);
+    metadata.len().hash(hasher);
+    metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .hash(hasher);
+    Ok(())
+}
+
+/// Cache file of a source, which changes with its path, modification time
+/// and size, those of every image of a sequence, and the output frames are
+/// resampled for. `None` when there is no cache directory.
+pub fn path(source: &Path, images: &[PathBuf], target: Option<Target>) -> Option<PathBuf> {
+    Some(dirs::cache_dir()?.join(file_name(source, images, target)?))
+}
+
+fn file_name(source: &Path, images: &[PathBuf], target: Option<Target>) -> Option<String> {
+    let mut hasher = StableHasher::default();
+    // Patterns do not name a file.
+    let source = fs::canonicalize(source).unwrap_or_else(|_| source.to_path_buf());
+    hash_path(&source, &mut hasher);
+    if source.is_file() {
+        hash_metadata(&source, &mut hasher).ok()?;
+    }
+    for image in images {
+        hash_metadata(image, &mut hasher).ok()?;
+    }
+    target.hash(&mut hasher);
+    VERSION.hash(&mut hasher);
+
+    Some(format!("{:016x}.frames", hasher.finish()))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// Decoded frames memory-mapped from the cache, identical frames sharing
+/// a layer.
+pub struct Entry {
+    data: *mut libc::c_void,
+    length: usize,
+    pub width: u32,
+    pub height: u32,
+    pub repeat: Option<u32>,
+    pub paletted: bool,
+    /// Layer of every frame.
+    pub frame_layers: Vec<u32>,
+    pub layers: u32,
+}
+
+// The mapping is read-only and private to the entry.
+unsafe impl Send for Entry {}
+unsafe impl Sync for Entry {}
+
+impl Entry {
+    /// Maps the cache file at `path`, `None` when it is missing or invalid.
+    pub fn open(path: &Path) -> Option<Self> {
+        let file = File::open(path).ok()?;
+        let length = file.metadata().ok()?.len() as usize;
+        if length < HEADER_SIZE {
+            return None;
+        }
+
+        let data = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                length,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if data == libc::MAP_FAILED {
+            return None;
+        }
+
+        let mut entry = Self {
+            data,
+            length,
+            width: 0,
+            height: 0,
+            repeat: None,
+            paletted: false,
+            frame_layers: Vec::new(),
+            layers: 0,
+        };
+        entry.read_header()?;
+        Some(entry)
+    }
+
+    fn bytes(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.data as *const u8, self.length) }
+    }
+
+    fn read_header(&mut self) -> Option<()> {
+        let bytes = self.bytes();
+        if &bytes[..8] != MAGIC || read_u32(bytes, 8) != VERSION {
+            return None;
+        }
+
+        let (width, height) = (read_u32(bytes, 12), read_u32(bytes, 16));
+        let (total_frame, layers) = (read_u32(bytes, 20) as usize, read_u32(bytes, 24));
+        let (repeat, flags) = (read_u32(bytes, 28), read_u32(bytes, 32));
+
+        // Sizes of a damaged header may not fit.
+        let layers_start = total_frame.checked_mul(4)?.checked_add(HEADER_SIZE)?;
+        let expected = (layers as usize)
+            .checked_mul(4)?
+            .checked_mul(width as usize)?
+            .checked_mul(height as usize)?
+            .checked_add(layers_start)?;
+        if total_frame == 0 || self.length != expected {
+            return None;
+        }
+        let frame_layers: Vec<u32> = (0..total_frame)
+            .map(|frame| read_u32(bytes, HEADER_SIZE + 4 * frame))
+            .collect();
+        if frame_layers.iter().any(|layer| *layer >= layers) {
+            return None;
+        }
+
+        self.width = width;
+        self.height = height;
+        self.repeat = Some(repeat).filter(|repeat| *repeat != FOREVER);
+        self.paletted = flags & FLAG_PALETTED != 0;
+        self.frame_layers = frame_layers;
+        self.layers = layers;
+        Some(())
+    }
+
+    /// RGBA pixels of a layer, straight from the mapping.
+    pub fn layer(&self, layer: u32) -> &[u8] {
+        let size = 4 * self.width as usize * self.height as usize;
+        let start = HEADER_SIZE + 4 * self.frame_layers.len() + layer as usize * size;
+        &self.bytes()[start..start + size]
+    }
+
+    pub fn frame(&self, frame: usize) -> &[u8] {
+        self.layer(self.frame_layers[frame])
+    }
+}
+
+impl Drop for Entry {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.data, self.length);
+        }
+    }
+}
+
+/// Writes a cache entry as frames are decoded, in any order and from any
+/// thread, each frame being written at the offset of its layer. The file
+/// only gets its final name once every frame is there.
+pub struct Writer {
+    file: File,
+    path: PathBuf,
+    temporary: PathBuf,
+    /// Offset of the first layer.
+    layers_start: u64,
+    layer_size: u64,
+    table: Mutex<LayerTable>,
+}
+
+/// Layers given to the frames added so far.
+struct LayerTable {
+    frame_layers: Vec<Option<u32>>,
+    /// Layers written so far, by the hash of their frame.
+    hashes: HashMap<u64, Vec<u32>>,
+    count: u32,
+}
+
+impl Writer {
+    /// `None` when the source is too large to be cached, or on errors,
+    /// which are only logged as the cache is an optimization.
+    pub fn create(
+        path: PathBuf,
+        (width, height): (u32, u32),
+        total_frame: u32,
+        repeat: Option<u32>,
+        paletted: bool,
+    ) -> Option<Self> {
+        let size = 4 * u64::from(width) * u64::from(height) * u64::from(total_frame);
+        if size > MAX_ENTRY_SIZE {
+            info!("Not caching {} MiB of frames", size >> 20);
+            return None;
+        }
+
+        let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+        let result = Self::write_header(&temporary, (width, height), total_frame, repeat, paletted);
+        match result {
+            Ok(file) => Some(Self {
+                file,
+                path,
+                temporary,
+                layers_start: (HEADER_SIZE + 4 * total_frame as usize) as u64,
+                layer_size: 4 * u64::from(width) * u64::from(height),
+                table: Mutex::new(LayerTable {
+                    frame_layers: vec![None; total_frame as usize],
+                    hashes: HashMap::new(),
+                    count: 0,
+                }),
+            }),
+            Err(e) => {
+                warn!("Cannot create {}: {}", temporary.display(), e);
+                None
+            }
+        }
+    }
+
+    fn write_header(
+        temporary: &Path,
+        (width, height): (u32, u32),
+        total_frame: u32,
+        repeat: Option<u32>,
+        paletted: bool,
+    ) -> io::Result<File> {
+        if let Some(dir) = temporary.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        // Layers are read back to be compared with the next frames.
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(temporary)?;
+        file.write_all(MAGIC)?;
+        let flags = if paletted { FLAG_PALETTED } else { 0 };
+        let repeat = repeat.unwrap_or(FOREVER);
+        // The layer count is only known at the end.
+        for value in &[VERSION, width, height, total_frame, 0, repeat, flags] {
+            file.write_all(&value.to_le_bytes())?;
+        }
+        Ok(file)
+    }
+
+    /// Writes `frame` to a layer of its own unless an identical one was
+    /// written before, layers with the same hash being read back to compare
+    /// them. Only giving it a layer waits for the other threads.
+    pub fn add(&self, index: u32, frame: &RgbaImage) -> io::Result<()> {
+        let hash = dedup::hash(frame);
+        let candidates = self.table.lock().unwrap().hashes.get(&hash).cloned();
+        let mut layer_pixels = Vec::new();
+        for layer in candidates.unwrap_or_default() {
+            layer_pixels.resize(frame.len(), 0);
+            self.file
+                .read_exact_at(&mut layer_pixels, self.layer_offset(layer))?;
+            if layer_pixels[..] == **frame {
+                self.table.lock().unwrap().frame_layers[index as usize] = Some(layer);
+                return Ok(());
+            }
+        }
+
+        let layer = {
+            let mut table = self.table.lock().unwrap();
+            table.count += 1;
+            table.count - 1
+        };
+        self.file.write_all_at(frame, self.layer_offset(layer))?;
+        let mut table = self.table.lock().unwrap();
+        table.hashes.entry(hash).or_default().push(layer);
+        table.frame_layers[index as usize] = Some(layer);
+        Ok(())
+    }
+
+    fn layer_offset(&self, layer: u32) -> u64 {
+        self.layers_start + u64::from(layer) * self.layer_size
+    }
+
+    /// Writes the layer table and moves the entry in place, then makes room
+    /// in the cache.
+    pub fn finish(mut self) -> io::Result<()> {
+        let table = self.table.get_mut().unwrap();
+        let count = table.count;
+        let frame_layers: Option<Vec<u32>> = table.frame_layers.iter().copied().collect();
+        let frame_layers =
+            frame_layers.ok_or_else(|| io::Error::new(io::ErrorKind::Other, "missing frames"))?;
+
+        self.file.seek(SeekFrom::Start(24))?;
+        self.file.write_all(&count.to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(HEADER_SIZE as u64))?;
+        for layer in frame_layers {
+            self.file.write_all(&layer.to_le_bytes())?;
+        }
+        self.file.sync_all()?;
+        fs::rename(&self.temporary, &self.path)?;
+        info!("Cached frames in {}", self.path.display());
+
+        if let Some(dir) = self.path.parent() {
+            prune(dir, MAX_CACHE_SIZE)?;
+        }
+        Ok(())
+    }
+
+    /// Writes every frame at once.
+    pub fn store(self, frames: &[RgbaImage]) {
+        let path = self.path.clone();
+        let result = frames
+            .iter()
+            .zip(0..)
+            .try_for_each(|(frame, index)| self.add(index, frame))
+            .and_then(|()| self.finish());
+        if let Err(e) = result {
+            warn!("Cannot cache frames in {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// An entry left unfinished, after an error, is not kept.
+impl Drop for Writer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.temporary);
+    }
+}
+
+/// Whether `path` is an unfinished entry of a process that is gone, named
+/// `<hash>.<pid>.tmp`.
+fn is_stale(path: &Path) -> bool {
+    let pid = path
+        .file_stem()
+        .and_then(|stem| Path::new(stem).extension())
+        .and_then(|pid| pid.to_str()?.parse::<libc::pid_t>().ok());
+    let pid = match pid {
+        Some(pid) => pid,
+        None => return false,
+    };
+    // Signal 0 only checks that the process exists.
+    let result = unsafe { libc::kill(pid, 0) };
+    result != 0 && io::Error::last_os_error().raw_os_error() == Some(libc::ESRCH)
+}
+
+/// Removes unfinished entries left by processes that were killed, then the
+/// oldest entries past `max_size`.
+fn prune(dir: &Path, max_size: u64) -> io::Result<()> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .map_or(false, |extension| extension == "tmp")
+            && is_stale(&path)
+        {
+            info!("Removing {} from the cache", path.display());
+            fs::remove_file(path)?;
+        } else if path
+            .extension()
+            .map_or(false, |extension| extension == "frames")
+        {
+            let metadata = fs::metadata(&path)?;
+            entries.push((metadata.modified()?, metadata.len(), path));
+        }
+    }
+    entries.sort();
+
+    let mut size: u64 = entries.iter().map(|(_, length, _)| length).sum();
+    for (_, length, path) in entries {
+        if size <= max_size {
+            break;
+        }
+        info!("Removing {} from the cache", path.display());
+        fs::remove_file(path)?;
+        size -= length;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FitMode;
+    use image::Rgba;
+    use std::{env, sync::Arc, thread};
+
+    /// An empty directory of the system one, for the test `name`.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            env::temp_dir().join(format!("swaynimated-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn frame(color: u8) -> RgbaImage {
+        RgbaImage::from_pixel(3, 2, Rgba([color, 0, 0, 255]))
+    }
+
+    /// Caches frames of the colors of `colors`, added from the last one.
+    fn write(path: &Path, colors: &[u8]) {
+        let writer = Writer::create(
+            path.to_path_buf(),
+            (3, 2),
+            colors.len() as u32,
+            Some(2),
+            true,
+        )
+        .unwrap();
+        for (index, color) in colors.iter().enumerate().rev() {
+            writer.add(index as u32, &frame(*color)).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn key_changes_with_the_source() {
+        let dir = temp_dir("key");
+        let source = dir.join("a.gif");
+        fs::write(&source, b"GIF89a").unwrap();
+        let target = Some(Target {
+            output: (1920, 1080),
+            fit: FitMode::Fill,
+        });
+        let name = file_name(&source, &[], target).unwrap();
+        assert_eq!(file_name(&source, &[], target), Some(name.clone()));
+        assert_ne!(file_name(&source, &[], None), Some(name.clone()));
+        // Resolved from another directory, it is the same file.
+        let relative = dir.join("..").join(dir.file_name().unwrap()).join("a.gif");
+        assert_eq!(file_name(&relative, &[], target), Some(name.clone()));
+        fs::write(&source, b"GIF89a;").unwrap();
+        assert_ne!(file_name(&source, &[], target), Some(name));
+
+        // Images of a sequence have to be there.
+        let image = dir.join("0001.png");
+        assert_eq!(file_name(&dir, slice::from_ref(&image), None), None);
+        fs::write(&image, b"").unwrap();
+        assert!(file_name(&dir, &[image], None).is_some());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn entry_written_then_read() {
+        let dir = temp_dir("entry");
+        let path = dir.join("entry.frames");
+        write(&path, &[1, 2, 1, 3, 2]);
+        // Only the finished entry is left.
+        let files: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|file| file.unwrap().path())
+            .collect();
+        assert_eq!(files, slice::from_ref(&path));
+
+        let entry = Entry::open(&path).unwrap();
+        assert_eq!((entry.width, entry.height), (3, 2));
+        assert_eq!(entry.repeat, Some(2));
+        assert!(entry.paletted);
+        assert_eq!(entry.layers, 3);
+        // Layers are given from the last frame on.
+        assert_eq!(entry.frame_layers, [2, 0, 2, 1, 0]);
+        for (index, color) in [1, 2, 1, 3, 2].iter().enumerate() {
+            let case = format!("frame {}", index);
+            assert_eq!(entry.frame(index), &*frame(*color), "{}", case);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_entries_refused() {
+        let dir = temp_dir("invalid");
+        let path = dir.join("entry.frames");
+        write(&path, &[1, 2]);
+        let bytes = fs::read(&path).unwrap();
+
+        let mut outdated = bytes.clone();
+        outdated[8..12].copy_from_slice(&(VERSION - 1).to_le_bytes());
+        let mut out_of_range = bytes.clone();
+        out_of_range[HEADER_SIZE..HEADER_SIZE + 4].copy_from_slice(&2_u32.to_le_bytes());
+        let mut too_large = bytes.clone();
+        too_large[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        let cases = [
+            ("empty", Vec::new()),
+            ("truncated header", bytes[..HEADER_SIZE - 1].to_vec()),
+            ("truncated layers", bytes[..bytes.len() - 1].to_vec()),
+            ("another format", [b"NOTCACHE", &bytes[8..]].concat()),
+            ("outdated", outdated),
+            ("layer out of range", out_of_range),
+            ("frames too large", too_large),
+        ];
+        for (case, bytes) in &cases {
+            fs::write(&path, bytes).unwrap();
+            assert!(Entry::open(&path).is_none(), "{}", case);
+        }
+        assert!(Entry::open(&dir.join("missing.frames")).is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn entry_read_from_other_threads() {
+        fn is_send_sync<T: Send + Sync>() {}
+        is_send_sync::<Entry>();
+
+        let dir = temp_dir("threads");
+        let path = dir.join("entry.frames");
+        write(&path, &[4, 5, 4]);
+        let entry = Arc::new(Entry::open(&path).unwrap());
+        let threads: Vec<_> = (0..3)
+            .map(|index| {
+                let entry = Arc::clone(&entry);
+                thread::spawn(move || entry.frame(index).to_vec())
+            })
+            .collect();
+        for (thread, color) in threads.into_iter().zip(&[4, 5, 4]) {
+            assert_eq!(thread.join().unwrap(), &*frame(*color));
+        }
+        // The mapping outlives the file.
+        fs::remove_dir_all(dir).unwrap();
+        assert_eq!(entry.frame(1), &*frame(5));
+    }
+
+    #[test]
+    fn oldest_entries_pruned() {
+        let dir = temp_dir("prune");
+        for name in &["a", "b", "c"] {
+            fs::write(dir.join(format!("{}.frames", name)), [0; 10]).unwrap();
+            // Modification times tell the entries apart.
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        fs::write(dir.join("other"), [0; 100]).unwrap();
+        // Unfinished entries of this process, and of none.
+        let unfinished = format!("d.{}.tmp", std::process::id());
+        fs::write(dir.join(&unfinished), []).unwrap();
+        fs::write(dir.join(format!("e.{}.tmp", libc::pid_t::MAX)), []).unwrap();
+
+        prune(&dir, 25).unwrap();
+        let mut files: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|file| file.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            ["b.frames", "c.frames", unfinished.as_str(), "other"]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
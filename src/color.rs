@@ -0,0 +1,141 @@
+use serde::Deserialize;
+use std::{convert::TryFrom, fmt, str::FromStr};
+
+/// An sRGB color, written `#RRGGBB` or `#RRGGBBAA` (the `#` is optional).
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Color {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+    pub alpha: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color {
+        red: 0,
+        green: 0,
+        blue: 0,
+        alpha: 255,
+    };
+
+    /// Linear components, as render targets in an sRGB format expect them.
+    pub fn to_linear(self) -> [f64; 4] {
+        let linear = |component: u8| {
+            let c = f64::from(component) / 255.0;
+            if c <= 0.040_45 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+
+        [
+            linear(self.red),
+            linear(self.green),
+            linear(self.blue),
+            f64::from(self.alpha) / 255.0,
+        ]
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.trim_start_matches('#');
+        let component = |i: usize| {
+            hex.get(i..i + 2)
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+                .ok_or_else(|| format!("invalid color '{}' (expected #RRGGBB or #RRGGBBAA)", s))
+        };
+
+        // Digits only, `from_str_radix` accepting a sign.
+        match hex.len() {
+            6 | 8 if hex.bytes().all(|byte| byte.is_ascii_hexdigit()) => Ok(Color {
+                red: component(0)?,
+                green: component(2)?,
+                blue: component(4)?,
+                alpha: if hex.len() == 8 { component(6)? } else { 255 },
+            }),
+            _ => Err(format!(
+                "invalid color '{}' (expected #RRGGBB or #RRGGBBAA)",
+                s
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "#{:02x}{:02x}{:02x}{:02x}",
+            self.red, self.green, self.blue, self.alpha
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color(red: u8, green: u8, blue: u8, alpha: u8) -> Color {
+        Color {
+            red,
+            green,
+            blue,
+            alpha,
+        }
+    }
+
+    #[test]
+    fn opaque_and_transparent() {
+        assert_eq!("#102030".parse(), Ok(color(0x10, 0x20, 0x30, 255)));
+        assert_eq!("ABCDEF80".parse(), Ok(color(0xab, 0xcd, 0xef, 0x80)));
+        assert_eq!("#abcdef".parse(), Ok(color(0xab, 0xcd, 0xef, 255)));
+    }
+
+    #[test]
+    fn display_round_trip() {
+        let parsed: Color = "#01fe7f40".parse().unwrap();
+        assert_eq!(parsed.to_string(), "#01fe7f40");
+        assert_eq!(parsed.to_string().parse(), Ok(parsed));
+    }
+
+    #[test]
+    fn linear_components() {
+        let close = |a: [f64; 4], b: [f64; 4]| a.iter().zip(&b).all(|(a, b)| (a - b).abs() < 1e-4);
+        assert!(close(Color::BLACK.to_linear(), [0.0, 0.0, 0.0, 1.0]));
+        assert!(close(
+            color(255, 255, 128, 0).to_linear(),
+            [1.0, 1.0, 0.215_861, 0.0]
+        ));
+    }
+
+    #[test]
+    fn invalid_colors() {
+        for invalid in &[
+            "",
+            "#",
+            "#12345",
+            "#1234567",
+            "#123456789",
+            "#GG0000",
+            "+1+2+3",
+            "#-1-2-3",
+            "#12 456",
+            "#é1234",
+        ] {
+            assert!(invalid.parse::<Color>().is_err(), "{}", invalid);
+        }
+    }
+}
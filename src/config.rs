@@ -0,0 +1,499 @@
+use crate::{
+    builtin::Builtin,
+    bundle::{self, Bundle},
+    color::Color,
+    dirs,
+    source::Source,
+    swaybg, Opt,
+};
+use log::{info, warn};
+use serde::Deserialize;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    convert::TryFrom,
+    env,
+    error::Error,
+    fs, io,
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// How a source is laid out on an output with another aspect ratio.
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub enum FitMode {
+    /// Covers the whole output, distorting the source.
+    Stretch,
+    /// Covers the whole output, cropping the source.
+    Fill,
+    /// Shows the whole source, with borders.
+    Fit,
+    /// Keeps the size of the source, centered.
+    Center,
+    /// Repeats the source from the top left corner, at its size.
+    Tile,
+}
+
+impl FromStr for FitMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stretch" => Ok(FitMode::Stretch),
+            "fill" => Ok(FitMode::Fill),
+            "fit" => Ok(FitMode::Fit),
+            "center" => Ok(FitMode::Center),
+            "tile" => Ok(FitMode::Tile),
+            _ => Err(format!(
+                "unknown fit mode '{}' (expected stretch, fill, fit, center or tile)",
+                s
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for FitMode {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Layer-shell layer the wallpaper surface is put on.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Layer {
+    Background,
+    Bottom,
+    Top,
+    Overlay,
+}
+
+impl FromStr for Layer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "background" => Ok(Layer::Background),
+            "bottom" => Ok(Layer::Bottom),
+            "top" => Ok(Layer::Top),
+            "overlay" => Ok(Layer::Overlay),
+            _ => Err(format!(
+                "unknown layer '{}' (expected background, bottom, top or overlay)",
+                s
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for Layer {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Settings of the configuration file, either global or for one output.
+/// Everything is optional, missing settings come from the next level.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct Section {
+    source: Option<PathBuf>,
+    shader: Option<PathBuf>,
+    fit: Option<FitMode>,
+    /// Zero is refused, frame intervals being divided by it.
+    fps: Option<NonZeroU32>,
+    rendered_fps: Option<NonZeroU32>,
+    layer: Option<Layer>,
+    post_processing: Option<PathBuf>,
+    background: Option<Color>,
+    backdrop: Option<PathBuf>,
+}
+
+impl Section {
+    fn from_options(options: &Opt) -> Self {
+        Self {
+            source: options.frame_path.clone(),
+            shader: options.custom_fragment.clone(),
+            fit: options.fit,
+            fps: options.fps,
+            rendered_fps: options.rendered_fps,
+            layer: options.layer,
+            post_processing: options.post_processing.clone(),
+            background: options.background,
+            backdrop: options.backdrop.clone(),
+        }
+    }
+
+    /// Fills the settings missing from `self` with the ones of `fallback`.
+    fn or(self, fallback: &Section) -> Self {
+        Self {
+            source: self.source.or_else(|| fallback.source.clone()),
+            shader: self.shader.or_else(|| fallback.shader.clone()),
+            fit: self.fit.or(fallback.fit),
+            fps: self.fps.or(fallback.fps),
+            rendered_fps: self.rendered_fps.or(fallback.rendered_fps),
+            layer: self.layer.or(fallback.layer),
+            post_processing: self
+                .post_processing
+                .or_else(|| fallback.post_processing.clone()),
+            background: self.background.or(fallback.background),
+            backdrop: self.backdrop.or_else(|| fallback.backdrop.clone()),
+        }
+    }
+
+    /// Settings a bundle comes with, below every other level.
+    fn from_bundle(path: &Path) -> Self {
+        match Bundle::open(path) {
+            Ok(bundle) => Self {
+                fit: bundle.manifest.fit,
+                fps: bundle.manifest.fps,
+                rendered_fps: bundle.manifest.rendered_fps,
+                ..Self::default()
+            },
+            Err(e) => {
+                warn!("Cannot read {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Makes the paths of the section relative to `base`, leaving built-in
+    /// sources, colors and `-`, the standard input, as they are.
+    fn resolve_paths(&mut self, base: &Path) {
+        let paths = vec![
+            &mut self.source,
+            &mut self.shader,
+            &mut self.post_processing,
+            &mut self.backdrop,
+        ];
+        for path in paths.into_iter().flatten() {
+            let spec = path.to_str();
+            let builtin = spec.and_then(Builtin::parse).is_some();
+            let color = spec.map_or(false, |spec| spec.parse::<Color>().is_ok());
+            let stdin = spec == Some("-");
+            if !builtin && !color && !stdin {
+                *path = resolve_path(base, path);
+            }
+        }
+    }
+}
+
+/// Expands a leading `~` and makes relative paths relative to `base`.
+fn resolve_path(base: &Path, path: &Path) -> PathBuf {
+    if let Ok(relative) = path.strip_prefix("~") {
+        if let Some(home) = env::var_os("HOME") {
+            return PathBuf::from(home).join(relative);
+        }
+    }
+
+    base.join(path)
+}
+
+/// A color, or a source such as a gradient or an image.
+fn backdrop_source(spec: &Path) -> Option<Source> {
+    if let Some(color) = spec.to_str().and_then(|spec| spec.parse().ok()) {
+        return Some(Source {
+            frames: None,
+            fragment: None,
+            builtin: Some(Builtin::Solid(color)),
+        });
+    }
+
+    let source = Source::from_path(spec);
+    if source.is_none() {
+        warn!(
+            "Ignoring the backdrop {}, neither a color nor an image",
+            spec.display()
+        );
+    }
+    source
+}
+
+/// Everything needed to display something on an output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputSettings {
+    pub source: Source,
+    pub fit: FitMode,
+    pub fps: u32,
+    pub rendered_fps: u32,
+    pub layer: Layer,
+    pub post_processing: Option<PathBuf>,
+    /// Around the source when it does not cover the output.
+    pub background: Color,
+    /// What transparent frames are composited on, the background color when
+    /// there is none.
+    pub backdrop: Option<Source>,
+}
+
+/// Contents of `config.toml`: global settings followed by
+/// `[output."<name>"]` sections overriding them for some outputs.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    #[serde(flatten)]
+    defaults: Section,
+    output: HashMap<String, Section>,
+    /// Settings of the bundles displayed so far, so that their manifest is
+    /// read once rather than for every output and every source switch.
+    #[serde(skip)]
+    bundles: RefCell<HashMap<PathBuf, Section>>,
+}
+
+impl Config {
+    fn path(options: &Opt) -> Option<PathBuf> {
+        options
+            .config
+            .clone()
+            .or_else(|| dirs::config_dir().map(|dir| dir.join("config.toml")))
+    }
+
+    /// Reads the configuration file. A missing file is an empty
+    /// configuration, unless its path was given on the command line.
+    pub fn load(options: &Opt) -> Result<Self, Box<dyn Error>> {
+        let path = match Self::path(options) {
+            Some(path) => path,
+            None => return Ok(Self::default()),
+        };
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && options.config.is_none() => {
+                return Ok(Self::default());
+            }
+            Err(e) => return Err(format!("{}: {}", path.display(), e).into()),
+        };
+
+        let mut config: Self =
+            toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let base = path.parent().unwrap_or_else(|| Path::new("."));
+        config.defaults.resolve_paths(base);
+        for section in config.output.values_mut() {
+            section.resolve_paths(base);
+        }
+
+        info!("Loaded configuration from {}", path.display());
+        Ok(config)
+    }
+
+    /// Settings of an output, from the command line first, then its section
+    /// of the configuration file, then the global settings, then the ones of
+    /// a bundle. `source` replaces the configured source and shader, as a
+    /// slideshow or schedule does.
+    pub fn settings(
+        &self,
+        options: &Opt,
+        output: Option<&str>,
+        source: Option<&Source>,
+    ) -> OutputSettings {
+        let section = output
+            .and_then(|name| self.output.get(name))
+            .cloned()
+            .unwrap_or_default();
+        let merged = Section::from_options(options)
+            .or(&section)
+            .or(&self.defaults);
+
+        let background = merged.background.unwrap_or(Color::BLACK);
+        let source = if let Some(source) = source {
+            source.clone()
+        } else {
+            let mut source = match &merged.source {
+                Some(path) => Source::from_path(path).unwrap_or_else(|| Source {
+                    frames: Some(path.clone()),
+                    fragment: None,
+                    builtin: None,
+                }),
+                // Outputs without a source show their background.
+                None => Source {
+                    frames: None,
+                    fragment: None,
+                    builtin: Some(Builtin::Solid(background)),
+                },
+            };
+            if merged.shader.is_some() {
+                source.fragment.clone_from(&merged.shader);
+            }
+            source
+        };
+        let merged = match source
+            .frames
+            .as_deref()
+            .filter(|path| bundle::is_bundle(path))
+        {
+            Some(path) => merged.or(&self.bundle_settings(path)),
+            None => merged,
+        };
+
+        OutputSettings {
+            source,
+            fit: merged.fit.unwrap_or(FitMode::Stretch),
+            fps: merged.fps.map_or(5, NonZeroU32::get),
+            rendered_fps: merged.rendered_fps.map_or(25, NonZeroU32::get),
+            layer: merged.layer.unwrap_or(Layer::Background),
+            post_processing: merged.post_processing,
+            background,
+            backdrop: merged.backdrop.as_deref().and_then(backdrop_source),
+        }
+    }
+
+    /// Settings of the bundle at `path`, its manifest being read the first
+    /// time only.
+    fn bundle_settings(&self, path: &Path) -> Section {
+        self.bundles
+            .borrow_mut()
+            .entry(path.to_path_buf())
+            .or_insert_with(|| Section::from_bundle(path))
+            .clone()
+    }
+
+    /// Applies swaybg arguments over the configuration file, the `*` output
+    /// standing for the global settings.
+    pub fn apply_swaybg(&mut self, outputs: &[swaybg::Output]) {
+        for output in outputs {
+            let section = if output.name == "*" {
+                &mut self.defaults
+            } else {
+                self.output.entry(output.name.clone()).or_default()
+            };
+
+            if let Some(image) = &output.image {
+                section.source = Some(image.clone());
+            }
+            if let Some(color) = output.color {
+                section.background = Some(color);
+            }
+
+            if let Some(swaybg::Mode::Fit(fit)) = output.mode {
+                section.fit = Some(fit);
+            }
+
+            // swaybg shows the color alone in the solid_color mode, and when
+            // there is no image.
+            let solid = output.mode == Some(swaybg::Mode::SolidColor)
+                || (output.image.is_none() && output.color.is_some());
+            if solid {
+                let color = output.color.unwrap_or(Color::BLACK);
+                section.source = Some(PathBuf::from(format!("solid:{}", color)));
+                section.shader = None;
+            }
+        }
+    }
+
+    /// Global source, used as the directory of a slideshow.
+    pub fn default_source(&self, options: &Opt) -> Option<PathBuf> {
+        options
+            .frame_path
+            .clone()
+            .or_else(|| self.defaults.source.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use structopt::StructOpt;
+
+    fn options(args: &[&str]) -> Opt {
+        Opt::from_iter([&["swaynimated"], args].concat())
+    }
+
+    /// A bundle of a GIF with `settings` in its manifest.
+    fn bundle(name: &str, settings: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!(
+            "swaynimated-{}-{}.swaynim",
+            name,
+            std::process::id()
+        ));
+        let mut zip = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        zip.start_file("manifest.toml", zip::write::FileOptions::default())
+            .unwrap();
+        write!(zip, "type = \"gif\"\nframes = \"a.gif\"\n{}", settings).unwrap();
+        zip.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn resolve_paths_from_the_config_directory() {
+        let mut section = Section {
+            source: Some(PathBuf::from("frames/a.gif")),
+            shader: Some(PathBuf::from("/usr/share/shader.glsl")),
+            post_processing: Some(PathBuf::from("solid:#102030")),
+            backdrop: Some(PathBuf::from("#102030")),
+            ..Section::default()
+        };
+        section.resolve_paths(Path::new("/etc/swaynimated"));
+        assert_eq!(
+            section.source,
+            Some(PathBuf::from("/etc/swaynimated/frames/a.gif"))
+        );
+        assert_eq!(
+            section.shader,
+            Some(PathBuf::from("/usr/share/shader.glsl"))
+        );
+        assert_eq!(
+            section.post_processing,
+            Some(PathBuf::from("solid:#102030"))
+        );
+        assert_eq!(section.backdrop, Some(PathBuf::from("#102030")));
+
+        let mut section = Section {
+            source: Some(PathBuf::from("-")),
+            backdrop: Some(PathBuf::from("~/backdrop.png")),
+            ..Section::default()
+        };
+        section.resolve_paths(Path::new("/etc/swaynimated"));
+        assert_eq!(section.source, Some(PathBuf::from("-")));
+        if let Some(home) = env::var_os("HOME") {
+            assert_eq!(
+                section.backdrop,
+                Some(PathBuf::from(home).join("backdrop.png"))
+            );
+        }
+    }
+
+    #[test]
+    fn settings_precedence() {
+        let bundle = bundle("precedence", "fps = 1\nrendered_fps = 2\nfit = \"tile\"");
+        let text = format!(
+            "source = \"{}\"\nfps = 10\nrendered_fps = 50\n\
+             [output.\"DP-1\"]\nfps = 20\nfit = \"center\"",
+            bundle.display()
+        );
+        let config: Config = toml::from_str(&text).unwrap();
+        let timing = |settings: OutputSettings| (settings.fps, settings.rendered_fps, settings.fit);
+
+        let settings = config.settings(&options(&["--fps", "30"]), Some("DP-1"), None);
+        assert_eq!(timing(settings), (30, 50, FitMode::Center));
+        let settings = config.settings(&options(&[]), Some("DP-1"), None);
+        assert_eq!(timing(settings), (20, 50, FitMode::Center));
+        let settings = config.settings(&options(&[]), Some("HDMI-A-1"), None);
+        assert_eq!(timing(settings), (10, 50, FitMode::Tile));
+
+        let config: Config = toml::from_str(&format!("source = \"{}\"", bundle.display())).unwrap();
+        let settings = config.settings(&options(&[]), None, None);
+        assert_eq!(timing(settings), (1, 2, FitMode::Tile));
+        let settings = Config::default().settings(&options(&[]), None, None);
+        assert_eq!(timing(settings), (5, 25, FitMode::Stretch));
+
+        // The manifest is read once.
+        fs::remove_file(bundle).unwrap();
+        let settings = config.settings(&options(&[]), Some("DP-1"), None);
+        assert_eq!(timing(settings), (1, 2, FitMode::Tile));
+    }
+
+    #[test]
+    fn zero_fps_refused() {
+        for text in &["fps = 0", "rendered_fps = 0", "[output.\"DP-1\"]\nfps = 0"] {
+            assert!(toml::from_str::<Config>(text).is_err(), "{}", text);
+        }
+        let config: Config = toml::from_str("fps = 12").unwrap();
+        assert_eq!(config.defaults.fps, NonZeroU32::new(12));
+    }
+}
@@ -0,0 +1,165 @@
+use image::RgbaImage;
+use rayon::prelude::*;
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    hash::{Hash, Hasher},
+};
+
+/// Multiplier of `FxHash`, the hasher of rustc.
+const SEED: u64 = 0x517c_c1b7_2722_0a95;
+
+/// `FxHash`, a word at a time. Unlike `DefaultHasher`, it hashes the same way
+/// whatever the Rust release, for the hashes that end up on disk.
+#[derive(Default)]
+pub struct StableHasher(u64);
+
+impl StableHasher {
+    fn add(&mut self, word: u64) {
+        self.0 = (self.0.rotate_left(5) ^ word).wrapping_mul(SEED);
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        let mut words = bytes.chunks_exact(8);
+        for word in &mut words {
+            self.add(u64::from_le_bytes(word.try_into().unwrap()));
+        }
+        for byte in words.remainder() {
+            self.add(u64::from(*byte));
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Identifies the pixels of a frame, frames with the same hash being
+/// compared to tell whether they are identical.
+pub fn hash(frame: &RgbaImage) -> u64 {
+    let mut hasher = StableHasher::default();
+    frame.dimensions().hash(&mut hasher);
+    hasher.write(frame);
+    hasher.finish()
+}
+
+/// Layer of every frame when identical frames share one, and the first frame
+/// of every layer.
+pub fn deduplicate(frames: &[&RgbaImage]) -> (Vec<u32>, Vec<usize>) {
+    let hashes: Vec<u64> = frames.par_iter().map(|frame| hash(frame)).collect();
+    let mut layers: HashMap<u64, Vec<u32>> = HashMap::new();
+    let mut unique = Vec::new();
+
+    let frame_layers = hashes
+        .iter()
+        .zip(frames)
+        .enumerate()
+        .map(|(index, (hash, frame))| {
+            let candidates = layers.entry(*hash).or_default();
+            let identical = candidates
+                .iter()
+                .find(|layer| frames[unique[**layer as usize]] == *frame)
+                .copied();
+            identical.unwrap_or_else(|| {
+                let layer = unique.len() as u32;
+                unique.push(index);
+                candidates.push(layer);
+                layer
+            })
+        })
+        .collect();
+
+    (frame_layers, unique)
+}
+
+/// Frames given a layer one at a time, as they are received. They are kept
+/// to be compared with the next ones within `budget` bytes, the frames past
+/// it getting a layer of their own.
+pub struct Received {
+    layers: HashMap<u64, Vec<(u32, RgbaImage)>>,
+    budget: usize,
+}
+
+impl Received {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            layers: HashMap::new(),
+            budget,
+        }
+    }
+
+    /// Layer of a frame identical to `frame` added before, else `layer`,
+    /// which `frame` then keeps.
+    pub fn add(&mut self, layer: u32, frame: &RgbaImage) -> u32 {
+        let candidates = self.layers.entry(hash(frame)).or_default();
+        if let Some((identical, _)) = candidates.iter().find(|(_, kept)| kept == frame) {
+            return *identical;
+        }
+        if frame.len() <= self.budget {
+            self.budget -= frame.len();
+            candidates.push((layer, frame.clone()));
+        }
+        layer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn frame(color: u8) -> RgbaImage {
+        RgbaImage::from_pixel(2, 2, Rgba([color, 0, 0, 255]))
+    }
+
+    #[test]
+    fn stable_hash() {
+        // Cache files are named after hashes, which must not change.
+        let mut hasher = StableHasher::default();
+        hasher.write(&[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        let word = (0_u64.rotate_left(5) ^ 0x0807_0605_0403_0201).wrapping_mul(SEED);
+        assert_eq!(
+            hasher.finish(),
+            (word.rotate_left(5) ^ 9).wrapping_mul(SEED)
+        );
+        assert_eq!(hash(&frame(1)), hash(&frame(1)));
+        assert_ne!(hash(&frame(1)), hash(&frame(2)));
+        // The same pixels in another shape.
+        assert_ne!(
+            hash(&frame(1)),
+            hash(&RgbaImage::from_pixel(4, 1, Rgba([1, 0, 0, 255])))
+        );
+    }
+
+    #[test]
+    fn identical_frames_share_a_layer() {
+        let frames = [frame(1), frame(2), frame(1), frame(3), frame(2)];
+        let frames: Vec<&RgbaImage> = frames.iter().collect();
+        assert_eq!(deduplicate(&frames), (vec![0, 1, 0, 2, 1], vec![0, 1, 3]));
+    }
+
+    #[test]
+    fn received_frames_compared_within_the_budget() {
+        let mut received = Received::new(2 * frame(0).len());
+        let cases = [
+            (0, 1, 0),
+            (1, 2, 1),
+            (2, 1, 0),
+            (3, 3, 3),
+            (4, 3, 4),
+            (5, 2, 1),
+        ];
+        for (layer, color, expected) in &cases {
+            let case = format!("frame {} of color {}", layer, color);
+            assert_eq!(received.add(*layer, &frame(*color)), *expected, "{}", case);
+        }
+
+        // Frames with the same hash are told apart by their pixels.
+        let mut received = Received::new(usize::MAX);
+        received.layers.insert(hash(&frame(5)), vec![(0, frame(6))]);
+        assert_eq!(received.add(1, &frame(5)), 1);
+        assert_eq!(received.add(2, &frame(5)), 1);
+    }
+}
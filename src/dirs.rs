@@ -0,0 +1,22 @@
+use std::{env, path::PathBuf};
+
+/// Resolves an XDG base directory, falling back to its default location
+/// under `$HOME`.
+fn base_dir(variable: &str, fallback: &str) -> Option<PathBuf> {
+    match env::var_os(variable) {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback)),
+    }
+}
+
+pub fn state_dir() -> Option<PathBuf> {
+    base_dir("XDG_STATE_HOME", ".local/state").map(|dir| dir.join("swaynimated"))
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    base_dir("XDG_CONFIG_HOME", ".config").map(|dir| dir.join("swaynimated"))
+}
+
+pub fn cache_dir() -> Option<PathBuf> {
+    base_dir("XDG_CACHE_HOME", ".cache").map(|dir| dir.join("swaynimated"))
+}
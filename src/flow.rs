@@ -0,0 +1,173 @@
+use image::RgbaImage;
+use rayon::prelude::*;
+
+/// Side of the square blocks matched between frames, the flow field has one
+/// texel per block.
+pub const BLOCK_SIZE: u32 = 8;
+
+/// Largest displacement searched for, in pixels.
+const SEARCH_RADIUS: i32 = 8;
+
+/// Size of the flow field estimated for frames of the given size.
+pub fn field_size(width: u32, height: u32) -> (u32, u32) {
+    (
+        (width + BLOCK_SIZE - 1) / BLOCK_SIZE,
+        (height + BLOCK_SIZE - 1) / BLOCK_SIZE,
+    )
+}
+
+struct Luma {
+    width: i32,
+    height: i32,
+    pixels: Vec<u8>,
+}
+
+impl Luma {
+    fn new(image: &RgbaImage) -> Self {
+        let pixels = image
+            .pixels()
+            .map(|p| {
+                ((u32::from(p[0]) * 77 + u32::from(p[1]) * 150 + u32::from(p[2]) * 29) >> 8) as u8
+            })
+            .collect();
+
+        Self {
+            width: image.width() as i32,
+            height: image.height() as i32,
+            pixels,
+        }
+    }
+
+    fn get(&self, x: i32, y: i32) -> i32 {
+        let x = x.max(0).min(self.width - 1);
+        let y = y.max(0).min(self.height - 1);
+        i32::from(self.pixels[(y * self.width + x) as usize])
+    }
+}
+
+/// Estimates where every block of `previous` moved to in `next` by
+/// exhaustive block matching.
+///
+/// Displacements are stored as two bytes per block, offset by 128 so they
+/// can be uploaded as a `Rg8Unorm` texture and decoded in the shader with
+/// `value * 255.0 - 128.0`.
+pub fn estimate(previous: &RgbaImage, next: &RgbaImage) -> Vec<u8> {
+    let previous = Luma::new(previous);
+    let next = Luma::new(next);
+    let (field_width, field_height) = field_size(previous.width as u32, previous.height as u32);
+
+    (0..field_width * field_height)
+        .into_par_iter()
+        .flat_map(|block| {
+            let block_x = (block % field_width * BLOCK_SIZE) as i32;
+            let block_y = (block / field_width * BLOCK_SIZE) as i32;
+            let (dx, dy) = best_match(&previous, &next, block_x, block_y);
+            vec![(dx + 128) as u8, (dy + 128) as u8]
+        })
+        .collect()
+}
+
+/// Fields from every frame to the next one, the last wrapping to the first.
+pub fn estimate_loop(frames: &[&RgbaImage]) -> Vec<Vec<u8>> {
+    (0..frames.len())
+        .into_par_iter()
+        .map(|index| estimate(frames[index], frames[(index + 1) % frames.len()]))
+        .collect()
+}
+
+fn best_match(previous: &Luma, next: &Luma, block_x: i32, block_y: i32) -> (i32, i32) {
+    let size = BLOCK_SIZE as i32;
+    let mut best = (0i32, 0i32);
+    let mut best_cost = i32::max_value();
+
+    for dy in -SEARCH_RADIUS..=SEARCH_RADIUS {
+        for dx in -SEARCH_RADIUS..=SEARCH_RADIUS {
+            let mut cost = 0;
+            for y in block_y..block_y + size {
+                for x in block_x..block_x + size {
+                    cost += (previous.get(x, y) - next.get(x + dx, y + dy)).abs();
+                }
+                if cost > best_cost {
+                    break;
+                }
+            }
+
+            // Prefer the smallest displacement on ties, static areas would
+            // otherwise wobble.
+            if cost < best_cost
+                || (cost == best_cost && dx.abs() + dy.abs() < best.0.abs() + best.1.abs())
+            {
+                best_cost = cost;
+                best = (dx, dy);
+            }
+        }
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Noise, so that every block matches at one displacement only.
+    fn noise(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            let hash = (x.wrapping_mul(374_761_393) ^ y.wrapping_mul(668_265_263))
+                .wrapping_mul(1_274_126_177);
+            let value = (hash >> 24) as u8;
+            image::Rgba([value, value, value, 255])
+        })
+    }
+
+    /// Displacement of the block at `(column, row)`.
+    fn vector(field: &[u8], field_width: u32, (column, row): (u32, u32)) -> (i32, i32) {
+        let index = 2 * (row * field_width + column) as usize;
+        (
+            i32::from(field[index]) - 128,
+            i32::from(field[index + 1]) - 128,
+        )
+    }
+
+    #[test]
+    fn field_sizes() {
+        assert_eq!(field_size(64, 64), (8, 8));
+        assert_eq!(field_size(65, 1), (9, 1));
+    }
+
+    #[test]
+    fn find_a_shift() {
+        let previous = noise(48, 48);
+        // Everything moves 3 pixels right and 2 up.
+        let next = RgbaImage::from_fn(48, 48, |x, y| {
+            *previous.get_pixel((x + 45) % 48, (y + 2) % 48)
+        });
+        let field = estimate(&previous, &next);
+        assert_eq!(field.len(), 2 * 6 * 6);
+        for block in &[(1, 1), (2, 3), (4, 2)] {
+            assert_eq!(vector(&field, 6, *block), (3, -2), "{:?}", block);
+        }
+    }
+
+    #[test]
+    fn no_motion_on_still_frames() {
+        let frame = noise(16, 16);
+        let plain = RgbaImage::from_pixel(16, 16, image::Rgba([10, 20, 30, 255]));
+        assert!(estimate(&frame, &frame).iter().all(|value| *value == 128));
+        // Every displacement matches as well, the smallest one wins.
+        assert!(estimate(&plain, &plain).iter().all(|value| *value == 128));
+    }
+
+    #[test]
+    fn fields_loop() {
+        let first = noise(16, 16);
+        let frames = [
+            RgbaImage::from_fn(16, 16, |x, y| *first.get_pixel((x + 1) % 16, y)),
+            first,
+        ];
+        let frames: Vec<_> = frames.iter().collect();
+        let fields = estimate_loop(&frames);
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[1], estimate(frames[1], frames[0]));
+    }
+}
@@ -0,0 +1,105 @@
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// Colors of a palette, as in GIFs.
+pub const PALETTE_SIZE: u32 = 256;
+
+fn color(pixel: &[u8]) -> [u8; 4] {
+    [pixel[0], pixel[1], pixel[2], pixel[3]]
+}
+
+fn distance(a: [u8; 4], b: [u8; 4]) -> i32 {
+    a.iter()
+        .zip(&b)
+        .map(|(a, b)| (i32::from(*a) - i32::from(*b)).pow(2))
+        .sum()
+}
+
+/// Splits the RGBA pixels of a frame `width` pixels wide into one palette
+/// index per pixel and its palette, four bytes per color. Frames with too
+/// many colors, as when GIF disposal keeps pixels of a previous local
+/// palette, keep the most frequent ones and have the others mapped to the
+/// closest kept color.
+pub fn index(frame: &[u8], width: u32) -> (Vec<u8>, Vec<u8>) {
+    let row_size = 4 * width as usize;
+    let counts = frame
+        .par_chunks(row_size)
+        .fold(HashMap::new, |mut counts, row| {
+            for pixel in row.chunks(4) {
+                *counts.entry(color(pixel)).or_insert(0_usize) += 1;
+            }
+            counts
+        })
+        .reduce(HashMap::new, |mut counts, other| {
+            for (color, count) in other {
+                *counts.entry(color).or_insert(0) += count;
+            }
+            counts
+        });
+
+    // Most frequent first, ties in a stable order.
+    let mut colors: Vec<([u8; 4], usize)> = counts.into_iter().collect();
+    colors.sort_unstable_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+    let colors: Vec<[u8; 4]> = colors.into_iter().map(|(color, _)| color).collect();
+    let kept = colors.len().min(PALETTE_SIZE as usize);
+    let (palette, others) = colors.split_at(kept);
+
+    let mut indices: HashMap<[u8; 4], u8> = palette
+        .iter()
+        .enumerate()
+        .map(|(index, color)| (*color, index as u8))
+        .collect();
+    for other in others {
+        let closest = (0..kept).min_by_key(|index| distance(palette[*index], *other));
+        indices.insert(*other, closest.unwrap_or(0) as u8);
+    }
+
+    let data = frame
+        .par_chunks(4)
+        .map(|pixel| indices[&color(pixel)])
+        .collect();
+    let mut palette: Vec<u8> = palette.iter().flatten().copied().collect();
+    palette.resize(4 * PALETTE_SIZE as usize, 0);
+
+    (data, palette)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn most_frequent_colors_first() {
+        let (red, blue) = ([255, 0, 0, 255], [0, 0, 255, 255]);
+        // 2x2 pixels, blue being the most frequent.
+        let frame: Vec<u8> = [red, blue, blue, blue].iter().flatten().copied().collect();
+        let (indices, palette) = index(&frame, 2);
+        assert_eq!(indices, [1, 0, 0, 0]);
+        assert_eq!(palette.len(), 4 * PALETTE_SIZE as usize);
+        assert_eq!(palette[..8], [0, 0, 255, 255, 255, 0, 0, 255]);
+        assert!(palette[8..].iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn extra_colors_mapped_to_the_closest() {
+        // Grays from 0 to 255 twice each, then 256 colors of red only once.
+        let mut frame = Vec::new();
+        for gray in 0..=255_u8 {
+            frame.extend_from_slice(&[gray, gray, gray, 255, gray, gray, gray, 255]);
+        }
+        for red in 0..=255_u8 {
+            frame.extend_from_slice(&[red, 0, 0, 255]);
+        }
+        let (indices, palette) = index(&frame, 16);
+        let colors: Vec<&[u8]> = palette.chunks(4).collect();
+        let cases = [
+            (2 * 10, [10, 10, 10, 255]),
+            (2 * 256, [0, 0, 0, 255]),
+            (2 * 256 + 255, [85, 85, 85, 255]),
+        ];
+        for (pixel, expected) in &cases {
+            let case = format!("pixel {}", pixel);
+            assert_eq!(colors[indices[*pixel] as usize], expected, "{}", case);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Marker of `shaders/frag.glsl` replaced by the interpolation function.
+const MARKER: &str = "// INTERPOLATION";
+
+/// How two consecutive frames are blended by the default fragment shader.
+///
+/// Custom snippets must define
+/// `vec4 interpolate(vec4 previousColor, vec4 nextColor, float mixValue)`,
+/// they can also read every field of the `Locals` uniform block.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    Smoothstep,
+    Cubic,
+    HoldThenFade,
+    Custom(PathBuf),
+    /// A custom snippet read from a bundle.
+    Snippet(String),
+}
+
+impl FromStr for Interpolation {
+    type Err = String;
+
+    /// Anything else than a built-in interpolation is a GLSL file, which
+    /// must exist so that a misspelled name is not taken for one.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Self::builtin(s) {
+            Some(interpolation) => Ok(interpolation),
+            None if Path::new(s).is_file() => Ok(Interpolation::Custom(PathBuf::from(s))),
+            None => Err(format!(
+                "unknown interpolation '{}' (expected linear, smoothstep, cubic, hold or a \
+                 GLSL file)",
+                s
+            )),
+        }
+    }
+}
+
+impl Interpolation {
+    /// `None` when `name` is not a built-in interpolation.
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(Interpolation::Linear),
+            "smoothstep" => Some(Interpolation::Smoothstep),
+            "cubic" => Some(Interpolation::Cubic),
+            "hold" => Some(Interpolation::HoldThenFade),
+            _ => None,
+        }
+    }
+
+    fn glsl(&self) -> io::Result<String> {
+        Ok(match self {
+            Interpolation::Linear => {
+                String::from(include_str!("shaders/interpolation/linear.glsl"))
+            }
+            Interpolation::Smoothstep => {
+                String::from(include_str!("shaders/interpolation/smoothstep.glsl"))
+            }
+            Interpolation::Cubic => String::from(include_str!("shaders/interpolation/cubic.glsl")),
+            Interpolation::HoldThenFade => {
+                String::from(include_str!("shaders/interpolation/hold.glsl"))
+            }
+            Interpolation::Custom(path) => fs::read_to_string(path)?,
+            Interpolation::Snippet(glsl) => glsl.clone(),
+        })
+    }
+
+    /// Splices the interpolation function into a fragment shader source.
+    pub fn splice(&self, fragment: &str) -> io::Result<String> {
+        Ok(fragment.replacen(MARKER, &self.glsl()?, 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_interpolation() {
+        let cases = [
+            ("linear", Interpolation::Linear),
+            ("smoothstep", Interpolation::Smoothstep),
+            ("cubic", Interpolation::Cubic),
+            ("hold", Interpolation::HoldThenFade),
+        ];
+        for (s, expected) in &cases {
+            assert_eq!(s.parse::<Interpolation>().as_ref(), Ok(expected), "{}", s);
+        }
+
+        let file = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/shaders/interpolation/cubic.glsl"
+        );
+        assert_eq!(file.parse(), Ok(Interpolation::Custom(PathBuf::from(file))));
+        for s in &["Linear", "", "nearest", "/nonexistent/interpolation.glsl"] {
+            assert!(s.parse::<Interpolation>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn splice_once() {
+        let snippet = Interpolation::Snippet(String::from("vec4 interpolate();"));
+        let fragment = format!("a\n{}\nb {}", MARKER, MARKER);
+        assert_eq!(
+            snippet.splice(&fragment).unwrap(),
+            format!("a\nvec4 interpolate();\nb {}", MARKER)
+        );
+    }
+}
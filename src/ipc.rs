@@ -0,0 +1,185 @@
+use crate::{
+    pipeline::SceneLoader, platform::CustomEvent, playback::PlaybackMode, source::Source,
+    transition::Transition,
+};
+use log::{info, warn};
+use std::{
+    env,
+    error::Error,
+    fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    str::FromStr,
+    sync::mpsc::{self, Sender},
+    thread,
+};
+use winit::event_loop::{EventLoop, EventLoopProxy};
+
+/// Where the event loop tells whether a command succeeded, with an error
+/// message when it did not.
+pub type Reply = Sender<Result<(), String>>;
+
+/// Sends the outcome of a command to the connection it came from, if any.
+pub fn reply(reply: Option<Reply>, result: Result<(), String>) {
+    if let Some(reply) = reply {
+        // The connection may be closed already.
+        let _ = reply.send(result);
+    }
+}
+
+/// Commands accepted on the control socket, one per line.
+#[derive(Debug)]
+pub enum Command {
+    Playback(PlaybackMode),
+    Transition(Transition),
+    /// Handled by the IPC thread, which decodes the source before handing it
+    /// to the event loop.
+    Source(PathBuf),
+    /// Reads the configuration file again, also sent on SIGHUP.
+    Reload,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.trim().splitn(2, char::is_whitespace);
+        match (words.next(), words.next().map(str::trim)) {
+            (Some("playback"), Some(mode)) => Ok(Command::Playback(mode.parse()?)),
+            (Some("transition"), Some(transition)) => Ok(Command::Transition(transition.parse()?)),
+            (Some("source"), Some(path)) => Ok(Command::Source(PathBuf::from(path))),
+            (Some("reload"), None) => Ok(Command::Reload),
+            (Some(""), _) | (None, _) => Err(String::from("empty command")),
+            (Some(command), _) => Err(format!("unknown command '{}'", command)),
+        }
+    }
+}
+
+pub fn socket_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("SWAYNIMATED_SOCK") {
+        return Some(PathBuf::from(path));
+    }
+
+    env::var_os("XDG_RUNTIME_DIR").map(|dir| PathBuf::from(dir).join("swaynimated.sock"))
+}
+
+/// Starts listening on the control socket, forwarding every parsed command
+/// to the event loop.
+pub fn listen(
+    event_loop: &EventLoop<CustomEvent>,
+    loader: SceneLoader,
+) -> Result<(), Box<dyn Error>> {
+    let path = socket_path().ok_or("neither SWAYNIMATED_SOCK nor XDG_RUNTIME_DIR is set")?;
+
+    // A previous instance that did not exit cleanly leaves its socket behind.
+    if path.exists() {
+        fs::remove_file(&path)?;
+    }
+
+    let listener = UnixListener::bind(&path)?;
+    let event_proxy = event_loop.create_proxy();
+    info!("Listening for commands on {}", path.display());
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => handle_client(stream, &event_proxy, &loader),
+                Err(e) => warn!("IPC connection failed: {}", e),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Turns SIGHUP into a reload command. Must be called before any other
+/// thread is started, so that they all inherit the blocked signal and only the
+/// waiting thread receives it.
+pub fn reload_on_sighup(event_loop: &EventLoop<CustomEvent>) -> Result<(), Box<dyn Error>> {
+    let signals = unsafe {
+        let mut signals: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut signals);
+        libc::sigaddset(&mut signals, libc::SIGHUP);
+        if libc::pthread_sigmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut()) != 0 {
+            return Err("cannot block SIGHUP".into());
+        }
+        signals
+    };
+    let event_proxy = event_loop.create_proxy();
+
+    thread::spawn(move || loop {
+        let mut signal = 0;
+        if unsafe { libc::sigwait(&signals, &mut signal) } != 0 {
+            warn!("Cannot wait for SIGHUP, reloading is only possible through IPC");
+            return;
+        }
+
+        info!("Received SIGHUP");
+        if event_proxy
+            .send_event(CustomEvent::Command(Command::Reload, None))
+            .is_err()
+        {
+            return;
+        }
+    });
+
+    Ok(())
+}
+
+fn handle_client(
+    stream: UnixStream,
+    event_proxy: &EventLoopProxy<CustomEvent>,
+    loader: &SceneLoader,
+) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(e) => {
+            warn!("IPC connection failed: {}", e);
+            return;
+        }
+    };
+
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+
+        // Other commands wait for the event loop to apply them.
+        let (sender, receiver) = mpsc::channel();
+        let event = match line.parse::<Command>() {
+            Ok(Command::Source(path)) => match Source::from_path(&path) {
+                Some(source) => match loader.prepare(&source) {
+                    Ok(prepared) => Ok((CustomEvent::SourcePrepared(prepared), None)),
+                    Err(e) => Err(e.to_string()),
+                },
+                None => Err(format!("unsupported source {}", path.display())),
+            },
+            Ok(command) => Ok((CustomEvent::Command(command, Some(sender)), Some(receiver))),
+            Err(e) => Err(e),
+        };
+
+        let result = match event {
+            Ok((event, receiver)) => {
+                if event_proxy.send_event(event).is_err() {
+                    return;
+                }
+                match receiver.map(|receiver| receiver.recv()) {
+                    Some(Ok(result)) => result,
+                    Some(Err(_)) => return,
+                    None => Ok(()),
+                }
+            }
+            Err(e) => Err(e),
+        };
+        let reply = match result {
+            Ok(()) => String::from("ok"),
+            Err(e) => format!("error: {}", e),
+        };
+
+        if writeln!(writer, "{}", reply).is_err() {
+            return;
+        }
+    }
+}
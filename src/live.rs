@@ -0,0 +1,254 @@
+use crate::source::SourceError;
+use image::RgbaImage;
+use log::{info, warn};
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read},
+    os::unix::fs::FileTypeExt,
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+/// Layout of the pixels of live frames.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PixelFormat {
+    Rgba,
+    Bgra,
+    Rgb,
+}
+
+impl PixelFormat {
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgba | PixelFormat::Bgra => 4,
+            PixelFormat::Rgb => 3,
+        }
+    }
+
+    fn to_rgba(self, bytes: &[u8]) -> Vec<u8> {
+        let pixels = bytes.len() / self.bytes_per_pixel();
+        let mut rgba = Vec::with_capacity(4 * pixels);
+        match self {
+            PixelFormat::Rgba => rgba.extend_from_slice(bytes),
+            PixelFormat::Bgra => {
+                for pixel in bytes.chunks_exact(4) {
+                    rgba.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+                }
+            }
+            PixelFormat::Rgb => {
+                for pixel in bytes.chunks_exact(3) {
+                    rgba.extend_from_slice(&[pixel[0], pixel[1], pixel[2], 255]);
+                }
+            }
+        }
+        rgba
+    }
+}
+
+/// The line starting a live source, `<width> <height> <format> <fps>` as in
+/// `1920 1080 rgba 30`, the format being `rgba`, `bgra` or `rgb`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Header {
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+    pub fps: f32,
+}
+
+impl Header {
+    fn parse(line: &str) -> Result<Self, SourceError> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 4 {
+            return Err(format!(
+                "expected `<width> <height> <format> <fps>`, got {:?}",
+                line.trim()
+            )
+            .into());
+        }
+
+        let width: u32 = fields[0].parse()?;
+        let height: u32 = fields[1].parse()?;
+        let format = match fields[2].to_lowercase().as_str() {
+            "rgba" => PixelFormat::Rgba,
+            "bgra" => PixelFormat::Bgra,
+            "rgb" => PixelFormat::Rgb,
+            other => return Err(format!("unsupported pixel format {}", other).into()),
+        };
+        let fps: f32 = fields[3].parse()?;
+        if width == 0 || height == 0 || !fps.is_finite() || fps <= 0.0 {
+            return Err("the frame size and rate cannot be 0".into());
+        }
+
+        Ok(Self {
+            width,
+            height,
+            format,
+            fps,
+        })
+    }
+
+    fn frame_size(&self) -> usize {
+        self.width as usize * self.height as usize * self.format.bytes_per_pixel()
+    }
+}
+
+/// Whether `path` is read live: `-` for the standard input, or a named pipe.
+pub fn is_live(path: &Path) -> bool {
+    path == Path::new("-")
+        || fs::metadata(path).map_or(false, |metadata| metadata.file_type().is_fifo())
+}
+
+#[derive(Default)]
+struct Latest {
+    header: Option<Header>,
+    /// The last frame read, along with its number from 1 on.
+    frame: Option<(u64, Arc<RgbaImage>)>,
+    /// Nothing more is read, after the end of the input or an error.
+    ended: bool,
+}
+
+/// Frames of a live source, read by a background thread as they arrive. Only
+/// the latest one is kept, the scenes showing it upload it when they render.
+pub struct Feed {
+    latest: Mutex<Latest>,
+}
+
+impl Feed {
+    /// Starts reading `path`. Opening a named pipe waits for a writer, the
+    /// header is known once it has written it.
+    pub fn open(path: &Path) -> Arc<Self> {
+        let feed = Arc::new(Self {
+            latest: Mutex::new(Latest::default()),
+        });
+
+        let reader = Arc::clone(&feed);
+        let path = path.to_path_buf();
+        thread::spawn(move || {
+            match reader.read(&path) {
+                Ok(()) => info!("{} ended", path.display()),
+                Err(e) => warn!("Cannot read {}: {}", path.display(), e),
+            }
+            reader.latest.lock().unwrap().ended = true;
+        });
+
+        feed
+    }
+
+    fn read(&self, path: &Path) -> Result<(), SourceError> {
+        let input: Box<dyn Read> = if path == Path::new("-") {
+            Box::new(io::stdin())
+        } else {
+            Box::new(File::open(path)?)
+        };
+        let mut input = BufReader::new(input);
+
+        let mut line = String::new();
+        input.read_line(&mut line)?;
+        let header = Header::parse(&line)?;
+        info!(
+            "Reading {}x{} {:?} frames at {} fps from {}",
+            header.width,
+            header.height,
+            header.format,
+            header.fps,
+            path.display()
+        );
+        self.latest.lock().unwrap().header = Some(header);
+
+        let mut bytes = vec![0; header.frame_size()];
+        for number in 1.. {
+            match input.read_exact(&mut bytes) {
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                result => result?,
+            }
+            let frame =
+                RgbaImage::from_raw(header.width, header.height, header.format.to_rgba(&bytes))
+                    .ok_or("truncated frame")?;
+            self.latest.lock().unwrap().frame = Some((number, Arc::new(frame)));
+        }
+
+        Ok(())
+    }
+
+    /// Ended feeds keep their last frame, the input is opened again for new
+    /// scenes.
+    pub fn has_ended(&self) -> bool {
+        self.latest.lock().unwrap().ended
+    }
+
+    pub fn header(&self) -> Option<Header> {
+        self.latest.lock().unwrap().header
+    }
+
+    /// The latest frame along with its number, when newer than frame `after`.
+    pub fn frame_after(&self, after: u64) -> Option<(u64, Arc<RgbaImage>)> {
+        match &self.latest.lock().unwrap().frame {
+            Some((number, frame)) if *number > after => Some((*number, Arc::clone(frame))),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header() {
+        let header = Header::parse("1920 1080 rgba 30\n").unwrap();
+        assert_eq!(
+            header,
+            Header {
+                width: 1920,
+                height: 1080,
+                format: PixelFormat::Rgba,
+                fps: 30.0,
+            }
+        );
+        assert_eq!(header.frame_size(), 1920 * 1080 * 4);
+    }
+
+    #[test]
+    fn formats_in_any_case() {
+        assert_eq!(
+            Header::parse("2 2 BGRA 0.5").unwrap().format,
+            PixelFormat::Bgra
+        );
+        let header = Header::parse(" 3  1\trgb 60 ").unwrap();
+        assert_eq!(header.format, PixelFormat::Rgb);
+        assert_eq!(header.frame_size(), 9);
+    }
+
+    #[test]
+    fn pixels_to_rgba() {
+        assert_eq!(PixelFormat::Rgba.to_rgba(&[1, 2, 3, 4]), [1, 2, 3, 4]);
+        assert_eq!(
+            PixelFormat::Bgra.to_rgba(&[1, 2, 3, 4, 5, 6, 7, 8]),
+            [3, 2, 1, 4, 7, 6, 5, 8]
+        );
+        assert_eq!(
+            PixelFormat::Rgb.to_rgba(&[1, 2, 3, 4, 5, 6]),
+            [1, 2, 3, 255, 4, 5, 6, 255]
+        );
+    }
+
+    #[test]
+    fn invalid_headers() {
+        for line in &[
+            "",
+            "1920 1080 rgba",
+            "1920 1080 rgba 30 1",
+            "0 1080 rgba 30",
+            "1920 0 rgba 30",
+            "-1 1080 rgba 30",
+            "1920 1080 yuv 30",
+            "1920 1080 rgba 0",
+            "1920 1080 rgba -30",
+            "1920 1080 rgba NaN",
+            "1920 1080 rgba inf",
+        ] {
+            assert!(Header::parse(line).is_err(), "{}", line);
+        }
+    }
+}
@@ -1,10 +1,36 @@
 #![deny(clippy::all, clippy::pedantic)]
 
+mod builtin;
+mod bundle;
+mod cache;
+mod color;
+mod config;
+mod dedup;
+mod dirs;
+mod flow;
+mod indexed;
+mod interpolation;
+mod ipc;
+mod live;
 mod pipeline;
 mod platform;
+mod playback;
+mod random;
+mod resize;
+mod schedule;
+mod sequence;
+mod slideshow;
+mod source;
+mod sprites;
+mod swaybg;
+mod transition;
+mod y4m;
 
+use std::env;
 use std::error::Error;
+use std::num::NonZeroU32;
 use std::path::PathBuf;
+use std::sync::Arc;
 use structopt::StructOpt;
 
 use winit::{
@@ -13,15 +39,24 @@ use winit::{
 };
 
 use std::time::Duration;
-use std::time::Instant;
 
+use crate::color::Color;
+use crate::config::{Config, FitMode, Layer};
+use crate::interpolation::Interpolation;
+use crate::ipc::Command;
 use crate::pipeline::{Pipeline, PipelineWindows};
 use crate::platform::CustomEvent;
+use crate::playback::PlaybackMode;
+use crate::schedule::Schedule;
+use crate::slideshow::{Order, Slideshow};
+use crate::source::Source;
+use crate::transition::Transition;
 
 #[derive(Debug, StructOpt)]
 #[structopt(
     name = "swaynimated",
-    about = "Animating your wl-roots compositor since 2019"
+    about = "Animating your wl-roots compositor since 2019",
+    after_help = "Settings can also be given, globally or per output, in $XDG_CONFIG_HOME/swaynimated/config.toml. Flags override them. Send SIGHUP or the `reload` command to apply changes to the file.\n\nThe swaybg arguments are accepted too (-o <output> -i <image> -m <mode> -c <color>), when called as `swaybg`, with `--swaybg` first, or starting with `-o`, so that swaynimated can be sway's `swaybg_command`."
 )]
 pub struct Opt {
     #[structopt(short, long, help = "Enabled debug (verbose) output")]
@@ -30,18 +65,16 @@ pub struct Opt {
     #[structopt(
         short = "f",
         long = "fps",
-        default_value = "5",
-        help = "The number of frame per second of the animation."
+        help = "The number of frame per second of the animation. Defaults to 5."
     )]
-    fps: u32,
+    fps: Option<NonZeroU32>,
 
     #[structopt(
         short = "r",
         long = "rendered_fps",
-        default_value = "25",
-        help = "The number of frame rendered (interpolate with mix between frames). To disable put the same as the number of frame."
+        help = "The number of frame rendered (interpolate with mix between frames). To disable put the same as the number of frame. Defaults to 25."
     )]
-    rendered_fps: u32,
+    rendered_fps: Option<NonZeroU32>,
 
     #[structopt(
         short = "g",
@@ -50,26 +83,266 @@ pub struct Opt {
     )]
     custom_fragment: Option<PathBuf>,
 
+    #[structopt(
+        short = "p",
+        long = "playback",
+        default_value = "loop",
+        help = "How frames are played: loop, ping-pong, reverse, once (then hold the last frame) or random."
+    )]
+    playback: PlaybackMode,
+
+    #[structopt(
+        short = "i",
+        long = "interpolation",
+        help = "Blending between frames: linear (the default, unless a bundle comes with its own), smoothstep, cubic, hold (then fade), or a GLSL file defining `vec4 interpolate(vec4 previousColor, vec4 nextColor, float mixValue)`. Ignored with a custom fragment."
+    )]
+    interpolation: Option<Interpolation>,
+
+    #[structopt(
+        short = "m",
+        long = "motion_compensation",
+        help = "Estimate the motion between frames at load time and follow it when interpolating, instead of only cross-fading. Slow to load. Ignored with a custom fragment."
+    )]
+    motion_compensation: bool,
+
+    #[structopt(
+        long = "palette",
+        help = "Store GIF frames as palette indices on the GPU, a quarter of the video memory of colors. Ignored with a custom fragment."
+    )]
+    palette: bool,
+
+    #[structopt(
+        long = "stream",
+        help = "Keep only that many frames in video memory, decoding the next ones while playing, for animations too long to fit. Motion compensation is ignored."
+    )]
+    stream: Option<u32>,
+
+    #[structopt(
+        long = "staging_memory",
+        default_value = "256",
+        help = "Memory, in MiB, taken at most by frames decoded and waiting to be uploaded, and by the staging buffers uploading them, so that it stays flat whatever the frame count. Frames are all kept until the motion is estimated with motion compensation."
+    )]
+    staging_memory: u32,
+
+    #[structopt(
+        long = "video_memory",
+        default_value = "2048",
+        help = "Video memory, in MiB, the sources of every output may take along with the swap chains and offscreen targets, a new source getting what the others leave. Over it, GIF frames are stored as palette indices, or else downscaled to half their size at most, or else streamed."
+    )]
+    video_memory: u32,
+
+    #[structopt(
+        long = "max_texture_size",
+        default_value = "4096",
+        help = "Width and height of the largest texture the GPU takes, larger frames being cut into tiles. The default is the one every Vulkan device supports, smaller values are ignored."
+    )]
+    max_texture_size: u32,
+
+    #[structopt(
+        long = "max_texture_layers",
+        default_value = "256",
+        help = "Layers of the largest texture array the GPU takes, frames being split across 4 arrays. The default is the one every Vulkan device supports, smaller values are ignored."
+    )]
+    max_texture_layers: u32,
+
+    #[structopt(
+        short = "s",
+        long = "slideshow",
+        help = "Treat the path as a directory of GIFs, frame directories, images and GLSL fragments, and switch to another one every given number of minutes. The custom fragment option is ignored."
+    )]
+    slideshow: Option<f32>,
+
+    #[structopt(
+        long = "slideshow_order",
+        default_value = "shuffle",
+        help = "Order of the slideshow: sequential, shuffle or persisted (shuffled once and resumed across restarts)."
+    )]
+    slideshow_order: Order,
+
+    #[structopt(
+        long = "schedule",
+        conflicts_with = "slideshow",
+        help = "Display sources depending on the time of day, instead of the path. The file has one `<time> <source>` line per entry, the time being `HH:MM`, `sunrise` or `sunset` (optionally offset, as in `sunset-0:30`)."
+    )]
+    schedule: Option<PathBuf>,
+
+    #[structopt(
+        long = "schedule_transition",
+        default_value = "10",
+        help = "Duration in minutes of the blending between two entries of the schedule."
+    )]
+    schedule_transition: f32,
+
+    #[structopt(
+        long = "latitude",
+        allow_hyphen_values = true,
+        requires = "longitude",
+        help = "Latitude in degrees used to compute the sunrise and the sunset."
+    )]
+    latitude: Option<f64>,
+
+    #[structopt(
+        long = "longitude",
+        allow_hyphen_values = true,
+        requires = "latitude",
+        help = "Longitude in degrees (east positive) used to compute the sunrise and the sunset."
+    )]
+    longitude: Option<f64>,
+
+    #[structopt(
+        short = "t",
+        long = "transition_duration",
+        default_value = "1",
+        help = "Duration in seconds of the transition when switching to another source."
+    )]
+    transition_duration: f32,
+
+    #[structopt(
+        long = "transition",
+        default_value = "fade",
+        help = "Transition when switching to another source: fade, wipe, dissolve, zoom, or a gl-transitions compatible GLSL file."
+    )]
+    transition: Transition,
+
+    #[structopt(
+        long = "fit",
+        help = "How the frames are laid out on outputs with another aspect ratio: stretch, fill, fit, center or tile. Defaults to stretch."
+    )]
+    fit: Option<FitMode>,
+
+    #[structopt(
+        long = "background",
+        help = "Color (#RRGGBB or #RRGGBBAA) around the frames when they do not cover the output. Defaults to black."
+    )]
+    background: Option<Color>,
+
+    #[structopt(
+        long = "backdrop",
+        help = "What transparent frames are composited on: a color, a built-in gradient (as `linear-gradient:#000000,#203040`) or an image, filling the output. Defaults to the background color. Custom fragments are only composited on a given backdrop."
+    )]
+    backdrop: Option<PathBuf>,
+
+    #[structopt(
+        long = "layer",
+        help = "Layer the wallpaper is put on: background, bottom, top or overlay. Defaults to background."
+    )]
+    layer: Option<Layer>,
+
+    #[structopt(
+        long = "post_processing",
+        help = "GLSL file defining `vec4 postProcess(vec2 uv)`, applied to the whole output. It can call `getColor(uv)` and read `resolution` and `time`."
+    )]
+    post_processing: Option<PathBuf>,
+
+    #[structopt(
+        long = "config",
+        help = "Configuration file to read instead of $XDG_CONFIG_HOME/swaynimated/config.toml."
+    )]
+    config: Option<PathBuf>,
+
     #[structopt(parse(from_os_str))]
-    frame_path: PathBuf,
+    frame_path: Option<PathBuf>,
+}
+
+/// Reads the configuration file, with the swaybg arguments applied over it.
+fn load_config(opt: &Opt, swaybg: Option<&[swaybg::Output]>) -> Result<Config, Box<dyn Error>> {
+    let mut config = Config::load(opt)?;
+    if let Some(outputs) = swaybg {
+        config.apply_swaybg(outputs);
+    }
+    Ok(config)
+}
+
+/// Source a slideshow or a schedule imposes on every output.
+fn imposed_source<'a>(
+    slideshow: Option<&'a Slideshow>,
+    schedule: Option<&'a Schedule>,
+) -> Option<&'a Source> {
+    slideshow
+        .map(Slideshow::current)
+        .or_else(|| schedule.map(Schedule::current))
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let opt = Opt::from_args();
+    let args: Vec<String> = env::args_os()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let swaybg = if swaybg::is_invocation(&args) {
+        Some(swaybg::parse(&args)?)
+    } else {
+        None
+    };
+    let opt = match swaybg {
+        Some(_) => Opt::from_iter(&["swaynimated"]),
+        None => Opt::from_args(),
+    };
 
+    // Before anything starts a thread.
     let event_loop = EventLoop::with_user_event();
+    if let Err(e) = ipc::reload_on_sighup(&event_loop) {
+        log::warn!("Reloading on SIGHUP disabled: {}", e);
+    }
+
+    let config = load_config(&opt, swaybg.as_deref())?;
+
+    let mut slideshow = match opt.slideshow {
+        Some(minutes) => Some(Slideshow::new(
+            &config
+                .default_source(&opt)
+                .ok_or("the slideshow needs a directory")?,
+            opt.slideshow_order,
+            Duration::from_secs_f32(minutes * 60.0),
+        )?),
+        None => None,
+    };
+    let mut schedule = match &opt.schedule {
+        Some(path) => Some(Schedule::load(
+            path,
+            match (opt.latitude, opt.longitude) {
+                (Some(latitude), Some(longitude)) => Some((latitude, longitude)),
+                _ => None,
+            },
+            Duration::from_secs_f32(opt.schedule_transition * 60.0),
+        )?),
+        None => None,
+    };
+    let decoded = match (&mut slideshow, &mut schedule) {
+        (Some(slideshow), _) => Some(Arc::new(slideshow.first()?)),
+        (None, Some(schedule)) => Some(Arc::new(schedule.first()?)),
+        (None, None) => None,
+    };
+
     let mut pipeline = Pipeline::new(&opt)?;
-    let mut windows = PipelineWindows::new(&event_loop, &pipeline);
+    let source = imposed_source(slideshow.as_ref(), schedule.as_ref());
+    let preloaded = match (source, &decoded) {
+        (Some(source), Some(decoded)) => Some((source, decoded)),
+        _ => None,
+    };
+    let mut windows = PipelineWindows::new(
+        &event_loop,
+        &mut pipeline,
+        |output| config.settings(&opt, output, source),
+        preloaded,
+    )?;
+    drop(decoded);
 
-    let timer_length = Duration::new(0, 1_000_000_000 / opt.rendered_fps);
-    let mut next_update = Instant::now();
+    if let Some(slideshow) = &mut slideshow {
+        slideshow.start_preloading(pipeline.loader());
+    }
+    if let Some(schedule) = &mut schedule {
+        schedule.start_preloading(pipeline.loader());
+    }
+    if let Err(e) = ipc::listen(&event_loop, pipeline.loader()) {
+        log::warn!("IPC disabled: {}", e);
+    }
 
-    event_loop.run(move |event, _, control_flow| match event {
+    event_loop.run(move |event, event_loop, control_flow| match event {
         Event::WindowEvent {
             event: WindowEvent::CloseRequested,
             window_id,
         } => {
             windows.close(window_id);
+            pipeline.retain_screens(&windows.screens());
             if windows.is_empty() {
                 *control_flow = ControlFlow::Exit
             }
@@ -89,33 +362,63 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .resize(new_size, &pipeline);
         }
 
-        Event::EventsCleared => {
-            *control_flow = ControlFlow::WaitUntil(next_update);
+        Event::UserEvent(CustomEvent::Command(command, reply)) => {
+            let result = match &command {
+                Command::Playback(mode) => {
+                    pipeline.set_playback_mode(*mode);
+                    Ok(())
+                }
+                Command::Transition(transition) => pipeline
+                    .set_transition(transition)
+                    .map_err(|e| e.to_string()),
+                Command::Reload => load_config(&opt, swaybg.as_deref())
+                    .map(|config| {
+                        let source = imposed_source(slideshow.as_ref(), schedule.as_ref());
+                        windows.reload(event_loop, &mut pipeline, |output| {
+                            config.settings(&opt, output, source)
+                        });
+                    })
+                    .map_err(|e| e.to_string()),
+                // Prepared by the IPC thread, which sends the source instead.
+                Command::Source(_) => Ok(()),
+            };
+            if let Err(e) = &result {
+                log::warn!("Cannot apply {:?}: {}", command, e);
+            }
+            ipc::reply(reply, result);
         }
 
-        Event::NewEvents(StartCause::WaitCancelled {
-            requested_resume, ..
-        }) => {
-            next_update = requested_resume.unwrap_or_else(|| Instant::now() + timer_length);
-            *control_flow = ControlFlow::WaitUntil(next_update);
+        Event::UserEvent(CustomEvent::SourcePrepared(prepared)) => {
+            pipeline.set_source(prepared);
+        }
+
+        Event::EventsCleared | Event::NewEvents(StartCause::WaitCancelled { .. }) => {
+            *control_flow = ControlFlow::WaitUntil(pipeline.next_frame_at());
         }
 
         Event::NewEvents(StartCause::ResumeTimeReached { .. }) => {
-            next_update = Instant::now() + timer_length;
-            *control_flow = ControlFlow::WaitUntil(next_update);
-            pipeline.go_to_next_frame();
-            windows.request_redraw();
+            if let Some(slideshow) = &mut slideshow {
+                if let Some(prepared) = slideshow.poll() {
+                    pipeline.set_source(prepared);
+                }
+            }
+            if let Some(schedule) = &mut schedule {
+                if let Some((prepared, duration)) = schedule.poll() {
+                    pipeline.set_source_with_transition(prepared, duration);
+                }
+            }
+            windows.request_redraw(&pipeline.go_to_next_frame());
+            *control_flow = ControlFlow::WaitUntil(pipeline.next_frame_at());
         }
 
         Event::WindowEvent {
             event: WindowEvent::RedrawRequested,
-            ..
+            window_id,
         } => {
-            pipeline.update_shader_globals();
-            windows.render(&mut pipeline);
-            *control_flow = ControlFlow::WaitUntil(next_update)
+            windows.render(window_id, &mut pipeline);
+            *control_flow = ControlFlow::WaitUntil(pipeline.next_frame_at())
         }
 
-        _ => *control_flow = ControlFlow::WaitUntil(next_update),
+        _ => *control_flow = ControlFlow::WaitUntil(pipeline.next_frame_at()),
     });
 }
@@ -1,56 +1,465 @@
 use crate::{
     Opt,
-    platform::CustomEvent
-};
-use image::{
-    gif::GifDecoder,
-    GenericImageView,
-    RgbaImage,
-    AnimationDecoder,
-    ImageDecoder
+    color::Color,
+    config::{FitMode, Layer, OutputSettings},
+    dedup, flow,
+    indexed::{self, PALETTE_SIZE},
+    interpolation::Interpolation,
+    live,
+    platform::{self, CustomEvent},
+    playback::{Playback, PlaybackMode},
+    resize::Target,
+    source::{self, Decoded, DecodedFrames, Frame, Loaded, Loading, Source, SourceError, Stream},
+    transition::Transition,
+    y4m,
 };
 use std::{
+    collections::HashMap,
     error::Error,
-    fs,
-    fs::File,
+    fmt, fs,
     path::{Path, PathBuf},
-    time::SystemTime
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{Receiver, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime}
 };
+use image::RgbaImage;
 use winit::{
     dpi::{PhysicalSize, LogicalSize},
-    event_loop::EventLoop,
+    event_loop::{EventLoop, EventLoopProxy, EventLoopWindowTarget},
     monitor::MonitorHandle,
     platform::unix::WindowBuilderExtUnix,
     window::{Window, WindowBuilder, WindowId},
 };
-use log::info;
+use log::{info, warn};
 use rayon::prelude::*;
 
+/// Marker of `shaders/post.glsl` replaced by the post-processing function.
+const POST_PROCESSING_MARKER: &str = "// POST_PROCESSING";
+
+/// Animation resident ahead of the playback before a source being loaded
+/// starts playing.
+const READAHEAD_SECONDS: u32 = 2;
+
+/// Frames uploaded at most per rendered frame while a source is loading, so
+/// that rendering goes on smoothly.
+const UPLOADS_PER_FRAME: usize = 4;
+
+/// Frames of a video kept on the GPU when `--stream` does not say.
+const VIDEO_RING_SIZE: u32 = 8;
+
+/// Images of the swap chain of an output.
+const SWAP_CHAIN_IMAGES: u64 = 3;
+
+/// Frames over the video memory budget are downscaled down to this scale,
+/// and streamed past it.
+const MIN_DOWNSCALE: f64 = 0.5;
+
+/// Size of a texture and layers of a texture array every Vulkan device
+/// supports, the limits assumed unless larger ones are given.
+const MIN_TEXTURE_DIMENSION: u32 = 4096;
+const MIN_ARRAY_LAYERS: u32 = 256;
+
+/// Texture arrays the frames are split across, as many as the scene shaders
+/// bind.
+const FRAME_ARRAYS: u32 = 4;
+
+/// Texels tiles take from their neighbours on every side, so that filtering
+/// across their edges blends the same texels as within a frame.
+const TILE_GUTTER: u32 = 1;
+
 pub struct Pipeline {
+    scene_options: SceneOptions,
+    loader: SceneLoader,
+    default_transition_duration: Duration,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    linear_sampler: wgpu::Sampler,
+    transition_bind_group_layout: wgpu::BindGroupLayout,
+    transition_pipeline: wgpu::RenderPipeline,
+    post_bind_group_layout: wgpu::BindGroupLayout,
+    screens: Vec<Screen>,
+    next_screen_id: usize,
+    /// Live sources can only be read once, every scene showing one follows
+    /// the same feed.
+    live_feeds: HashMap<PathBuf, Arc<live::Feed>>,
+    /// Size of every output, each one having a swap chain.
+    outputs: Vec<(u32, u32)>,
+}
+
+/// What the outputs sharing the same settings display, animated at their
+/// own pace.
+struct Screen {
+    id: usize,
+    settings: OutputSettings,
+    /// Size of the largest output displaying the screen.
+    output: (u32, u32),
+    increment: f32,
+    frame_interval: Duration,
+    next_frame_at: Instant,
+    started_at: Instant,
+    scene: Scene,
+    /// Scene being replaced since `transition_started_at`.
+    previous_scene: Option<Scene>,
+    transition_started_at: Instant,
+    transition_duration: Duration,
+    post_pipeline: Option<wgpu::RenderPipeline>,
+    /// Drawn under the scenes, filling the output.
+    backdrop: Option<Scene>,
+}
+
+/// Offscreen renderings of both scenes of a transition for one window,
+/// blended by the transition shader.
+struct TransitionTargets {
+    size: (u32, u32),
+    from: wgpu::TextureView,
+    to: wgpu::TextureView,
+    uniform_buf: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    _memory: Allocation,
+}
+
+/// Offscreen rendering of a window, read by the post-processing shader.
+struct PostTargets {
+    size: (u32, u32),
+    input: wgpu::TextureView,
+    uniform_buf: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    _memory: Allocation,
+}
+
+/// Video memory the scenes of every screen and the offscreen targets take,
+/// a new scene being given what they leave of the budget.
+#[derive(Clone, Default)]
+struct MemoryUsage(Arc<AtomicU64>);
+
+impl MemoryUsage {
+    fn total(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Counts `bytes` until the returned allocation is dropped.
+    fn allocate(&self, bytes: u64) -> Allocation {
+        self.0.fetch_add(bytes, Ordering::Relaxed);
+        Allocation {
+            usage: self.clone(),
+            bytes,
+        }
+    }
+}
+
+/// Bytes counted in a `MemoryUsage`, released when dropped along with what
+/// they were allocated for.
+struct Allocation {
+    usage: MemoryUsage,
+    bytes: u64,
+}
+
+impl Allocation {
+    fn grow(&mut self, bytes: u64) {
+        self.usage.0.fetch_add(bytes, Ordering::Relaxed);
+        self.bytes += bytes;
+    }
+
+    fn shrink(&mut self, bytes: u64) {
+        let bytes = bytes.min(self.bytes);
+        self.usage.0.fetch_sub(bytes, Ordering::Relaxed);
+        self.bytes -= bytes;
+    }
+
+    /// Stops counting the bytes before what they were allocated for is
+    /// dropped.
+    fn release(&mut self) {
+        self.shrink(self.bytes);
+    }
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        self.usage.0.fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
+
+/// Settings applied to every scene, whatever its source.
+#[derive(Clone)]
+struct SceneOptions {
+    playback: PlaybackMode,
+    /// Given on the command line, it overrides the one of a bundle.
+    interpolation: Option<Interpolation>,
+    motion_compensation: bool,
+    /// Number of frames kept on the GPU when streaming.
+    stream: Option<u32>,
+    /// Whether GIF frames are stored as palette indices.
+    palette: bool,
+    /// What the textures of the frames may take.
+    limits: TextureLimits,
+    /// Whether the scene is drawn over a backdrop. Custom fragments replace
+    /// what is under them otherwise, as the default one composites
+    /// transparent frames over the background color.
+    backdrop: bool,
+    /// Bytes of frames decoded or being uploaded at most at a time.
+    staging_memory: usize,
+    /// Bytes of video memory the scenes may take along with the swap chains
+    /// and the offscreen targets.
+    video_memory: u64,
+    /// What they take so far, shared by every scene.
+    memory_usage: MemoryUsage,
+}
+
+/// Frames of a scene that are not all uploaded when it is created.
+enum Pending {
+    /// Every frame arriving from the background decoding, with the number
+    /// of frames to load before playing.
+    Loading(Loading, u32),
+    /// Frames decoded on demand around the playback, with the size of the
+    /// ring holding them.
+    Streaming(Stream, u32),
+    /// Frames read live, uploaded as they arrive.
+    Live(Arc<live::Feed>),
+}
+
+/// Where a screen displays its scenes, which preparing them depends on.
+#[derive(Clone, Copy, PartialEq)]
+struct ScreenTarget {
+    id: usize,
+    target: Target,
+    backdrop: bool,
+    fps: u32,
+}
+
+/// Screens the scenes of preloaded sources are prepared for.
+#[derive(Default)]
+struct Displayed {
+    screens: Vec<ScreenTarget>,
+    /// Video memory the swap chains of the outputs take.
+    swap_chain_memory: u64,
+}
+
+/// Prepares scenes without the device, from the threads preloading sources
+/// too, so that opening and decoding them does not stall the event loop.
+#[derive(Clone)]
+pub struct SceneLoader {
+    options: SceneOptions,
+    /// Kept up to date by the pipeline.
+    displayed: Arc<Mutex<Displayed>>,
+}
+
+/// A scene ready to be created: its first frames, the ones to come, and
+/// whether GIF frames are stored as palette indices.
+struct PreparedScene {
+    decoded: Arc<Decoded>,
+    pending: Option<Pending>,
+    palette: bool,
+    /// Video memory the scene will take, counted until it is created.
+    reserved: Allocation,
+}
+
+/// A source opened and decoded, with the scene of every screen displayed at
+/// the time prepared.
+pub struct Prepared {
+    source: Source,
+    decoded: Arc<Decoded>,
+    scenes: Vec<(ScreenTarget, Result<PreparedScene, SourceError>)>,
+}
+
+impl fmt::Debug for Prepared {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Prepared")
+            .field("source", &self.source)
+            .field("decoded", &self.decoded)
+            .field("scenes", &self.scenes.len())
+            .finish()
+    }
+}
+
+/// A source uploaded to the GPU along with its playback state.
+struct Scene {
     position: f32,
     total_frame: u32,
-    increment: f32,
+    /// Size of the frames, `None` for fragment shaders drawing without them.
+    size: Option<(u32, u32)>,
+    playback: Playback,
     use_timestamp: bool,
     started_at: SystemTime,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
     bind_group: wgpu::BindGroup,
     render_pipeline: wgpu::RenderPipeline,
     uniform: Vec<u8>,
     uniform_buf: wgpu::Buffer,
+    loading: Option<SceneLoading>,
+    stream: Option<SceneStream>,
+    live: Option<SceneLive>,
+    /// Layer of every frame when identical frames share one.
+    frame_layers: Option<Vec<u32>>,
+    /// The one of the options, else the one the source comes with, else
+    /// linear.
+    interpolation: Interpolation,
+    /// Custom fragment, for the pipeline to be created again when live
+    /// frames turn out to be tiled.
+    fragment: Option<String>,
+    /// Whether fragments are composited over what is under them.
+    blend: bool,
+    /// Video memory of its frames, released once it is dropped.
+    memory: Allocation,
 }
 
+/// Frames of a scene still arriving from the background decoding.
+struct SceneLoading {
+    receiver: Receiver<Result<Loaded, SourceError>>,
+    textures: FrameTextures,
+    resident: Vec<bool>,
+    /// Number of frames resident from the first one on.
+    prefix: u32,
+    /// Frames to load before playing.
+    readahead: u32,
+    /// The motion between frames comes last, when estimated.
+    flow_pending: bool,
+    /// Frames received so far, to give identical ones a single layer, when
+    /// deduplicating.
+    received: Option<dedup::Received>,
+    /// Uploaded again in place of frames that cannot be loaded.
+    first: RgbaImage,
+    /// The decoding stopped, the frames still missing are substituted.
+    failed: bool,
+}
+
+/// Window of frames around the playback, kept in a ring of texture layers
+/// the shader indexes instead of frames.
+struct SceneStream {
+    requests: Sender<u32>,
+    receiver: Receiver<(u32, Result<Frame, SourceError>)>,
+    textures: FrameTextures,
+    /// Frame held by every layer.
+    layers: Vec<Option<u32>>,
+    /// Frames requested and not received yet.
+    requested: Vec<u32>,
+    /// Frames that cannot be read, and the neighbouring ones shown instead.
+    substitutes: HashMap<u32, u32>,
+}
+
+/// Frames of a live source. The latest one is uploaded over the older of two
+/// layers, the shader blending from the one before.
+struct SceneLive {
+    feed: Arc<live::Feed>,
+    limits: TextureLimits,
+    /// Created with the first frame, the header giving their size.
+    textures: Option<FrameTextures>,
+    /// Number of the latest frame uploaded.
+    received: u64,
+    /// Layer of the latest frame.
+    layer: u32,
+    received_at: Instant,
+    fps: f32,
+}
+
+/// What the textures holding frames may take. wgpu does not tell the limits
+/// of the device, they come from the options.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct TextureLimits {
+    /// Width and height of a texture.
+    dimension: u32,
+    /// Layers of a texture array.
+    array_layers: u32,
+    /// Texture arrays the frames are split across.
+    arrays: u32,
+}
+
+impl Default for TextureLimits {
+    fn default() -> Self {
+        Self {
+            dimension: MIN_TEXTURE_DIMENSION,
+            array_layers: MIN_ARRAY_LAYERS,
+            arrays: FRAME_ARRAYS,
+        }
+    }
+}
+
+impl TextureLimits {
+    /// Custom fragments only sample `t_Color`, the first array.
+    fn sampled_by(self, custom_fragment: bool) -> Self {
+        let arrays = if custom_fragment { 1 } else { self.arrays };
+        Self { arrays, ..self }
+    }
+
+    /// Columns and rows of tiles frames of `size` are cut into, so that each
+    /// one fits in a texture along with its gutter.
+    fn tile_grid(self, (width, height): (u32, u32)) -> (u32, u32) {
+        if width <= self.dimension && height <= self.dimension {
+            return (1, 1);
+        }
+        let inner = self.dimension - 2 * TILE_GUTTER;
+        ((width + inner - 1) / inner, (height + inner - 1) / inner)
+    }
+}
+
+/// Layers of frames, along with their palettes when they are stored as
+/// palette indices, or their chroma planes for videos.
+struct FrameTextures {
+    /// Arrays of `limits.array_layers` layers at most, the last one holding
+    /// the remaining layers. Frames larger than `limits.dimension` take a
+    /// layer per tile, the tiles of a frame layer following each other.
+    arrays: Vec<wgpu::Texture>,
+    /// One row of `PALETTE_SIZE` colors per frame layer.
+    palette: Option<wgpu::Texture>,
+    /// U and V planes, one layer per frame layer.
+    chroma: Option<(wgpu::Texture, wgpu::Texture)>,
+    texels: Texels,
+    size: (u32, u32),
+    /// Columns and rows of tiles the frames are cut into.
+    tiles: (u32, u32),
+    layers: u32,
+    limits: TextureLimits,
+}
+
+/// What the layers of frame textures hold.
+#[derive(Clone, Copy, PartialEq)]
+enum Texels {
+    Rgba,
+    /// Palette indices.
+    Indexed,
+    /// Luma, converted to colors along with the chroma planes.
+    Yuv(y4m::Format),
+}
+
+/// How the frames of a scene are stored on the GPU.
+#[derive(Clone, Copy)]
+struct Storage {
+    texels: Texels,
+    /// Identical frames sharing a layer.
+    deduplicate: bool,
+    limits: TextureLimits,
+}
+
+/// Everything loaded from the source that the render pipeline binds.
+struct Frames {
+    textures: FrameTextures,
+    /// Layer of every frame when identical frames share one.
+    frame_layers: Option<Vec<u32>>,
+    total_frame: u32,
+    repeat: Option<u32>,
+    flow: Option<wgpu::TextureView>,
+}
+
+/// Macros are defined from `NAME` or `NAME=VALUE`. Shaders come from files,
+/// compile errors are returned rather than panicking.
 fn create_shader_module(
     device: &wgpu::Device,
     code: String,
     shader_type: shaderc::ShaderKind,
-) -> Result<wgpu::ShaderModule, Box<dyn Error>> {
-    let mut compiler = shaderc::Compiler::new().unwrap();
-    let options = shaderc::CompileOptions::new().unwrap();
+    macros: &[&str],
+) -> Result<wgpu::ShaderModule, SourceError> {
+    let mut compiler = shaderc::Compiler::new().ok_or("cannot start the shader compiler")?;
+    let mut options = shaderc::CompileOptions::new().ok_or("cannot start the shader compiler")?;
+    for definition in macros {
+        let mut parts = definition.splitn(2, '=');
+        options.add_macro_definition(parts.next().unwrap(), parts.next());
+    }
 
-    let binary_result = compiler
-        .compile_into_spirv(&code, shader_type, "file.glsl", "main", Some(&options))
-        .unwrap();
+    let binary_result =
+        compiler.compile_into_spirv(&code, shader_type, "file.glsl", "main", Some(&options))?;
 
     Ok(device.create_shader_module(binary_result.as_binary()))
 }
@@ -69,6 +478,81 @@ fn create_sampler(device: &wgpu::Device) -> wgpu::Sampler {
     })
 }
 
+/// Flow fields have one texel per block, they need to be filtered when
+/// sampled at the frame resolution. Transitions sample scenes rendered at the
+/// window resolution, filtering matters for the ones moving them around.
+fn create_linear_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Nearest,
+        lod_min_clamp: -100.0,
+        lod_max_clamp: 100.0,
+        compare_function: wgpu::CompareFunction::Always,
+    })
+}
+
+fn create_transition_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        bindings: &[
+            wgpu::BindGroupLayoutBinding {
+                binding: 0,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Sampler,
+            },
+            wgpu::BindGroupLayoutBinding {
+                binding: 1,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::SampledTexture {
+                    multisampled: false,
+                    dimension: wgpu::TextureViewDimension::D2,
+                },
+            },
+            wgpu::BindGroupLayoutBinding {
+                binding: 2,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::SampledTexture {
+                    multisampled: false,
+                    dimension: wgpu::TextureViewDimension::D2,
+                },
+            },
+            wgpu::BindGroupLayoutBinding {
+                binding: 3,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+            },
+        ],
+    })
+}
+
+fn create_post_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        bindings: &[
+            wgpu::BindGroupLayoutBinding {
+                binding: 0,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Sampler,
+            },
+            wgpu::BindGroupLayoutBinding {
+                binding: 1,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::SampledTexture {
+                    multisampled: false,
+                    dimension: wgpu::TextureViewDimension::D2,
+                },
+            },
+            wgpu::BindGroupLayoutBinding {
+                binding: 2,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+            },
+        ],
+    })
+}
+
 fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
     device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         bindings: &[
@@ -90,51 +574,145 @@ fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
                 visibility: wgpu::ShaderStage::FRAGMENT,
                 ty: wgpu::BindingType::UniformBuffer { dynamic: false },
             },
+            wgpu::BindGroupLayoutBinding {
+                binding: 3,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::SampledTexture {
+                    multisampled: false,
+                    dimension: wgpu::TextureViewDimension::D2Array,
+                },
+            },
+            wgpu::BindGroupLayoutBinding {
+                binding: 4,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Sampler,
+            },
+            wgpu::BindGroupLayoutBinding {
+                binding: 5,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::SampledTexture {
+                    multisampled: false,
+                    dimension: wgpu::TextureViewDimension::D2Array,
+                },
+            },
+            wgpu::BindGroupLayoutBinding {
+                binding: 6,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::SampledTexture {
+                    multisampled: false,
+                    dimension: wgpu::TextureViewDimension::D2Array,
+                },
+            },
+            wgpu::BindGroupLayoutBinding {
+                binding: 7,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::SampledTexture {
+                    multisampled: false,
+                    dimension: wgpu::TextureViewDimension::D2Array,
+                },
+            },
+            wgpu::BindGroupLayoutBinding {
+                binding: 8,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::SampledTexture {
+                    multisampled: false,
+                    dimension: wgpu::TextureViewDimension::D2Array,
+                },
+            },
+            wgpu::BindGroupLayoutBinding {
+                binding: 9,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::SampledTexture {
+                    multisampled: false,
+                    dimension: wgpu::TextureViewDimension::D2Array,
+                },
+            },
+            wgpu::BindGroupLayoutBinding {
+                binding: 10,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::SampledTexture {
+                    multisampled: false,
+                    dimension: wgpu::TextureViewDimension::D2Array,
+                },
+            },
         ],
     })
 }
 
 fn get_shaders(
     device: &wgpu::Device,
-    custom_fragment: &Option<PathBuf>,
-) -> Result<(wgpu::ShaderModule, wgpu::ShaderModule), Box<dyn Error>> {
+    custom_fragment: Option<&str>,
+    interpolation: &Interpolation,
+    macros: &[&str],
+) -> Result<(wgpu::ShaderModule, wgpu::ShaderModule), SourceError> {
     let frag_code = match custom_fragment {
-        Some(path) => fs::read_to_string(path).expect("Cannot find custom shader"),
-        None => String::from(include_str!("shaders/frag.glsl")),
+        Some(code) => String::from(code),
+        None => interpolation.splice(include_str!("shaders/frag.glsl"))?,
     };
 
-    let frag = create_shader_module(&device, frag_code, shaderc::ShaderKind::Fragment)?;
+    let frag = create_shader_module(&device, frag_code, shaderc::ShaderKind::Fragment, macros)?;
 
     let vert = create_shader_module(
         &device,
         String::from(include_str!("shaders/vert.glsl")),
         shaderc::ShaderKind::Vertex,
+        &[],
     )?;
 
     Ok((frag, vert))
 }
 
+fn physical_extent(size: PhysicalSize) -> (u32, u32) {
+    (size.width.round() as u32, size.height.round() as u32)
+}
+
 fn create_swap_chain(
     device: &wgpu::Device,
     surface: &wgpu::Surface,
     size: PhysicalSize,
 ) -> wgpu::SwapChain {
+    let (width, height) = physical_extent(size);
     let sc_desc = wgpu::SwapChainDescriptor {
         usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
         format: wgpu::TextureFormat::Bgra8UnormSrgb,
-        width: size.width.round() as u32,
-        height: size.height.round() as u32,
+        width,
+        height,
         present_mode: wgpu::PresentMode::Vsync,
     };
 
     device.create_swap_chain(&surface, &sc_desc)
 }
 
+/// Video memory a render target of `size` takes.
+fn render_target_memory((width, height): (u32, u32)) -> u64 {
+    4 * u64::from(width) * u64::from(height)
+}
+
+/// Texture a scene can be rendered to instead of the swap chain.
+fn create_render_target(device: &wgpu::Device, (width, height): (u32, u32)) -> wgpu::TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+            array_layer_count: 1,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Bgra8UnormSrgb,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        })
+        .create_default_view()
+}
+
 fn create_texture(
     device: &wgpu::Device,
     width: u32,
     height: u32,
     total_frame: u32,
+    format: wgpu::TextureFormat,
 ) -> (wgpu::Extent3d, wgpu::Texture) {
     let extent = wgpu::Extent3d {
         width,
@@ -148,133 +726,882 @@ fn create_texture(
         mip_level_count: 1,
         sample_count: 1,
         dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Rgba8UnormSrgb,
-        usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        format,
+        usage: wgpu::TextureUsage::SAMPLED
+            | wgpu::TextureUsage::COPY_DST
+            | wgpu::TextureUsage::COPY_SRC,
     });
 
     (extent, texture)
 }
 
-fn load_textures(
-    frames_path: &Path,
+/// Copies one layer of `texture` from `data`, `texel_size` bytes per texel.
+fn copy_to_layer(
     device: &wgpu::Device,
-    queue: &mut wgpu::Queue,
-) -> Result<(wgpu::TextureView, u32), Box<dyn Error>> {
-    let pathmd = std::fs::metadata(frames_path).unwrap();
-
-    if pathmd.is_dir() {
-        load_textures_from_path(frames_path, device, queue)
-    } else {
-        load_textures_from_gif(frames_path, device, queue)
-    }
+    encoder: &mut wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+    size: (u32, u32),
+    texel_size: u32,
+    layer: u32,
+    data: &[u8],
+) {
+    copy_to_rows(device, encoder, texture, size, texel_size, (0, layer), data);
 }
 
-fn load_textures_in_gpu(
-    frames: &Vec<&RgbaImage>,
-    total_frame: usize,
-    width: u32,
-    height: u32,
+/// Copies the rows of `layer` from row `y` on from `data`.
+fn copy_to_rows(
     device: &wgpu::Device,
-    queue: &mut wgpu::Queue,
-) -> (wgpu::Texture, usize) {
-    info!("Loading frames");
-
-    let (texture_extent, texture) = create_texture(&device, width, height, total_frame as u32);
-
-    let commands = frames.par_iter().enumerate().map(|(index, frame)| {
-        let mut init_encoder =
-            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
-
-        let temp_buf = device
-            .create_buffer_mapped(frame.len(), wgpu::BufferUsage::COPY_SRC)
-            .fill_from_slice(&frame);
-
-        init_encoder.copy_buffer_to_texture(
-            wgpu::BufferCopyView {
-                buffer: &temp_buf,
-                offset: 0,
-                row_pitch: 4 * width,
-                image_height: height,
-            },
-            wgpu::TextureCopyView {
-                texture: &texture,
-                mip_level: 0,
-                array_layer: index as u32,
-                origin: wgpu::Origin3d {
-                    x: 0.0,
-                    y: 0.0,
-                    z: 0.0,
-                },
+    encoder: &mut wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+    (width, height): (u32, u32),
+    texel_size: u32,
+    (y, layer): (u32, u32),
+    data: &[u8],
+) {
+    let temp_buf = device
+        .create_buffer_mapped(data.len(), wgpu::BufferUsage::COPY_SRC)
+        .fill_from_slice(data);
+
+    encoder.copy_buffer_to_texture(
+        wgpu::BufferCopyView {
+            buffer: &temp_buf,
+            offset: 0,
+            row_pitch: texel_size * width,
+            image_height: height,
+        },
+        wgpu::TextureCopyView {
+            texture,
+            mip_level: 0,
+            array_layer: layer,
+            origin: wgpu::Origin3d {
+                x: 0.0,
+                y: y as f32,
+                z: 0.0,
             },
-            texture_extent,
-        );
-        Ok(init_encoder.finish())
-    });
-    let commands_vec: Result<Vec<_>, Box<dyn Error + Send>> = commands.collect();
-    queue.submit(&commands_vec.unwrap()); // FIXME : Remove unwrap
-    info!("Finished loading frames");
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth: 1,
+        },
+    );
+}
 
-    return (texture, total_frame);
+/// How the frames of `decoded` are stored, as palette indices with `palette`
+/// for GIFs. Custom fragments sample the frames themselves, they get colors
+/// unless the source is a video.
+fn scene_texels(decoded: &Decoded, palette: bool) -> Texels {
+    let frames = match &decoded.frames {
+        Some(frames) => frames,
+        None => return Texels::Rgba,
+    };
+    match &frames.video {
+        Some((format, _)) => Texels::Yuv(*format),
+        None if palette && decoded.fragment.is_none() && frames.paletted => Texels::Indexed,
+        None => Texels::Rgba,
+    }
 }
 
-fn load_textures_from_gif(
-    gif_path: &Path,
-    device: &wgpu::Device,
-    queue: &mut wgpu::Queue,
-) -> Result<(wgpu::TextureView, u32), Box<dyn Error>> {
-    let file_in = File::open(gif_path)?;
-    let decoder = GifDecoder::new(file_in).unwrap();
-    let (width, height) = decoder.dimensions();
-    let frames = decoder
-        .into_frames()
-        .collect_frames()
-        .expect("error decoding gif");
-    let rgba_frames: Vec<_> = frames.par_iter().map(|frame| frame.buffer()).collect();
+/// Video memory a frame layer of `size` takes.
+fn layer_memory((width, height): (u32, u32), texels: Texels) -> u64 {
+    let texels_size = u64::from(width) * u64::from(height);
+    match texels {
+        Texels::Rgba => 4 * texels_size,
+        Texels::Indexed => texels_size + 4 * u64::from(PALETTE_SIZE),
+        Texels::Yuv(format) => {
+            texels_size + 2 * u64::from(format.chroma.0) * u64::from(format.chroma.1)
+        }
+    }
+}
 
-    let (texture, total_frame) =
-        load_textures_in_gpu(&rgba_frames, frames.len(), width, height, device, queue);
+/// Target frames of `size` are downscaled for, so that `layers` of them take
+/// `available` bytes at most as colors. `None` when they would get smaller
+/// than `MIN_DOWNSCALE`, when they fit already, and for centered and tiled
+/// sources, shown at their own size.
+fn downscaled_target(
+    target: Target,
+    (width, height): (u32, u32),
+    (layers, available): (u32, u64),
+    limits: TextureLimits,
+) -> Option<Target> {
+    if target.fit == FitMode::Center || target.fit == FitMode::Tile {
+        return None;
+    }
+    let memory = u64::from(layers) * layer_memory((width, height), Texels::Rgba);
+    let scale = (available as f64 / memory as f64).sqrt();
+    if !(MIN_DOWNSCALE..1.0).contains(&scale) {
+        return None;
+    }
 
-    Ok((texture.create_default_view(), total_frame as u32))
+    let size = (
+        ((f64::from(width) * scale) as u32).max(1),
+        ((f64::from(height) * scale) as u32).max(1),
+    );
+    if frame_capacity(size, Texels::Rgba, limits) < layers {
+        return None;
+    }
+    Some(Target {
+        output: size,
+        fit: target.fit,
+    })
 }
 
-fn load_textures_from_path(
-    frames_path: &Path,
-    device: &wgpu::Device,
-    queue: &mut wgpu::Queue,
-) -> Result<(wgpu::TextureView, u32), Box<dyn Error>> {
-    let dir: Result<Vec<_>, Box<dyn Error>> = std::fs::read_dir(frames_path)?
-        .map(|p| Ok(p?.path()))
-        .collect();
-    let mut dir = dir?;
-    dir.sort_by(|a, b| {
-        natord::compare(
-            a.file_name().unwrap().to_str().unwrap(),
-            b.file_name().unwrap().to_str().unwrap(),
+/// Whether the frames of `decoded` are cut into tiles.
+fn is_tiled(decoded: &Decoded, limits: TextureLimits) -> bool {
+    decoded.frames.as_ref().map_or(false, |frames| {
+        limits.tile_grid((frames.width, frames.height)) != (1, 1)
+    })
+}
+
+/// Size of the tiles of frames of `size` cut into `(columns, rows)`, the
+/// last ones running past the frames, without their gutter.
+fn tile_size((width, height): (u32, u32), (columns, rows): (u32, u32)) -> (u32, u32) {
+    ((width + columns - 1) / columns, (height + rows - 1) / rows)
+}
+
+/// Size of the textures holding those tiles, their gutter included. Frames
+/// that are not cut have none.
+fn tile_texture_size(size: (u32, u32), tiles: (u32, u32)) -> (u32, u32) {
+    let (width, height) = tile_size(size, tiles);
+    if tiles == (1, 1) {
+        (width, height)
+    } else {
+        (width + 2 * TILE_GUTTER, height + 2 * TILE_GUTTER)
+    }
+}
+
+/// Frame layers the textures hold for frames of `size`, 0 when not even one
+/// fits. The chroma planes of videos are neither split nor tiled, and the
+/// palettes take a row of a single texture per layer.
+fn frame_capacity(size: (u32, u32), texels: Texels, limits: TextureLimits) -> u32 {
+    let (columns, rows) = limits.tile_grid(size);
+    match (texels, columns * rows) {
+        (Texels::Yuv(_), 1) => limits.array_layers,
+        (Texels::Yuv(_), _) => 0,
+        (Texels::Indexed, tiles) => {
+            (limits.arrays * limits.array_layers / tiles).min(limits.dimension)
+        }
+        (Texels::Rgba, tiles) => limits.arrays * limits.array_layers / tiles,
+    }
+}
+
+/// Texels of the area of `size` at `(x, y)` of an image, along with
+/// `gutter` texels around it, the texels on its edges being repeated past
+/// them.
+fn crop(
+    data: &[u8],
+    (width, height): (u32, u32),
+    texel_size: u32,
+    (x, y): (u32, u32),
+    (crop_width, crop_height): (u32, u32),
+    gutter: u32,
+) -> Vec<u8> {
+    let texel_size = texel_size as usize;
+    let (crop_width, crop_height) = (crop_width + 2 * gutter, crop_height + 2 * gutter);
+    let mut cropped = Vec::with_capacity(crop_width as usize * crop_height as usize * texel_size);
+    for row in y..y + crop_height {
+        let row = row.saturating_sub(gutter).min(height - 1) as usize;
+        for column in x..x + crop_width {
+            let column = column.saturating_sub(gutter).min(width - 1) as usize;
+            let at = (row * width as usize + column) * texel_size;
+            cropped.extend_from_slice(&data[at..at + texel_size]);
+        }
+    }
+    cropped
+}
+
+impl FrameTextures {
+    fn new(
+        device: &wgpu::Device,
+        size: (u32, u32),
+        layers: u32,
+        (texels, limits): (Texels, TextureLimits),
+    ) -> Self {
+        let tiles = limits.tile_grid(size);
+        let (tile_width, tile_height) = tile_texture_size(size, tiles);
+        let format = match texels {
+            Texels::Rgba => wgpu::TextureFormat::Rgba8UnormSrgb,
+            Texels::Indexed | Texels::Yuv(_) => wgpu::TextureFormat::R8Unorm,
+        };
+        let total_layers = (layers * tiles.0 * tiles.1).max(1);
+        let arrays = (0..total_layers)
+            .step_by(limits.array_layers as usize)
+            .map(|first| {
+                let array_layers = (total_layers - first).min(limits.array_layers);
+                create_texture(device, tile_width, tile_height, array_layers, format).1
+            })
+            .collect();
+
+        let palette = match texels {
+            Texels::Indexed => Some(
+                create_texture(
+                    device,
+                    PALETTE_SIZE,
+                    layers,
+                    1,
+                    wgpu::TextureFormat::Rgba8UnormSrgb,
+                )
+                .1,
+            ),
+            Texels::Rgba | Texels::Yuv(_) => None,
+        };
+        let chroma = match texels {
+            Texels::Yuv(format) => {
+                let single_channel = || {
+                    let (width, height) = format.chroma;
+                    create_texture(device, width, height, layers, wgpu::TextureFormat::R8Unorm).1
+                };
+                Some((single_channel(), single_channel()))
+            }
+            Texels::Rgba | Texels::Indexed => None,
+        };
+
+        Self {
+            arrays,
+            palette,
+            chroma,
+            texels,
+            size,
+            tiles,
+            layers,
+            limits,
+        }
+    }
+
+    /// The array holding tile `tile` of frame layer `layer`, and the layer of
+    /// the array.
+    fn locate(&self, layer: u32, tile: u32) -> (&wgpu::Texture, u32) {
+        let layer = layer * self.tiles.0 * self.tiles.1 + tile;
+        let array_layers = self.limits.array_layers;
+        (
+            &self.arrays[(layer / array_layers) as usize],
+            layer % array_layers,
         )
-    });
+    }
 
-    let img = image::open(&dir[0])?;
-    let (width, height) = img.dimensions();
+    /// Copies a frame of `texel_size` bytes per texel to `layer`, tile by
+    /// tile.
+    fn upload_tiles(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        layer: u32,
+        texel_size: u32,
+        frame: &[u8],
+    ) {
+        if self.tiles == (1, 1) {
+            let (texture, array_layer) = self.locate(layer, 0);
+            copy_to_layer(
+                device,
+                encoder,
+                texture,
+                self.size,
+                texel_size,
+                array_layer,
+                frame,
+            );
+            return;
+        }
 
-    let rgba_frames: Vec<_> = dir
-        .par_iter()
-        .map(|entry| image::open(entry).unwrap().to_rgba())
-        .collect();
+        let (tile_width, tile_height) = tile_size(self.size, self.tiles);
+        let texture_size = tile_texture_size(self.size, self.tiles);
+        for row in 0..self.tiles.1 {
+            for column in 0..self.tiles.0 {
+                let (texture, array_layer) = self.locate(layer, row * self.tiles.0 + column);
+                let origin = (column * tile_width, row * tile_height);
+                let tile = crop(
+                    frame,
+                    self.size,
+                    texel_size,
+                    origin,
+                    (tile_width, tile_height),
+                    TILE_GUTTER,
+                );
+                copy_to_layer(
+                    device,
+                    encoder,
+                    texture,
+                    texture_size,
+                    texel_size,
+                    array_layer,
+                    &tile,
+                );
+            }
+        }
+    }
+
+    /// Video memory the frame layers take, as budgeted.
+    fn memory(&self) -> u64 {
+        u64::from(self.layers) * layer_memory(self.size, self.texels)
+    }
+
+    /// Copies the RGBA pixels of a frame to `layer`, as palette indices when
+    /// they are used.
+    fn upload(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        layer: u32,
+        frame: &[u8],
+    ) {
+        match &self.palette {
+            Some(palette) => {
+                let (indices, colors) = indexed::index(frame, self.size.0);
+                self.upload_tiles(device, encoder, layer, 1, &indices);
+                copy_to_rows(
+                    device,
+                    encoder,
+                    palette,
+                    (PALETTE_SIZE, 1),
+                    4,
+                    (layer, 0),
+                    &colors,
+                );
+            }
+            None => self.upload_tiles(device, encoder, layer, 4, frame),
+        }
+    }
 
-    let rgba_frames: Vec<_> = rgba_frames.par_iter().map(|i| i).collect();
+    /// Copies the planes of a video frame to `layer`.
+    fn upload_planes(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        layer: u32,
+        planes: &y4m::Planes,
+    ) {
+        if let (Texels::Yuv(format), Some((u, v))) = (self.texels, &self.chroma) {
+            self.upload_tiles(device, encoder, layer, 1, &planes.y);
+            copy_to_layer(device, encoder, u, format.chroma, 1, layer, &planes.u);
+            copy_to_layer(device, encoder, v, format.chroma, 1, layer, &planes.v);
+        }
+    }
 
-    let (texture, total_frame) =
-        load_textures_in_gpu(&rgba_frames, dir.len(), width, height, device, queue);
+    /// Uploads a streamed frame to `layer`.
+    fn upload_frame(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        layer: u32,
+        frame: &Frame,
+    ) {
+        match frame {
+            Frame::Rgba(image) => self.upload(device, encoder, layer, image),
+            Frame::Yuv(planes) => self.upload_planes(device, encoder, layer, planes),
+        }
+    }
 
-    Ok((texture.create_default_view(), total_frame as u32))
+    /// Copies `layers`, in that order, to textures holding them only.
+    fn compact(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        layers: &[u32],
+    ) -> Self {
+        let compacted = Self::new(
+            device,
+            self.size,
+            layers.len() as u32,
+            (self.texels, self.limits),
+        );
+        let copy_view = |texture, array_layer, y: u32| wgpu::TextureCopyView {
+            texture,
+            mip_level: 0,
+            array_layer,
+            origin: wgpu::Origin3d {
+                x: 0.0,
+                y: y as f32,
+                z: 0.0,
+            },
+        };
+        let (tile_width, tile_height) = tile_texture_size(self.size, self.tiles);
+
+        for (layer, old_layer) in layers.iter().enumerate() {
+            for tile in 0..self.tiles.0 * self.tiles.1 {
+                let (from, from_layer) = self.locate(*old_layer, tile);
+                let (to, to_layer) = compacted.locate(layer as u32, tile);
+                encoder.copy_texture_to_texture(
+                    copy_view(from, from_layer, 0),
+                    copy_view(to, to_layer, 0),
+                    wgpu::Extent3d {
+                        width: tile_width,
+                        height: tile_height,
+                        depth: 1,
+                    },
+                );
+            }
+            if let (Some(palette), Some(compacted_palette)) = (&self.palette, &compacted.palette) {
+                encoder.copy_texture_to_texture(
+                    copy_view(palette, 0, *old_layer),
+                    copy_view(compacted_palette, 0, layer as u32),
+                    wgpu::Extent3d {
+                        width: PALETTE_SIZE,
+                        height: 1,
+                        depth: 1,
+                    },
+                );
+            }
+        }
+
+        compacted
+    }
+
+    /// The layout always has `FRAME_ARRAYS` arrays, the first one is bound
+    /// again in place of the ones the frames do not need.
+    fn array_views(&self) -> Vec<wgpu::TextureView> {
+        (0..FRAME_ARRAYS as usize)
+            .map(|index| {
+                self.arrays
+                    .get(index)
+                    .unwrap_or(&self.arrays[0])
+                    .create_default_view()
+            })
+            .collect()
+    }
+
+    /// The layout always has a palette texture, an unused one is bound for
+    /// RGBA frames.
+    fn palette_view(&self, device: &wgpu::Device) -> wgpu::TextureView {
+        match &self.palette {
+            Some(palette) => palette.create_default_view(),
+            None => create_texture(device, 1, 1, 1, wgpu::TextureFormat::Rgba8UnormSrgb)
+                .1
+                .create_default_view(),
+        }
+    }
+
+    /// The layout always has chroma textures too.
+    fn chroma_views(&self, device: &wgpu::Device) -> (wgpu::TextureView, wgpu::TextureView) {
+        if let Some((u, v)) = &self.chroma {
+            (u.create_default_view(), v.create_default_view())
+        } else {
+            let unused = create_texture(device, 1, 1, 1, wgpu::TextureFormat::R8Unorm).1;
+            (unused.create_default_view(), unused.create_default_view())
+        }
+    }
+}
+
+/// Uploads the decoded frames to the first layers of a texture of
+/// `total_frame` layers, the other ones being loaded later. They are copied
+/// in batches of `staging_memory` bytes, each one waiting for the previous
+/// one to be done so that its staging buffers are freed.
+fn load_textures_in_gpu(
+    decoded: &DecodedFrames,
+    total_frame: u32,
+    motion_compensation: bool,
+    storage: Storage,
+    staging_memory: usize,
+    device: &wgpu::Device,
+    queue: &mut wgpu::Queue,
+) -> Frames {
+    info!("Loading frames");
+
+    let frames: Vec<_> = decoded.frames.iter().collect();
+    let pixels = decoded.pixels();
+    let (width, height) = (decoded.width, decoded.height);
+
+    let (frame_layers, uploaded): (_, Vec<&[u8]>) = match &decoded.cached {
+        // Identical frames already share a layer in the cache.
+        Some(entry) if storage.deduplicate => (
+            Some(entry.frame_layers.clone()),
+            (0..entry.layers).map(|layer| entry.layer(layer)).collect(),
+        ),
+        // Frames loaded later are deduplicated as they come.
+        _ if storage.deduplicate && total_frame <= frames.len() as u32 => {
+            let (frame_layers, unique) = dedup::deduplicate(&frames);
+            if unique.len() < frames.len() {
+                info!(
+                    "{} frames are identical to previous ones",
+                    frames.len() - unique.len()
+                );
+            }
+            (
+                Some(frame_layers),
+                unique.iter().map(|index| pixels[*index]).collect(),
+            )
+        }
+        _ => (None, pixels.clone()),
+    };
+    let layers = if frame_layers.is_some() {
+        uploaded.len() as u32
+    } else {
+        total_frame.max(decoded.total_frame() as u32)
+    };
+
+    let textures = FrameTextures::new(
+        device,
+        (width, height),
+        layers,
+        (storage.texels, storage.limits),
+    );
+
+    let batch_size = source::frames_within(staging_memory, (width, height));
+    for (batch, batch_frames) in uploaded.chunks(batch_size).enumerate() {
+        let commands: Vec<_> = batch_frames
+            .par_iter()
+            .enumerate()
+            .map(|(index, frame)| {
+                let mut init_encoder =
+                    device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+                textures.upload(
+                    device,
+                    &mut init_encoder,
+                    (batch * batch_size + index) as u32,
+                    frame,
+                );
+                init_encoder.finish()
+            })
+            .collect();
+        queue.submit(&commands);
+        device.poll(true);
+    }
+    if let Some((_, planes)) = &decoded.video {
+        let mut init_encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+        textures.upload_planes(device, &mut init_encoder, 0, planes);
+        queue.submit(&[init_encoder.finish()]);
+    }
+    info!("Finished loading frames");
+
+    let flow = if motion_compensation {
+        info!("Estimating motion between frames");
+        let fields = match &decoded.cached {
+            // The motion is estimated on images, mapped frames are copied.
+            Some(_) => {
+                let images: Vec<RgbaImage> = pixels
+                    .iter()
+                    .filter_map(|frame| RgbaImage::from_raw(width, height, frame.to_vec()))
+                    .collect();
+                flow::estimate_loop(&images.iter().collect::<Vec<_>>())
+            }
+            None => flow::estimate_loop(&frames),
+        };
+        info!("Finished estimating motion");
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+        let flow = load_flow_in_gpu(&fields, width, height, device, &mut encoder);
+        queue.submit(&[encoder.finish()]);
+        Some(flow.create_default_view())
+    } else {
+        None
+    };
+
+    Frames {
+        textures,
+        frame_layers,
+        total_frame: layers,
+        repeat: decoded.repeat,
+        flow,
+    }
+}
+
+/// Fragment shaders that do not sample any frame still need something bound.
+fn load_empty_texture(device: &wgpu::Device) -> Frames {
+    let textures = FrameTextures::new(device, (1, 1), 1, (Texels::Rgba, TextureLimits::default()));
+    Frames {
+        textures,
+        frame_layers: None,
+        total_frame: 1,
+        repeat: Some(0),
+        flow: None,
+    }
+}
+
+/// Layer `i` holds the motion from frame `i` to frame `i + 1` (wrapping).
+fn load_flow_in_gpu(
+    fields: &[Vec<u8>],
+    width: u32,
+    height: u32,
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+) -> wgpu::Texture {
+    let field_size = flow::field_size(width, height);
+    let (_, texture) = create_texture(
+        device,
+        field_size.0,
+        field_size.1,
+        fields.len() as u32,
+        wgpu::TextureFormat::Rg8Unorm,
+    );
+
+    for (index, field) in fields.iter().enumerate() {
+        copy_to_layer(
+            device,
+            encoder,
+            &texture,
+            field_size,
+            2,
+            index as u32,
+            field,
+        );
+    }
+
+    texture
+}
+
+/// Bound in place of the motion between frames when it is not estimated.
+fn unused_flow_view(device: &wgpu::Device) -> wgpu::TextureView {
+    create_texture(device, 1, 1, 1, wgpu::TextureFormat::Rg8Unorm)
+        .1
+        .create_default_view()
+}
+
+/// Size of the `Locals` block of the fragment shaders.
+const LOCALS_SIZE: wgpu::BufferAddress = 20;
+
+/// Matches the `Locals` block of the fragment shaders. Custom fragments only
+/// declaring `total` and `position` keep working as the new fields come last.
+fn uniform_bytes(
+    total_frame: u32,
+    position: f32,
+    (previous, next, mix_value): (u32, u32, f32),
+) -> Vec<u8> {
+    [
+        total_frame.to_ne_bytes(),
+        position.to_ne_bytes(),
+        previous.to_ne_bytes(),
+        next.to_ne_bytes(),
+        mix_value.to_ne_bytes(),
+    ]
+    .concat()
+}
+
+fn create_scene_bind_group(
+    device: &wgpu::Device,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    samplers: (&wgpu::Sampler, &wgpu::Sampler),
+    textures: &FrameTextures,
+    uniform_buf: &wgpu::Buffer,
+    flow_view: &wgpu::TextureView,
+) -> wgpu::BindGroup {
+    let arrays = textures.array_views();
+    let chroma = textures.chroma_views(device);
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: bind_group_layout,
+        bindings: &[
+            wgpu::Binding {
+                binding: 0,
+                resource: wgpu::BindingResource::Sampler(samplers.0),
+            },
+            wgpu::Binding {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&arrays[0]),
+            },
+            wgpu::Binding {
+                binding: 2,
+                resource: wgpu::BindingResource::Buffer {
+                    buffer: uniform_buf,
+                    range: 0..LOCALS_SIZE,
+                },
+            },
+            wgpu::Binding {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(flow_view),
+            },
+            wgpu::Binding {
+                binding: 4,
+                resource: wgpu::BindingResource::Sampler(samplers.1),
+            },
+            wgpu::Binding {
+                binding: 5,
+                resource: wgpu::BindingResource::TextureView(&textures.palette_view(device)),
+            },
+            wgpu::Binding {
+                binding: 6,
+                resource: wgpu::BindingResource::TextureView(&chroma.0),
+            },
+            wgpu::Binding {
+                binding: 7,
+                resource: wgpu::BindingResource::TextureView(&chroma.1),
+            },
+            wgpu::Binding {
+                binding: 8,
+                resource: wgpu::BindingResource::TextureView(&arrays[1]),
+            },
+            wgpu::Binding {
+                binding: 9,
+                resource: wgpu::BindingResource::TextureView(&arrays[2]),
+            },
+            wgpu::Binding {
+                binding: 10,
+                resource: wgpu::BindingResource::TextureView(&arrays[3]),
+            },
+        ],
+    })
 }
 
 fn create_pipeline(
     device: &wgpu::Device,
     bind_group_layout: &wgpu::BindGroupLayout,
-    custom_fragment: &Option<PathBuf>,
+    custom_fragment: Option<&str>,
+    interpolation: &Interpolation,
+    (motion_compensation, textures): (bool, &FrameTextures),
+    blend: bool,
+) -> Result<wgpu::RenderPipeline, SourceError> {
+    let (tile_width, tile_height) = tile_size(textures.size, textures.tiles);
+    let tile_macros = [
+        format!("TILES=uvec2({}, {})", textures.tiles.0, textures.tiles.1),
+        format!(
+            "TILE_SCALE=vec2({:?}, {:?})",
+            textures.size.0 as f32 / tile_width as f32,
+            textures.size.1 as f32 / tile_height as f32
+        ),
+        format!("TILE_TEXELS=vec2({}.0, {}.0)", tile_width, tile_height),
+        format!("TILE_GUTTER={}.0", TILE_GUTTER),
+    ];
+    let mut macros = Vec::new();
+    if motion_compensation {
+        macros.push("MOTION_COMPENSATION");
+    }
+    if textures.tiles != (1, 1) {
+        macros.extend(tile_macros.iter().map(String::as_str));
+    }
+    match textures.texels {
+        Texels::Rgba => {}
+        Texels::Indexed => macros.push("PALETTE"),
+        Texels::Yuv(format) => {
+            macros.push("YUV");
+            if format.full_range {
+                macros.push("YUV_FULL_RANGE");
+            }
+            if format.bt709 {
+                macros.push("YUV_BT709");
+            }
+        }
+    }
+    let (frag, vert) = get_shaders(&device, custom_fragment, interpolation, &macros)?;
+
+    Ok(create_render_pipeline(
+        device,
+        bind_group_layout,
+        (&frag, &vert),
+        blend,
+    ))
+}
+
+fn create_transition_pipeline(
+    device: &wgpu::Device,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    transition: &Transition,
+) -> Result<wgpu::RenderPipeline, SourceError> {
+    let frag_code = transition.splice(include_str!("shaders/transition.glsl"))?;
+    let frag = create_shader_module(device, frag_code, shaderc::ShaderKind::Fragment, &[])?;
+    let vert = create_shader_module(
+        device,
+        String::from(include_str!("shaders/vert.glsl")),
+        shaderc::ShaderKind::Vertex,
+        &[],
+    )?;
+
+    Ok(create_render_pipeline(
+        device,
+        bind_group_layout,
+        (&frag, &vert),
+        false,
+    ))
+}
+
+/// Custom post-processing files define `vec4 postProcess(vec2 uv)`, they can
+/// call `getColor(uv)` and read `resolution` and `time`.
+fn create_post_pipeline(
+    device: &wgpu::Device,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    path: &Path,
+) -> Result<wgpu::RenderPipeline, SourceError> {
+    let frag_code = include_str!("shaders/post.glsl").replacen(
+        POST_PROCESSING_MARKER,
+        &fs::read_to_string(path)?,
+        1,
+    );
+    let frag = create_shader_module(device, frag_code, shaderc::ShaderKind::Fragment, &[])?;
+    let vert = create_shader_module(
+        device,
+        String::from(include_str!("shaders/vert.glsl")),
+        shaderc::ShaderKind::Vertex,
+        &[],
+    )?;
+
+    Ok(create_render_pipeline(
+        device,
+        bind_group_layout,
+        (&frag, &vert),
+        false,
+    ))
+}
+
+/// Areas of a target the scene is drawn to, as x, y, width and height.
+fn viewports(
+    fit: FitMode,
+    source: Option<(u32, u32)>,
+    target: (u32, u32),
+) -> Vec<(f32, f32, f32, f32)> {
+    let (target_width, target_height) = (target.0 as f32, target.1 as f32);
+    let (width, height) = match source {
+        None => (target_width, target_height),
+        Some((width, height)) => {
+            let (width, height) = (width as f32, height as f32);
+            match fit {
+                FitMode::Stretch => (target_width, target_height),
+                FitMode::Fill => {
+                    let scale = (target_width / width).max(target_height / height);
+                    (width * scale, height * scale)
+                }
+                FitMode::Fit => {
+                    let scale = (target_width / width).min(target_height / height);
+                    (width * scale, height * scale)
+                }
+                FitMode::Center | FitMode::Tile => (width, height),
+            }
+        }
+    };
+
+    if fit == FitMode::Tile && source.is_some() {
+        let columns = (target_width / width).ceil() as u32;
+        let rows = (target_height / height).ceil() as u32;
+        return (0..rows)
+            .flat_map(|row| {
+                (0..columns)
+                    .map(move |column| (column as f32 * width, row as f32 * height, width, height))
+            })
+            .collect();
+    }
+
+    vec![(
+        (target_width - width) / 2.0,
+        (target_height - height) / 2.0,
+        width,
+        height,
+    )]
+}
+
+/// With `blend`, fragments are composited over what the target holds by
+/// their alpha, so that transparent frames show the backdrop.
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    (frag, vert): (&wgpu::ShaderModule, &wgpu::ShaderModule),
+    blend: bool,
 ) -> wgpu::RenderPipeline {
-    let (frag, vert) = get_shaders(&device, custom_fragment).unwrap();
+    let (color_blend, alpha_blend) = if blend {
+        (
+            wgpu::BlendDescriptor {
+                src_factor: wgpu::BlendFactor::SrcAlpha,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
+            wgpu::BlendDescriptor {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
+        )
+    } else {
+        (
+            wgpu::BlendDescriptor::REPLACE,
+            wgpu::BlendDescriptor::REPLACE,
+        )
+    };
 
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         bind_group_layouts: &[&bind_group_layout],
@@ -283,11 +1610,11 @@ fn create_pipeline(
     device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         layout: &pipeline_layout,
         vertex_stage: wgpu::ProgrammableStageDescriptor {
-            module: &vert,
+            module: vert,
             entry_point: "main",
         },
         fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
-            module: &frag,
+            module: frag,
             entry_point: "main",
         }),
         rasterization_state: Some(wgpu::RasterizationStateDescriptor {
@@ -300,8 +1627,8 @@ fn create_pipeline(
         primitive_topology: wgpu::PrimitiveTopology::TriangleList,
         color_states: &[wgpu::ColorStateDescriptor {
             format: wgpu::TextureFormat::Bgra8UnormSrgb,
-            color_blend: wgpu::BlendDescriptor::REPLACE,
-            alpha_blend: wgpu::BlendDescriptor::REPLACE,
+            color_blend,
+            alpha_blend,
             write_mask: wgpu::ColorWrite::ALL,
         }],
         depth_stencil_state: None,
@@ -313,6 +1640,1173 @@ fn create_pipeline(
     })
 }
 
+impl SceneLoading {
+    /// `uploaded` are the first frames, already uploaded. Frames are kept
+    /// to be compared within `staging_memory` bytes when deduplicating.
+    fn new(
+        loading: Loading,
+        textures: FrameTextures,
+        uploaded: &[RgbaImage],
+        readahead: u32,
+        (flow_pending, deduplicate, staging_memory): (bool, bool, usize),
+    ) -> Self {
+        let received = if deduplicate {
+            let mut received = dedup::Received::new(staging_memory);
+            for (frame, layer) in uploaded.iter().zip(0..) {
+                received.add(layer, frame);
+            }
+            Some(received)
+        } else {
+            None
+        };
+
+        Self {
+            resident: (0..loading.total_frame as usize)
+                .map(|frame| frame < uploaded.len())
+                .collect(),
+            receiver: loading.receiver,
+            textures,
+            prefix: uploaded.len() as u32,
+            readahead,
+            flow_pending,
+            received,
+            first: uploaded[0].clone(),
+            failed: false,
+        }
+    }
+
+    fn is_resident(&self, frame: u32) -> bool {
+        self.resident.get(frame as usize).copied().unwrap_or(false)
+    }
+
+    fn mark_resident(&mut self, frame: u32) {
+        self.resident[frame as usize] = true;
+        while self.resident.get(self.prefix as usize) == Some(&true) {
+            self.prefix += 1;
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.prefix as usize == self.resident.len() && !self.flow_pending
+    }
+
+    /// Shows the first frame in place of one that cannot be loaded, so that
+    /// the playback does not wait for it. Deduplicated frames point at its
+    /// layer, other ones get it uploaded again.
+    fn substitute(
+        &mut self,
+        frame: u32,
+        frame_layers: Option<&mut Vec<u32>>,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        match frame_layers {
+            Some(frame_layers) => frame_layers[frame as usize] = frame_layers[0],
+            None => self.textures.upload(device, encoder, frame, &self.first),
+        }
+        self.mark_resident(frame);
+    }
+
+    /// Substitutes frames the decoding did not get to, as many at a time as
+    /// there are uploads of received frames.
+    fn substitute_missing(
+        &mut self,
+        mut frame_layers: Option<&mut Vec<u32>>,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let missing: Vec<u32> = (self.prefix..self.resident.len() as u32)
+            .filter(|frame| !self.is_resident(*frame))
+            .take(UPLOADS_PER_FRAME)
+            .collect();
+        for frame in missing {
+            self.substitute(frame, frame_layers.as_deref_mut(), device, encoder);
+        }
+    }
+
+    /// Whether advancing the playback only reaches resident frames. Frames
+    /// played out of order wait for all of them.
+    fn can_play(&self, playback: &Playback, increment: f32) -> bool {
+        let total_frame = self.resident.len() as u32;
+        if self.prefix == total_frame {
+            return true;
+        }
+        if !playback.in_order() || self.prefix < self.readahead.min(total_frame) {
+            return false;
+        }
+
+        let reached = playback.next() + (playback.mix_value() + increment) as u32;
+        reached % total_frame < self.prefix
+    }
+}
+
+impl SceneStream {
+    /// The first `uploaded` frames are on the first layers of `textures`.
+    fn new(stream: Stream, textures: FrameTextures, uploaded: u32) -> Self {
+        let ring_size = textures.layers;
+        Self {
+            requests: stream.requests,
+            receiver: stream.receiver,
+            textures,
+            layers: (0..ring_size)
+                .map(|layer| if layer < uploaded { Some(layer) } else { None })
+                .collect(),
+            requested: Vec::new(),
+            substitutes: HashMap::new(),
+        }
+    }
+
+    /// The frame shown for `frame`, itself unless it cannot be read.
+    fn resolve(&self, frame: u32) -> u32 {
+        self.substitutes.get(&frame).copied().unwrap_or(frame)
+    }
+
+    fn layer_of(&self, frame: u32) -> Option<u32> {
+        let frame = self.resolve(frame);
+        self.layers
+            .iter()
+            .position(|layer| *layer == Some(frame))
+            .map(|layer| layer as u32)
+    }
+
+    /// Shows the previous frame in place of `frame` from now on, the next
+    /// one for the first frame.
+    fn substitute(&mut self, frame: u32) {
+        let neighbour = self.resolve(if frame > 0 { frame - 1 } else { 1 });
+        if neighbour != frame {
+            self.substitutes.insert(frame, neighbour);
+        }
+    }
+
+    /// Frames the playback shows next, as many as the ring holds. The
+    /// playback is simulated on a copy, random frames included.
+    fn upcoming(&self, playback: &Playback) -> Vec<u32> {
+        let mut playback = playback.clone();
+        let mut frames = vec![self.resolve(playback.previous())];
+
+        for _ in 0..2 * self.layers.len() {
+            if frames.len() >= self.layers.len() {
+                break;
+            }
+            let next = self.resolve(playback.next());
+            if !frames.contains(&next) {
+                frames.push(next);
+            }
+
+            let pair = (playback.previous(), playback.next());
+            playback.advance(1.0);
+            if (playback.previous(), playback.next()) == pair {
+                break;
+            }
+        }
+
+        frames
+    }
+
+    fn can_play(&self, playback: &Playback, increment: f32) -> bool {
+        let mut ahead = playback.clone();
+        ahead.advance(increment);
+        self.layer_of(ahead.previous()).is_some() && self.layer_of(ahead.next()).is_some()
+    }
+
+    /// Uploads the frames received over the layers the playback does not
+    /// need anymore, and requests the upcoming ones.
+    fn receive(
+        &mut self,
+        playback: &Playback,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let upcoming = self.upcoming(playback);
+
+        for _ in 0..UPLOADS_PER_FRAME {
+            let (frame, received) = match self.receiver.try_recv() {
+                Ok(received) => received,
+                Err(_) => break,
+            };
+            self.requested.retain(|requested| *requested != frame);
+
+            let received = match received {
+                Ok(Frame::Rgba(image)) if image.dimensions() != self.textures.size => {
+                    warn!(
+                        "Replacing frame {} with a neighbouring one, it does not match the \
+                         first one",
+                        frame
+                    );
+                    self.substitute(frame);
+                    continue;
+                }
+                Ok(received) => received,
+                Err(e) => {
+                    warn!("Replacing frame {} with a neighbouring one: {}", frame, e);
+                    self.substitute(frame);
+                    continue;
+                }
+            };
+            if !upcoming.contains(&frame) || self.layer_of(frame).is_some() {
+                continue;
+            }
+
+            let free = self
+                .layers
+                .iter()
+                .position(|layer| layer.map_or(true, |held| !upcoming.contains(&held)));
+            if let Some(layer) = free {
+                self.textures
+                    .upload_frame(device, encoder, layer as u32, &received);
+                self.layers[layer] = Some(frame);
+            }
+        }
+
+        // Frames that failed above are replaced by neighbouring ones now.
+        for frame in self.upcoming(playback) {
+            if self.layer_of(frame).is_none() && !self.requested.contains(&frame) {
+                self.requested.push(frame);
+                let _ = self.requests.send(frame);
+            }
+        }
+    }
+}
+
+impl SceneLive {
+    fn new(feed: Arc<live::Feed>, limits: TextureLimits) -> Self {
+        Self {
+            feed,
+            limits,
+            textures: None,
+            received: 0,
+            layer: 1,
+            received_at: Instant::now(),
+            fps: 1.0,
+        }
+    }
+
+    /// Uploads the latest frame when a new one arrived. Returns whether the
+    /// textures were created for it.
+    fn receive(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) -> bool {
+        let (received, frame) = match self.feed.frame_after(self.received) {
+            Some(latest) => latest,
+            None => return false,
+        };
+
+        if self.textures.is_none()
+            && frame_capacity(frame.dimensions(), Texels::Rgba, self.limits) < 2
+        {
+            if self.received == 0 {
+                warn!(
+                    "Live frames of {}x{} are too large for the textures",
+                    frame.width(),
+                    frame.height()
+                );
+            }
+            self.received = received;
+            return false;
+        }
+
+        let created = self.textures.is_none();
+        if created {
+            self.fps = self.feed.header().map_or(1.0, |header| header.fps);
+        }
+        let limits = self.limits;
+        let textures = self.textures.get_or_insert_with(|| {
+            FrameTextures::new(device, frame.dimensions(), 2, (Texels::Rgba, limits))
+        });
+        self.layer = 1 - self.layer;
+        textures.upload(device, encoder, self.layer, &frame);
+        if created {
+            // Nothing to blend from yet.
+            textures.upload(device, encoder, 1 - self.layer, &frame);
+        }
+        self.received = received;
+        self.received_at = Instant::now();
+
+        created
+    }
+
+    /// Blends from the previous frame to the latest one over a frame interval.
+    fn blended_frames(&self) -> (u32, u32, f32) {
+        if self.textures.is_none() {
+            return (0, 0, 0.0);
+        }
+        let mix_value = (self.received_at.elapsed().as_secs_f32() * self.fps).min(1.0);
+        (1 - self.layer, self.layer, mix_value)
+    }
+}
+
+/// Uploads the frames of a scene decoded so far, all of them without
+/// `pending`.
+fn upload_scene_frames(
+    device: &wgpu::Device,
+    queue: &mut wgpu::Queue,
+    decoded: &Decoded,
+    pending: Option<&Pending>,
+    estimate_now: bool,
+    storage: Storage,
+    options: &SceneOptions,
+) -> Frames {
+    let frames = match &decoded.frames {
+        Some(frames) => frames,
+        None => return load_empty_texture(device),
+    };
+    let total_frame = match pending {
+        Some(Pending::Loading(loading, _)) => loading.total_frame,
+        Some(Pending::Streaming(_, ring_size)) => *ring_size,
+        Some(Pending::Live(_)) | None => 0,
+    };
+    let staging_memory = options.staging_memory;
+    load_textures_in_gpu(
+        frames,
+        total_frame,
+        estimate_now,
+        storage,
+        staging_memory,
+        device,
+        queue,
+    )
+}
+
+/// Where the frames `pending` are received from, into `textures`.
+fn receive_pending(
+    pending: Option<Pending>,
+    decoded: &Decoded,
+    textures: FrameTextures,
+    (motion_compensation, deduplicate): (bool, bool),
+    options: &SceneOptions,
+) -> (Option<SceneLoading>, Option<SceneStream>, Option<SceneLive>) {
+    match (pending, &decoded.frames) {
+        (Some(Pending::Loading(loading, readahead)), Some(decoded_frames)) => {
+            let background = (motion_compensation, deduplicate, options.staging_memory);
+            let uploaded = &decoded_frames.frames;
+            let loading = SceneLoading::new(loading, textures, uploaded, readahead, background);
+            (Some(loading), None, None)
+        }
+        (Some(Pending::Streaming(stream, _)), Some(decoded_frames)) => {
+            let uploaded = decoded_frames.total_frame() as u32;
+            (
+                None,
+                Some(SceneStream::new(stream, textures, uploaded)),
+                None,
+            )
+        }
+        (Some(Pending::Live(feed)), _) => (None, None, Some(SceneLive::new(feed, options.limits))),
+        _ => (None, None, None),
+    }
+}
+
+impl Scene {
+    /// With `pending`, only the decoded frames are uploaded and the others
+    /// are received later. Tiled frames go without motion compensation, the
+    /// motion being estimated on whole frames.
+    fn new(
+        device: &wgpu::Device,
+        queue: &mut wgpu::Queue,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        samplers: (&wgpu::Sampler, &wgpu::Sampler),
+        options: &SceneOptions,
+        decoded: &Decoded,
+        pending: Option<Pending>,
+    ) -> Result<Self, SourceError> {
+        let streaming = matches!(pending, Some(Pending::Streaming(..)));
+        let motion_compensation = options.motion_compensation
+            && decoded.fragment.is_none()
+            && !streaming
+            && !is_tiled(decoded, options.limits);
+        let storage = Storage {
+            texels: scene_texels(decoded, options.palette),
+            // Both address the flow and the streamed frames by frame.
+            deduplicate: decoded.fragment.is_none() && !motion_compensation && !streaming,
+            limits: options.limits.sampled_by(decoded.fragment.is_some()),
+        };
+        let estimate_now = motion_compensation && pending.is_none();
+        let frames = upload_scene_frames(
+            device,
+            queue,
+            decoded,
+            pending.as_ref(),
+            estimate_now,
+            storage,
+            options,
+        );
+        let total_frame = frames.total_frame;
+        let played_frame = match (&pending, &frames.frame_layers) {
+            (Some(Pending::Streaming(stream, _)), _) => stream.total_frame,
+            (_, Some(frame_layers)) => frame_layers.len() as u32,
+            _ => total_frame,
+        };
+        let has_flow = frames.flow.is_some();
+        // The layout always has a flow texture, bind an unused one when the
+        // motion is not estimated.
+        let flow_view = frames.flow.unwrap_or_else(|| unused_flow_view(device));
+        let blend = decoded.fragment.is_none() || options.backdrop;
+        let interpolation = options
+            .interpolation
+            .clone()
+            .or_else(|| decoded.interpolation.clone());
+        let interpolation = interpolation.unwrap_or(Interpolation::Linear);
+        let render_pipeline = create_pipeline(
+            device,
+            bind_group_layout,
+            decoded.fragment.as_deref(),
+            &interpolation,
+            (has_flow, &frames.textures),
+            blend,
+        )?;
+
+        let playback = Playback::new(options.playback, played_frame, frames.repeat);
+        let uniform = uniform_bytes(
+            total_frame,
+            0.0,
+            (playback.previous(), playback.next(), 0.0),
+        );
+
+        let uniform_buf = device
+            .create_buffer_mapped(
+                uniform.len(),
+                wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            )
+            .fill_from_slice(&uniform);
+
+        let bind_group = create_scene_bind_group(
+            device,
+            bind_group_layout,
+            samplers,
+            &frames.textures,
+            &uniform_buf,
+            &flow_view,
+        );
+        let memory = options.memory_usage.allocate(frames.textures.memory());
+
+        let size = decoded
+            .frames
+            .as_ref()
+            .map(|frames| (frames.width, frames.height));
+        let mut frame_layers = frames.frame_layers;
+        if storage.deduplicate && matches!(pending, Some(Pending::Loading(..))) {
+            // Every frame on its own layer until they are all there.
+            frame_layers = Some((0..total_frame).collect());
+        }
+        let flags = (motion_compensation, storage.deduplicate);
+        let (loading, stream, live) =
+            receive_pending(pending, decoded, frames.textures, flags, options);
+
+        Ok(Self {
+            position: 0.0,
+            total_frame,
+            size,
+            playback,
+            use_timestamp: decoded.fragment.is_some(),
+            started_at: SystemTime::now(),
+            bind_group,
+            render_pipeline,
+            uniform,
+            uniform_buf,
+            loading,
+            stream,
+            live,
+            frame_layers,
+            interpolation,
+            fragment: decoded.fragment.clone(),
+            blend,
+            memory,
+        })
+    }
+
+    /// Uploads the frames decoded since the last call, a few at most, and
+    /// switches to motion compensation once the motion is received.
+    fn receive_frames(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        samplers: (&wgpu::Sampler, &wgpu::Sampler),
+    ) {
+        if let Some(stream) = &mut self.stream {
+            stream.receive(&self.playback, device, encoder);
+        }
+        self.receive_live_frame(device, encoder, bind_group_layout, samplers);
+
+        let loading = match &mut self.loading {
+            Some(loading) => loading,
+            None => return,
+        };
+
+        for _ in 0..UPLOADS_PER_FRAME {
+            if loading.failed {
+                break;
+            }
+            match loading.receiver.try_recv() {
+                Ok(Ok(Loaded::Frame(index, _))) if index as usize >= loading.resident.len() => {
+                    warn!(
+                        "Skipping frame {}, there are only {}",
+                        index,
+                        loading.resident.len()
+                    );
+                }
+                Ok(Ok(Loaded::Frame(index, frame)))
+                    if frame.dimensions() != loading.textures.size =>
+                {
+                    warn!(
+                        "Replacing frame {} with the first one, it does not match it",
+                        index
+                    );
+                    loading.substitute(index, self.frame_layers.as_mut(), device, encoder);
+                }
+                Ok(Ok(Loaded::Frame(index, frame))) => {
+                    let known = loading
+                        .received
+                        .as_mut()
+                        .map(|received| received.add(index, &frame))
+                        .filter(|layer| *layer != index);
+                    match (known, &mut self.frame_layers) {
+                        (Some(layer), Some(frame_layers)) => frame_layers[index as usize] = layer,
+                        _ => loading.textures.upload(device, encoder, index, &frame),
+                    }
+                    loading.mark_resident(index);
+                }
+                // Tiled frames go without the motion.
+                Ok(Ok(Loaded::Flow(_))) if !loading.flow_pending => {}
+                Ok(Ok(Loaded::Flow(fields))) => {
+                    let (width, height) = loading.textures.size;
+                    let flow_view = load_flow_in_gpu(&fields, width, height, device, encoder)
+                        .create_default_view();
+                    self.bind_group = create_scene_bind_group(
+                        device,
+                        bind_group_layout,
+                        samplers,
+                        &loading.textures,
+                        &self.uniform_buf,
+                        &flow_view,
+                    );
+                    // The layout is the same with or without the flow, frames
+                    // are only cross-faded when following it does not compile.
+                    let flow = (true, &loading.textures);
+                    match create_pipeline(
+                        device,
+                        bind_group_layout,
+                        None,
+                        &self.interpolation,
+                        flow,
+                        true,
+                    ) {
+                        Ok(render_pipeline) => self.render_pipeline = render_pipeline,
+                        Err(e) => warn!("Cannot follow the motion between frames: {}", e),
+                    }
+                    loading.flow_pending = false;
+                }
+                Ok(Err(e)) => {
+                    warn!(
+                        "Cannot load the remaining frames, replacing them with the first one: {}",
+                        e
+                    );
+                    loading.flow_pending = false;
+                    loading.failed = true;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    loading.flow_pending = false;
+                    loading.failed = true;
+                }
+            }
+        }
+        if loading.failed {
+            loading.substitute_missing(self.frame_layers.as_mut(), device, encoder);
+        }
+
+        if loading.is_complete() {
+            info!("Finished loading frames in the background");
+            self.finish_loading(device, encoder, bind_group_layout, samplers);
+        }
+    }
+
+    /// Duplicates were not uploaded, the layers they would have used are
+    /// released once every frame is there.
+    fn finish_loading(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        samplers: (&wgpu::Sampler, &wgpu::Sampler),
+    ) {
+        let (loading, frame_layers) = match (self.loading.take(), &mut self.frame_layers) {
+            (Some(loading), Some(frame_layers)) => (loading, frame_layers),
+            _ => return,
+        };
+        let mut layers: Vec<u32> = frame_layers.clone();
+        layers.sort_unstable();
+        layers.dedup();
+        if layers.len() == frame_layers.len() {
+            return;
+        }
+
+        info!(
+            "{} frames are identical to previous ones",
+            frame_layers.len() - layers.len()
+        );
+        let textures = loading.textures.compact(device, encoder, &layers);
+        self.memory
+            .shrink(loading.textures.memory() - textures.memory());
+        for layer in frame_layers.iter_mut() {
+            *layer = layers.binary_search(layer).unwrap() as u32;
+        }
+        let flow_view = unused_flow_view(device);
+        self.bind_group = create_scene_bind_group(
+            device,
+            bind_group_layout,
+            samplers,
+            &textures,
+            &self.uniform_buf,
+            &flow_view,
+        );
+        self.total_frame = layers.len() as u32;
+    }
+
+    /// Uploads the latest live frame, binding the textures created for the
+    /// first one.
+    fn receive_live_frame(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        samplers: (&wgpu::Sampler, &wgpu::Sampler),
+    ) {
+        let live = match &mut self.live {
+            Some(live) => live,
+            None => return,
+        };
+        if !live.receive(device, encoder) {
+            return;
+        }
+
+        let textures = live.textures.as_ref().unwrap();
+        self.memory.grow(textures.memory());
+        if textures.tiles != (1, 1) {
+            match create_pipeline(
+                device,
+                bind_group_layout,
+                self.fragment.as_deref(),
+                &self.interpolation,
+                (false, textures),
+                self.blend,
+            ) {
+                Ok(render_pipeline) => self.render_pipeline = render_pipeline,
+                Err(e) => warn!("Cannot draw tiled live frames: {}", e),
+            }
+        }
+        self.bind_group = create_scene_bind_group(
+            device,
+            bind_group_layout,
+            samplers,
+            textures,
+            &self.uniform_buf,
+            &unused_flow_view(device),
+        );
+        self.size = Some(textures.size);
+        self.total_frame = textures.layers;
+    }
+
+    /// Layers the shader blends, the first one as long as the playback is
+    /// on frames still loading.
+    fn blended_frames(&self) -> (u32, u32, f32) {
+        if let Some(live) = &self.live {
+            return live.blended_frames();
+        }
+        let playback = &self.playback;
+        if let Some(stream) = &self.stream {
+            return match (
+                stream.layer_of(playback.previous()),
+                stream.layer_of(playback.next()),
+            ) {
+                (Some(previous), Some(next)) => (previous, next, playback.mix_value()),
+                (Some(previous), None) => (previous, previous, 0.0),
+                (None, _) => (0, 0, 0.0),
+            };
+        }
+
+        let (previous, next, mix_value) = match &self.loading {
+            Some(loading)
+                if !loading.is_resident(playback.previous())
+                    || !loading.is_resident(playback.next()) =>
+            {
+                (0, 0, 0.0)
+            }
+            _ => (playback.previous(), playback.next(), playback.mix_value()),
+        };
+
+        match &self.frame_layers {
+            Some(frame_layers) => (
+                frame_layers[previous as usize],
+                frame_layers[next as usize],
+                mix_value,
+            ),
+            None => (previous, next, mix_value),
+        }
+    }
+
+    fn go_to_next_frame(&mut self, increment: f32) {
+        self.position = if self.use_timestamp {
+            SystemTime::now()
+                .duration_since(self.started_at)
+                .expect("Time went backwards")
+                .as_secs_f32()
+        } else {
+            if let Some(loading) = &self.loading {
+                if !loading.can_play(&self.playback, increment) {
+                    return;
+                }
+            }
+            if let Some(stream) = &self.stream {
+                if !stream.can_play(&self.playback, increment) {
+                    return;
+                }
+            }
+            self.playback.advance(increment);
+            self.playback.position()
+        };
+    }
+
+    fn set_playback_mode(&mut self, mode: PlaybackMode) {
+        self.playback.set_mode(mode);
+        self.position = self.playback.position();
+    }
+
+    fn update_shader_globals(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        let (previous, next, mix_value) = self.blended_frames();
+        let position = if self.use_timestamp {
+            self.position
+        } else {
+            previous as f32 + mix_value
+        };
+        let uniform = uniform_bytes(self.total_frame, position, (previous, next, mix_value));
+
+        let temp_buf = device
+            .create_buffer_mapped(self.uniform.len(), wgpu::BufferUsage::COPY_SRC)
+            .fill_from_slice(&uniform);
+
+        encoder.copy_buffer_to_buffer(
+            &temp_buf,
+            0,
+            &self.uniform_buf,
+            0,
+            uniform.len() as wgpu::BufferAddress,
+        );
+    }
+
+    /// Clears `view` to `background` first, otherwise draws over it.
+    fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        size: (u32, u32),
+        (fit, background): (FitMode, Option<Color>),
+    ) {
+        let (load_op, [r, g, b, a]) = match background {
+            Some(background) => (wgpu::LoadOp::Clear, background.to_linear()),
+            None => (wgpu::LoadOp::Load, [0.0; 4]),
+        };
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: view,
+                resolve_target: None,
+                load_op,
+                store_op: wgpu::StoreOp::Store,
+                clear_color: wgpu::Color { r, g, b, a },
+            }],
+            depth_stencil_attachment: None,
+        });
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        for (x, y, width, height) in viewports(fit, self.size, size) {
+            rpass.set_viewport(x, y, width, height, 0.0, 1.0);
+            rpass.draw(0..6, 0..1);
+        }
+    }
+}
+
+impl Screen {
+    fn scene_target(&self) -> ScreenTarget {
+        ScreenTarget {
+            id: self.id,
+            target: Target {
+                output: self.output,
+                fit: self.settings.fit,
+            },
+            backdrop: self.settings.backdrop.is_some(),
+            fps: self.settings.fps,
+        }
+    }
+
+    /// Draws `scene` over the backdrop, or over the background color without
+    /// one.
+    fn render_scene(
+        &self,
+        scene: &Scene,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        size: (u32, u32),
+    ) {
+        let background = Some(self.settings.background);
+        match &self.backdrop {
+            Some(backdrop) => {
+                backdrop.render(encoder, view, size, (FitMode::Fill, background));
+                scene.render(encoder, view, size, (self.settings.fit, None));
+            }
+            None => scene.render(encoder, view, size, (self.settings.fit, background)),
+        }
+    }
+
+    fn set_timing(&mut self, fps: u32, rendered_fps: u32) {
+        self.increment = fps as f32 / rendered_fps as f32;
+        self.frame_interval = Duration::new(0, 1_000_000_000 / rendered_fps);
+        self.next_frame_at = Instant::now();
+    }
+
+    /// The replaced scene is left out of the budget already, the scenes
+    /// prepared meanwhile being displayed once its transition is over.
+    fn set_scene(&mut self, scene: Scene, duration: Duration) {
+        let mut previous_scene = std::mem::replace(&mut self.scene, scene);
+        previous_scene.memory.release();
+        self.previous_scene = Some(previous_scene);
+        self.transition_started_at = Instant::now();
+        self.transition_duration = duration;
+    }
+
+    fn transition_progress(&self) -> f32 {
+        if self.previous_scene.is_none() || self.transition_duration == Duration::from_secs(0) {
+            return 1.0;
+        }
+
+        let elapsed = self.transition_started_at.elapsed().as_secs_f32();
+        (elapsed / self.transition_duration.as_secs_f32()).min(1.0)
+    }
+
+    fn go_to_next_frame(&mut self) {
+        if self.transition_progress() >= 1.0 {
+            self.previous_scene = None;
+        }
+
+        self.scene.go_to_next_frame(self.increment);
+        if let Some(previous_scene) = &mut self.previous_scene {
+            previous_scene.go_to_next_frame(self.increment);
+        }
+        if let Some(backdrop) = &mut self.backdrop {
+            backdrop.go_to_next_frame(self.increment);
+        }
+    }
+}
+
+impl SceneLoader {
+    /// Opens and decodes `source`, then prepares its scene for every screen
+    /// displayed now, so that the event loop only uploads them. Live feeds
+    /// are shared by the pipeline, which follows them itself.
+    pub fn prepare(&self, source: &Source) -> Result<Prepared, SourceError> {
+        let decoded = Arc::new(source.decode()?);
+        let screens = if source.is_live() {
+            Vec::new()
+        } else {
+            self.displayed.lock().unwrap().screens.clone()
+        };
+        let scenes = screens
+            .into_iter()
+            .map(|screen| (screen, self.prepare_scene(source, &decoded, screen)))
+            .collect();
+
+        Ok(Prepared {
+            source: source.clone(),
+            decoded,
+            scenes,
+        })
+    }
+
+    /// A streamed source is read again rather than uploaded whole.
+    fn prepare_scene(
+        &self,
+        source: &Source,
+        decoded: &Arc<Decoded>,
+        screen: ScreenTarget,
+    ) -> Result<PreparedScene, SourceError> {
+        let streamed = match self.stream_source(source, screen.target) {
+            Ok(streamed) => streamed,
+            Err(e) => {
+                warn!("Cannot stream {:?}: {}", source, e);
+                None
+            }
+        };
+        match streamed {
+            Some((first, pending)) => {
+                self.fit_scene(source, Arc::new(first), Some(pending), screen.target)
+            }
+            None => self.load_decoded(source, decoded, screen.fps, screen.target),
+        }
+    }
+
+    /// Frames come resampled for `target`, by the thread decoding them.
+    /// Sources with more frames than the textures hold are streamed instead.
+    /// Over the video memory budget, GIF frames are stored as palette
+    /// indices, or else decoded again downscaled to half their size at most,
+    /// or else streamed within the layers the budget holds.
+    fn fit_scene(
+        &self,
+        source: &Source,
+        decoded: Arc<Decoded>,
+        pending: Option<Pending>,
+        target: Target,
+    ) -> Result<PreparedScene, SourceError> {
+        let palette = self.options.palette;
+        let frames = match &decoded.frames {
+            Some(frames) => frames,
+            None => return Ok(self.prepared(decoded, pending, palette)),
+        };
+        let size = (frames.width, frames.height);
+
+        let limits = self.options.limits.sampled_by(decoded.fragment.is_some());
+        let capacity = |palette| frame_capacity(size, scene_texels(&decoded, palette), limits);
+        if capacity(palette) == 0 {
+            return Err(format!(
+                "frames of {}x{} do not fit in {}x{} textures",
+                size.0, size.1, limits.dimension, limits.dimension
+            )
+            .into());
+        }
+        let layers = match &pending {
+            Some(Pending::Loading(loading, _)) => loading.total_frame,
+            Some(Pending::Streaming(_, ring_size)) => *ring_size,
+            Some(Pending::Live(_)) => 0,
+            None => frames.total_frame() as u32,
+        };
+        // Scenes of the other screens, preloaded ones, backdrops and
+        // offscreen targets take their part of the budget.
+        let taken =
+            self.displayed.lock().unwrap().swap_chain_memory + self.options.memory_usage.total();
+        let available = self.options.video_memory.saturating_sub(taken);
+        let memory =
+            |palette| u64::from(layers) * layer_memory(size, scene_texels(&decoded, palette));
+        if layers <= capacity(palette) && memory(palette) <= available {
+            return Ok(self.prepared(decoded, pending, palette));
+        }
+
+        if layers > capacity(palette) {
+            info!(
+                "{} frames of {}x{} do not fit in the textures",
+                layers, size.0, size.1
+            );
+        } else {
+            info!(
+                "{} frames of {}x{} take about {} MiB of video memory, {} MiB are available",
+                layers,
+                size.0,
+                size.1,
+                memory(palette) >> 20,
+                available >> 20
+            );
+        }
+        // Palette indices come first, they take a quarter of the memory
+        // without losing anything.
+        let indexed = !palette && scene_texels(&decoded, true) == Texels::Indexed;
+        if indexed && layers <= capacity(true) && memory(true) <= available {
+            info!("Storing the frames as palette indices");
+            return Ok(self.prepared(decoded, pending, true));
+        }
+        let palette = palette || indexed;
+        let capacity = capacity(palette);
+
+        let pending = match pending {
+            Some(Pending::Streaming(stream, ring_size)) => {
+                let fitting = available / layer_memory(size, scene_texels(&decoded, palette));
+                let ring_size = ring_size.min(capacity).min(fitting.max(2) as u32);
+                info!("Keeping {} frames in video memory", ring_size);
+                return Ok(self.prepared(
+                    decoded,
+                    Some(Pending::Streaming(stream, ring_size)),
+                    palette,
+                ));
+            }
+            pending => pending,
+        };
+        if let Some(downscaled) = downscaled_target(target, size, (layers, available), limits) {
+            info!(
+                "Downscaling the frames to {}x{}",
+                downscaled.output.0, downscaled.output.1
+            );
+            // Frames decoded whole are played once they are all there again.
+            // Dropping the frames loading stops their decoding.
+            let readahead = match pending {
+                Some(Pending::Loading(_, readahead)) => readahead,
+                _ => u32::MAX,
+            };
+            drop(pending);
+            let (first, loading) = source.decode_progressively(
+                self.options.motion_compensation,
+                Some(downscaled),
+                self.options.staging_memory,
+            )?;
+            let pending = loading.map(|loading| Pending::Loading(loading, readahead));
+            return Ok(self.prepared(Arc::new(first), pending, palette));
+        }
+
+        match self.stream_scene(source, target, (capacity, available), palette)? {
+            Some(scene) => Ok(scene),
+            None if layers > capacity => Err(format!(
+                "{} frames of {}x{} cannot be streamed",
+                layers, size.0, size.1
+            )
+            .into()),
+            None => {
+                warn!(
+                    "Displaying {:?} over the video memory budget, it cannot be streamed",
+                    source
+                );
+                Ok(self.prepared(decoded, pending, palette))
+            }
+        }
+    }
+
+    /// Streams `source` within the layers the textures hold and the ones
+    /// `available` bytes hold, unless `--stream` says. `None` when it cannot
+    /// be streamed.
+    fn stream_scene(
+        &self,
+        source: &Source,
+        target: Target,
+        (capacity, available): (u32, u64),
+        palette: bool,
+    ) -> Result<Option<PreparedScene>, SourceError> {
+        let (first, stream) = match source.stream(Some(target))? {
+            Some(streamed) => streamed,
+            None => return Ok(None),
+        };
+        let fitting = match &first.frames {
+            Some(frames) => {
+                available
+                    / layer_memory((frames.width, frames.height), scene_texels(&first, palette))
+            }
+            None => 0,
+        };
+        let ring_size = self
+            .options
+            .stream
+            .unwrap_or(fitting.min(u64::from(capacity)) as u32)
+            .max(2)
+            .min(capacity)
+            .min(stream.total_frame);
+        info!("Streaming the frames, {} at a time", ring_size);
+
+        let pending = Some(Pending::Streaming(stream, ring_size));
+        Ok(Some(self.prepared(Arc::new(first), pending, palette)))
+    }
+
+    /// Counts the video memory the scene will take until it is created.
+    fn prepared(
+        &self,
+        decoded: Arc<Decoded>,
+        pending: Option<Pending>,
+        palette: bool,
+    ) -> PreparedScene {
+        let memory = match &decoded.frames {
+            Some(frames) => {
+                let layers = match &pending {
+                    Some(Pending::Loading(loading, _)) => loading.total_frame,
+                    Some(Pending::Streaming(_, ring_size)) => *ring_size,
+                    Some(Pending::Live(_)) => 0,
+                    None => frames.total_frame() as u32,
+                };
+                u64::from(layers)
+                    * layer_memory(
+                        (frames.width, frames.height),
+                        scene_texels(&decoded, palette),
+                    )
+            }
+            None => 0,
+        };
+        PreparedScene {
+            decoded,
+            pending,
+            palette,
+            reserved: self.options.memory_usage.allocate(memory),
+        }
+    }
+
+    /// Shows the first of frames decoded whole right away. The other ones
+    /// are uploaded as they come from a background thread, which resamples
+    /// them for `target` and estimates the motion between them too, so that
+    /// rendering goes on meanwhile. Deferred frames are decoded again there.
+    fn load_decoded(
+        &self,
+        source: &Source,
+        decoded: &Arc<Decoded>,
+        fps: u32,
+        target: Target,
+    ) -> Result<PreparedScene, SourceError> {
+        if decoded
+            .frames
+            .as_ref()
+            .map_or(false, |frames| frames.deferred)
+        {
+            return self.decode_source(source, fps, target);
+        }
+        let estimate_flow = self.options.motion_compensation && decoded.fragment.is_none();
+        let background = (estimate_flow, self.options.staging_memory);
+        match Decoded::load_progressively(decoded, target, background) {
+            Some((first, loading)) => {
+                let pending =
+                    loading.map(|loading| Pending::Loading(loading, fps * READAHEAD_SECONDS));
+                self.fit_scene(source, Arc::new(first), pending, target)
+            }
+            None => self.fit_scene(source, Arc::clone(decoded), None, target),
+        }
+    }
+
+    /// Starts streaming `source` when enabled and it has frames.
+    fn stream_source(
+        &self,
+        source: &Source,
+        target: Target,
+    ) -> Result<Option<(Decoded, Pending)>, SourceError> {
+        let ring_size = match self.options.stream {
+            Some(ring_size) => ring_size.max(2),
+            None if source.is_video() => VIDEO_RING_SIZE,
+            None => return Ok(None),
+        };
+
+        Ok(source.stream(Some(target))?.map(|(decoded, stream)| {
+            let ring_size = ring_size.min(stream.total_frame);
+            (decoded, Pending::Streaming(stream, ring_size))
+        }))
+    }
+
+    /// Streams `source` when it is streamed, or else decodes it
+    /// progressively.
+    fn load_source(
+        &self,
+        source: &Source,
+        fps: u32,
+        target: Target,
+    ) -> Result<PreparedScene, SourceError> {
+        match self.stream_source(source, target)? {
+            Some((decoded, pending)) => {
+                self.fit_scene(source, Arc::new(decoded), Some(pending), target)
+            }
+            None => self.decode_source(source, fps, target),
+        }
+    }
+
+    /// Decodes the first frame of `source`, the other ones following in the
+    /// background, resampled for `target`.
+    fn decode_source(
+        &self,
+        source: &Source,
+        fps: u32,
+        target: Target,
+    ) -> Result<PreparedScene, SourceError> {
+        let (decoded, loading) = source.decode_progressively(
+            self.options.motion_compensation,
+            Some(target),
+            self.options.staging_memory,
+        )?;
+        let pending = loading.map(|loading| Pending::Loading(loading, fps * READAHEAD_SECONDS));
+        self.fit_scene(source, Arc::new(decoded), pending, target)
+    }
+}
+
 impl Pipeline {
     pub fn new(options: &Opt) -> Result<Self, Box<dyn Error>> {
         let adapter = wgpu::Adapter::request(&wgpu::RequestAdapterOptions {
@@ -321,41 +2815,404 @@ impl Pipeline {
         })
         .unwrap(); // FIXME: Should use Result
 
-        let (device, mut queue) = adapter.request_device(&wgpu::DeviceDescriptor {
+        let (device, queue) = adapter.request_device(&wgpu::DeviceDescriptor {
             extensions: wgpu::Extensions {
                 anisotropic_filtering: false,
             },
             limits: wgpu::Limits::default(),
         });
 
-        let (texture_view, total_frame) = load_textures(&options.frame_path, &device, &mut queue)?;
+        let scene_options = SceneOptions {
+            playback: options.playback,
+            interpolation: options.interpolation.clone(),
+            motion_compensation: options.motion_compensation,
+            stream: options.stream,
+            palette: options.palette,
+            // Below the limits every device supports, textures would only be
+            // split more than needed.
+            limits: TextureLimits {
+                dimension: options.max_texture_size.max(MIN_TEXTURE_DIMENSION),
+                array_layers: options.max_texture_layers.max(MIN_ARRAY_LAYERS),
+                arrays: FRAME_ARRAYS,
+            },
+            backdrop: false,
+            staging_memory: options.staging_memory as usize * 1024 * 1024,
+            video_memory: u64::from(options.video_memory) * 1024 * 1024,
+            memory_usage: MemoryUsage::default(),
+        };
         let sampler = create_sampler(&device);
+        let linear_sampler = create_linear_sampler(&device);
         let bind_group_layout = create_bind_group_layout(&device);
-        let render_pipeline =
-            create_pipeline(&device, &bind_group_layout, &options.custom_fragment);
+        let transition_bind_group_layout = create_transition_bind_group_layout(&device);
+        let transition_pipeline =
+            create_transition_pipeline(&device, &transition_bind_group_layout, &options.transition)
+                .map_err(|e| e as Box<dyn Error>)?;
+        let post_bind_group_layout = create_post_bind_group_layout(&device);
+
+        let loader = SceneLoader {
+            options: scene_options.clone(),
+            displayed: Arc::default(),
+        };
+        let pipeline = Pipeline {
+            scene_options,
+            loader,
+            default_transition_duration: Duration::from_secs_f32(options.transition_duration),
+            device,
+            queue,
+            bind_group_layout,
+            sampler,
+            linear_sampler,
+            transition_bind_group_layout,
+            transition_pipeline,
+            post_bind_group_layout,
+            screens: Vec::new(),
+            next_screen_id: 0,
+            live_feeds: HashMap::new(),
+            outputs: Vec::new(),
+        };
 
-        let uniform = [total_frame.to_ne_bytes(), [0u8, 0, 0, 0]].concat();
+        Ok(pipeline)
+    }
 
-        let uniform_buf = device
-            .create_buffer_mapped(
-                uniform.len(),
-                wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
-            )
-            .fill_from_slice(&uniform);
+    /// Prepares the scenes of sources off the event loop.
+    pub fn loader(&self) -> SceneLoader {
+        self.loader.clone()
+    }
+
+    /// Creates the scene prepared for a screen. Custom fragments are blended
+    /// over what is under them with a `backdrop` only.
+    fn new_scene(&mut self, scene: PreparedScene, backdrop: bool) -> Result<Scene, SourceError> {
+        let PreparedScene {
+            decoded,
+            pending,
+            palette,
+            reserved,
+        } = scene;
+        // The scene counts what it takes itself.
+        drop(reserved);
+        let options = SceneOptions {
+            palette,
+            backdrop,
+            ..self.scene_options.clone()
+        };
+        Scene::new(
+            &self.device,
+            &mut self.queue,
+            &self.bind_group_layout,
+            (&self.sampler, &self.linear_sampler),
+            &options,
+            &decoded,
+            pending,
+        )
+    }
+
+    /// Where every screen displays its scenes, for the scenes of sources
+    /// preloaded from now on to be prepared for them.
+    fn publish_screens(&self) {
+        let mut displayed = self.loader.displayed.lock().unwrap();
+        displayed.screens = self.screens.iter().map(Screen::scene_target).collect();
+        displayed.swap_chain_memory = self
+            .outputs
+            .iter()
+            .map(|(width, height)| SWAP_CHAIN_IMAGES * 4 * u64::from(*width) * u64::from(*height))
+            .sum();
+    }
+
+    /// Follows a live source, `None` for other sources. Live frames are
+    /// always followed as they arrive.
+    fn follow_live(&mut self, source: &Source) -> Result<Option<(Decoded, Pending)>, SourceError> {
+        let path = match source.frames.as_ref().filter(|_| source.is_live()) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        // Scenes of ended feeds keep their last frame, new ones read the
+        // input again.
+        self.live_feeds.retain(|_, feed| !feed.has_ended());
+        let feed = self
+            .live_feeds
+            .entry(path.clone())
+            .or_insert_with(|| live::Feed::open(path));
+        let feed = Arc::clone(feed);
+        Ok(Some((source.decode()?, Pending::Live(feed))))
+    }
+
+    /// Prepares the scene of `screen` on the event loop, for the screens
+    /// created or resized since the source was preloaded, and for live
+    /// sources.
+    fn prepare_scene(
+        &mut self,
+        source: &Source,
+        decoded: &Arc<Decoded>,
+        screen: ScreenTarget,
+    ) -> Result<PreparedScene, SourceError> {
+        match self.follow_live(source)? {
+            Some((first, pending)) => {
+                self.loader
+                    .fit_scene(source, Arc::new(first), Some(pending), screen.target)
+            }
+            None => self.loader.prepare_scene(source, decoded, screen),
+        }
+    }
+
+    /// Creates a scene showing the first frame of `settings.source` right
+    /// away, the other frames being decoded in the background, resampled
+    /// for an `output` of that size.
+    fn load_scene(
+        &mut self,
+        settings: &OutputSettings,
+        output: (u32, u32),
+    ) -> Result<Scene, SourceError> {
+        let target = Target {
+            output,
+            fit: settings.fit,
+        };
+        let scene = match self.follow_live(&settings.source)? {
+            Some((decoded, pending)) => {
+                self.loader
+                    .fit_scene(&settings.source, Arc::new(decoded), Some(pending), target)?
+            }
+            None => self
+                .loader
+                .load_source(&settings.source, settings.fps, target)?,
+        };
+        self.new_scene(scene, settings.backdrop.is_some())
+    }
+
+    /// The scene transparent frames are composited on, filling the output.
+    /// Its frames are decoded in the background too.
+    fn load_backdrop(
+        &mut self,
+        settings: &OutputSettings,
+        output: (u32, u32),
+    ) -> Result<Option<Scene>, SourceError> {
+        let source = match &settings.backdrop {
+            Some(source) => source,
+            None => return Ok(None),
+        };
+
+        let target = Target {
+            output,
+            fit: FitMode::Fill,
+        };
+        let scene = self.loader.decode_source(source, settings.fps, target)?;
+        self.new_scene(scene, false).map(Some)
+    }
+
+    fn create_post_pipeline(
+        &self,
+        settings: &OutputSettings,
+    ) -> Result<Option<wgpu::RenderPipeline>, SourceError> {
+        match &settings.post_processing {
+            Some(path) => create_post_pipeline(&self.device, &self.post_bind_group_layout, path)
+                .map(Some)
+                .map_err(|e| format!("{}: {}", path.display(), e).into()),
+            None => Ok(None),
+        }
+    }
+
+    fn find_screen(&self, settings: &OutputSettings) -> Option<usize> {
+        self.screens
+            .iter()
+            .find(|screen| screen.settings == *settings)
+            .map(|screen| screen.id)
+    }
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bind_group_layout,
+    /// Returns the screen displaying `settings`, creating it when no output
+    /// displays them yet. `preloaded` avoids decoding a source again.
+    /// `output` is the size of the largest output it is displayed on.
+    pub fn screen_for(
+        &mut self,
+        settings: &OutputSettings,
+        preloaded: Option<(&Source, &Arc<Decoded>)>,
+        output: (u32, u32),
+    ) -> Result<usize, SourceError> {
+        if let Some(id) = self.find_screen(settings) {
+            return Ok(id);
+        }
+
+        let post_pipeline = self.create_post_pipeline(settings)?;
+        let target = Target {
+            output,
+            fit: settings.fit,
+        };
+        let scene = match preloaded {
+            // Videos are streamed and live frames followed, not taken whole.
+            Some((source, decoded))
+                if *source == settings.source && !source.is_video() && !source.is_live() =>
+            {
+                let scene = self
+                    .loader
+                    .load_decoded(source, decoded, settings.fps, target)?;
+                self.new_scene(scene, settings.backdrop.is_some())?
+            }
+            _ => self.load_scene(settings, output)?,
+        };
+        let backdrop = self.load_backdrop(settings, output)?;
+
+        let id = self.next_screen_id;
+        self.next_screen_id += 1;
+
+        let mut screen = Screen {
+            id,
+            settings: settings.clone(),
+            output,
+            increment: 0.0,
+            frame_interval: Duration::from_secs(0),
+            next_frame_at: Instant::now(),
+            started_at: Instant::now(),
+            scene,
+            previous_scene: None,
+            transition_started_at: Instant::now(),
+            transition_duration: Duration::from_secs(0),
+            post_pipeline,
+            backdrop,
+        };
+        screen.set_timing(settings.fps, settings.rendered_fps);
+        self.screens.push(screen);
+        self.publish_screens();
+
+        Ok(id)
+    }
+
+    /// Moves the outputs of screen `id` to new settings. When no output keeps
+    /// the old ones, `reuse` changes that screen in place so that a new
+    /// source transitions from the current one.
+    pub fn reconfigure(
+        &mut self,
+        id: usize,
+        settings: &OutputSettings,
+        reuse: bool,
+        output: (u32, u32),
+    ) -> Result<usize, SourceError> {
+        if let Some(id) = self.find_screen(settings) {
+            return Ok(id);
+        }
+
+        let index = match self.screens.iter().position(|screen| screen.id == id) {
+            Some(index) if reuse => index,
+            _ => return self.screen_for(settings, None, output),
+        };
+
+        // A post-processing shader that does not compile leaves the screen
+        // as it is, before anything is loaded.
+        let current = self.screens[index].settings.clone();
+        let post_pipeline = if current.post_processing == settings.post_processing {
+            None
+        } else {
+            Some(self.create_post_pipeline(settings)?)
+        };
+        // Custom fragments blend over a backdrop only, the scene is created
+        // again when there comes or goes one.
+        let blend_changed = current.backdrop.is_some() != settings.backdrop.is_some();
+        let scene =
+            if current.source == settings.source && current.fit == settings.fit && !blend_changed {
+                None
+            } else {
+                Some(self.load_scene(settings, output)?)
+            };
+        let backdrop = if current.backdrop == settings.backdrop {
+            None
+        } else {
+            Some(self.load_backdrop(settings, output)?)
+        };
+
+        let duration = self.default_transition_duration;
+        let screen = &mut self.screens[index];
+        if let Some(scene) = scene {
+            screen.set_scene(scene, duration);
+        }
+        if let Some(post_pipeline) = post_pipeline {
+            screen.post_pipeline = post_pipeline;
+        }
+        if let Some(backdrop) = backdrop {
+            screen.backdrop = backdrop;
+        }
+        if (current.fps, current.rendered_fps) != (settings.fps, settings.rendered_fps) {
+            screen.set_timing(settings.fps, settings.rendered_fps);
+        }
+        screen.settings = settings.clone();
+        screen.output = output;
+        self.publish_screens();
+
+        Ok(id)
+    }
+
+    /// Drops the screens no output displays anymore.
+    pub fn retain_screens(&mut self, ids: &[usize]) {
+        self.screens.retain(|screen| ids.contains(&screen.id));
+        self.publish_screens();
+    }
+
+    /// Replaces the displayed source on every output, transitioning from the
+    /// current one.
+    pub fn set_source(&mut self, prepared: Prepared) {
+        self.set_source_with_transition(prepared, self.default_transition_duration);
+    }
+
+    /// Uploads the scenes prepared for the screens, preparing the ones of the
+    /// screens created or resized since.
+    pub fn set_source_with_transition(&mut self, prepared: Prepared, duration: Duration) {
+        let Prepared {
+            source,
+            decoded,
+            mut scenes,
+        } = prepared;
+        for index in 0..self.screens.len() {
+            let screen = self.screens[index].scene_target();
+            let scene = match scenes.iter().position(|(target, _)| *target == screen) {
+                Some(position) => scenes.swap_remove(position).1,
+                None => self.prepare_scene(&source, &decoded, screen),
+            };
+            let scene = match scene.and_then(|scene| self.new_scene(scene, screen.backdrop)) {
+                Ok(scene) => scene,
+                Err(e) => {
+                    warn!("Cannot display {:?}: {}", source, e);
+                    continue;
+                }
+            };
+            let screen = &mut self.screens[index];
+            screen.set_scene(scene, duration);
+            screen.settings.source = source.clone();
+        }
+    }
+
+    /// Keeps the current transition when the new one cannot be compiled.
+    pub fn set_transition(&mut self, transition: &Transition) -> Result<(), SourceError> {
+        info!("Switching transition to {:?}", transition);
+        self.transition_pipeline = create_transition_pipeline(
+            &self.device,
+            &self.transition_bind_group_layout,
+            transition,
+        )?;
+        Ok(())
+    }
+
+    fn create_transition_targets(&self, size: (u32, u32)) -> TransitionTargets {
+        let from = create_render_target(&self.device, size);
+        let to = create_render_target(&self.device, size);
+
+        let uniform_buf = self.device.create_buffer(&wgpu::BufferDescriptor {
+            size: 8,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.transition_bind_group_layout,
             bindings: &[
                 wgpu::Binding {
                     binding: 0,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
+                    resource: wgpu::BindingResource::Sampler(&self.linear_sampler),
                 },
                 wgpu::Binding {
                     binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&texture_view),
+                    resource: wgpu::BindingResource::TextureView(&from),
                 },
                 wgpu::Binding {
                     binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&to),
+                },
+                wgpu::Binding {
+                    binding: 3,
                     resource: wgpu::BindingResource::Buffer {
                         buffer: &uniform_buf,
                         range: 0..8,
@@ -364,73 +3221,344 @@ impl Pipeline {
             ],
         });
 
-        let pipeline = Pipeline {
-            position: 0.0,
-            total_frame,
-            increment: options.fps as f32 / options.rendered_fps as f32,
-            use_timestamp: options.custom_fragment.is_some(),
-            started_at: SystemTime::now(),
-            device,
-            queue,
-            bind_group,
-            render_pipeline,
-            uniform,
+        TransitionTargets {
+            size,
+            from,
+            to,
             uniform_buf,
-        };
-
-        Ok(pipeline)
+            bind_group,
+            _memory: self
+                .scene_options
+                .memory_usage
+                .allocate(2 * render_target_memory(size)),
+        }
     }
 
-    pub fn go_to_next_frame(&mut self) {
-        self.position = if self.use_timestamp {
-            SystemTime::now()
-                .duration_since(self.started_at)
-                .expect("Time went backwards")
-                .as_secs_f32()
-        } else {
-            (self.position + self.increment) % self.total_frame as f32
-        };
-    }
+    /// Matches the `Transition` block of `shaders/transition.glsl`.
+    fn update_transition_globals(
+        &self,
+        targets: &TransitionTargets,
+        progress: f32,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let ratio = targets.size.0 as f32 / targets.size.1.max(1) as f32;
+        let uniform = [progress.to_ne_bytes(), ratio.to_ne_bytes()].concat();
 
-    pub fn update_shader_globals(&mut self) {
-        let mut encoder = self
+        let temp_buf = self
             .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+            .create_buffer_mapped(uniform.len(), wgpu::BufferUsage::COPY_SRC)
+            .fill_from_slice(&uniform);
 
-        let uniform = [self.total_frame.to_ne_bytes(), self.position.to_le_bytes()].concat();
+        encoder.copy_buffer_to_buffer(
+            &temp_buf,
+            0,
+            &targets.uniform_buf,
+            0,
+            uniform.len() as wgpu::BufferAddress,
+        );
+    }
+
+    fn create_post_targets(&self, size: (u32, u32)) -> PostTargets {
+        let input = create_render_target(&self.device, size);
+
+        let uniform_buf = self.device.create_buffer(&wgpu::BufferDescriptor {
+            size: 16,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.post_bind_group_layout,
+            bindings: &[
+                wgpu::Binding {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(&self.linear_sampler),
+                },
+                wgpu::Binding {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&input),
+                },
+                wgpu::Binding {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Buffer {
+                        buffer: &uniform_buf,
+                        range: 0..16,
+                    },
+                },
+            ],
+        });
+
+        PostTargets {
+            size,
+            input,
+            uniform_buf,
+            bind_group,
+            _memory: self
+                .scene_options
+                .memory_usage
+                .allocate(render_target_memory(size)),
+        }
+    }
+
+    /// Matches the `PostProcessing` block of `shaders/post.glsl`.
+    fn update_post_globals(
+        &self,
+        targets: &PostTargets,
+        time: f32,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let uniform = [
+            (targets.size.0 as f32).to_ne_bytes(),
+            (targets.size.1 as f32).to_ne_bytes(),
+            time.to_ne_bytes(),
+            0f32.to_ne_bytes(),
+        ]
+        .concat();
 
         let temp_buf = self
             .device
-            .create_buffer_mapped(self.uniform.len(), wgpu::BufferUsage::COPY_SRC)
+            .create_buffer_mapped(uniform.len(), wgpu::BufferUsage::COPY_SRC)
             .fill_from_slice(&uniform);
 
         encoder.copy_buffer_to_buffer(
             &temp_buf,
             0,
-            &self.uniform_buf,
+            &targets.uniform_buf,
             0,
             uniform.len() as wgpu::BufferAddress,
         );
+    }
+
+    /// When the next screen has to move to its next frame.
+    pub fn next_frame_at(&self) -> Instant {
+        self.screens
+            .iter()
+            .map(|screen| screen.next_frame_at)
+            .min()
+            .unwrap_or_else(|| Instant::now() + Duration::from_secs(1))
+    }
+
+    /// Moves the screens that are due to their next frame, returning them.
+    pub fn go_to_next_frame(&mut self) -> Vec<usize> {
+        let now = Instant::now();
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+        let mut advanced = Vec::new();
+
+        for screen in &mut self.screens {
+            let scenes = std::iter::once(&mut screen.scene)
+                .chain(&mut screen.previous_scene)
+                .chain(&mut screen.backdrop);
+            for scene in scenes {
+                scene.receive_frames(
+                    &self.device,
+                    &mut encoder,
+                    &self.bind_group_layout,
+                    (&self.sampler, &self.linear_sampler),
+                );
+            }
+
+            if screen.next_frame_at > now {
+                continue;
+            }
+
+            screen.next_frame_at += screen.frame_interval;
+            if screen.next_frame_at < now {
+                // Late after a stall, do not try to catch up.
+                screen.next_frame_at = now + screen.frame_interval;
+            }
+
+            screen.go_to_next_frame();
+            screen
+                .scene
+                .update_shader_globals(&self.device, &mut encoder);
+            if let Some(previous_scene) = &screen.previous_scene {
+                previous_scene.update_shader_globals(&self.device, &mut encoder);
+            }
+            if let Some(backdrop) = &screen.backdrop {
+                backdrop.update_shader_globals(&self.device, &mut encoder);
+            }
+            advanced.push(screen.id);
+        }
+
         self.queue.submit(&[encoder.finish()]);
+        advanced
+    }
+
+    pub fn set_playback_mode(&mut self, mode: PlaybackMode) {
+        info!(
+            "Switching playback from {:?} to {:?}",
+            self.scene_options.playback, mode
+        );
+        self.scene_options.playback = mode;
+        for screen in &mut self.screens {
+            screen.scene.set_playback_mode(mode);
+        }
     }
 }
 
+/// Size of the largest of the `outputs` showing `settings`, which frames are
+/// resampled for.
+fn largest_output<'a>(
+    outputs: impl Iterator<Item = (&'a MonitorHandle, &'a OutputSettings)>,
+    settings: &OutputSettings,
+) -> (u32, u32) {
+    outputs
+        .filter(|(_, output_settings)| *output_settings == settings)
+        .map(|(monitor, _)| physical_extent(monitor.size()))
+        .max_by_key(|(width, height)| u64::from(*width) * u64::from(*height))
+        .unwrap_or((1, 1))
+}
+
+fn create_window(event_loop: &EventLoopWindowTarget<CustomEvent>) -> Window {
+    WindowBuilder::new()
+        .with_shell(false)
+        .disable_input_region(true)
+        .build(event_loop)
+        .unwrap()
+}
+
 pub struct PipelineWindows {
     windows: Vec<PipelineWindow>,
+    event_proxy: EventLoopProxy<CustomEvent>,
 }
 
 impl PipelineWindows {
-    pub fn new(event_loop: &EventLoop<CustomEvent>, pipeline: &Pipeline) -> Self {
-        let windows = event_loop
+    /// Opens a window on every output, with the settings `resolve` returns
+    /// for its name.
+    pub fn new<F>(
+        event_loop: &EventLoop<CustomEvent>,
+        pipeline: &mut Pipeline,
+        resolve: F,
+        preloaded: Option<(&Source, &Arc<Decoded>)>,
+    ) -> Result<Self, Box<dyn Error>>
+    where
+        F: Fn(Option<&str>) -> OutputSettings,
+    {
+        let event_proxy = event_loop.create_proxy();
+        let mut windows = Vec::new();
+
+        let outputs: Vec<_> = event_loop
             .available_monitors()
-            .map(|monitor| PipelineWindow::new(&pipeline.device, &event_loop, &monitor))
+            .map(|monitor| {
+                let window = create_window(event_loop);
+                let output_name = platform::output_name(&monitor, &window);
+                let settings = resolve(output_name.as_deref());
+                info!("Output {:?}: {:?}", output_name, settings);
+                (monitor, window, output_name, settings)
+            })
+            .collect();
+        pipeline.outputs = outputs
+            .iter()
+            .map(|(monitor, _, _, _)| physical_extent(monitor.size()))
+            .collect();
+        let sizes: Vec<_> = outputs
+            .iter()
+            .map(|(_, _, _, settings)| {
+                let monitors = outputs
+                    .iter()
+                    .map(|(monitor, _, _, settings)| (monitor, settings));
+                largest_output(monitors, settings)
+            })
+            .collect();
+
+        for ((monitor, window, output_name, settings), size) in outputs.into_iter().zip(sizes) {
+            let screen = pipeline
+                .screen_for(&settings, preloaded, size)
+                .map_err(|e| e as Box<dyn Error>)?;
+            windows.push(PipelineWindow::new(
+                &pipeline.device,
+                window,
+                monitor,
+                output_name,
+                (screen, settings.layer),
+                event_proxy.clone(),
+            ));
+        }
+
+        Ok(Self {
+            windows,
+            event_proxy,
+        })
+    }
+
+    /// Applies the settings `resolve` now returns to every window. The layer
+    /// of a surface cannot change, windows moving to another layer are
+    /// opened again.
+    pub fn reload<F>(
+        &mut self,
+        event_loop: &EventLoopWindowTarget<CustomEvent>,
+        pipeline: &mut Pipeline,
+        resolve: F,
+    ) where
+        F: Fn(Option<&str>) -> OutputSettings,
+    {
+        let wanted: Vec<_> = self
+            .windows
+            .iter()
+            .map(|w| resolve(w.output_name.as_deref()))
+            .collect();
+
+        // Screens whose settings no output wants anymore can be changed in
+        // place, once.
+        let mut reusable: Vec<usize> = pipeline
+            .screens
+            .iter()
+            .filter(|screen| !wanted.contains(&screen.settings))
+            .map(|screen| screen.id)
             .collect();
 
-        Self { windows }
+        let sizes: Vec<_> = wanted
+            .iter()
+            .map(|settings| {
+                let monitors = self.windows.iter().map(|w| &w.monitor).zip(&wanted);
+                largest_output(monitors, settings)
+            })
+            .collect();
+
+        let event_proxy = &self.event_proxy;
+        for ((window, settings), size) in self.windows.iter_mut().zip(&wanted).zip(sizes) {
+            let reuse = reusable.contains(&window.screen);
+            match pipeline.reconfigure(window.screen, settings, reuse, size) {
+                Ok(screen) => {
+                    reusable.retain(|id| *id != screen);
+                    window.screen = screen;
+                }
+                Err(e) => {
+                    warn!("Cannot reload output {:?}: {}", window.output_name, e);
+                    continue;
+                }
+            }
+
+            if window.layer != settings.layer {
+                info!(
+                    "Moving output {:?} to the {:?} layer",
+                    window.output_name, settings.layer
+                );
+                *window = PipelineWindow::new(
+                    &pipeline.device,
+                    create_window(event_loop),
+                    window.monitor.clone(),
+                    window.output_name.clone(),
+                    (window.screen, settings.layer),
+                    event_proxy.clone(),
+                );
+            }
+        }
+
+        pipeline.retain_screens(&self.screens());
+        info!("Configuration reloaded");
+    }
+
+    /// Screens displayed by at least one window.
+    pub fn screens(&self) -> Vec<usize> {
+        self.windows.iter().map(|w| w.screen).collect()
     }
 
-    pub fn render(&mut self, pipeline: &mut Pipeline) {
-        self.windows.iter_mut().for_each(|w| w.render(pipeline));
+    pub fn render(&mut self, window_id: WindowId, pipeline: &mut Pipeline) {
+        if let Some(window) = self.find_mut(window_id) {
+            window.render(pipeline);
+        }
     }
 
     pub fn find_mut(&mut self, window_id: WindowId) -> Option<&mut PipelineWindow> {
@@ -451,58 +3579,140 @@ impl PipelineWindows {
         return self.windows.is_empty();
     }
 
-    pub fn request_redraw(&self) {
-        self.windows.iter().for_each(|w| w.window.request_redraw());
+    /// Redraws the windows of the given screens.
+    pub fn request_redraw(&self, screens: &[usize]) {
+        self.windows
+            .iter()
+            .filter(|w| screens.contains(&w.screen))
+            .for_each(|w| w.window.request_redraw());
     }
 }
 
 pub struct PipelineWindow {
     pub(crate) window: Window,
+    monitor: MonitorHandle,
+    output_name: Option<String>,
+    layer: Layer,
+    screen: usize,
+    size: (u32, u32),
     swap_chain: wgpu::SwapChain,
     surface: wgpu::Surface,
+    transition_targets: Option<TransitionTargets>,
+    post_targets: Option<PostTargets>,
 }
 
 impl PipelineWindow {
     pub fn new(
         device: &wgpu::Device,
-        event_loop: &EventLoop<crate::platform::CustomEvent>,
-        monitor: &MonitorHandle,
+        window: Window,
+        monitor: MonitorHandle,
+        output_name: Option<String>,
+        (screen, layer): (usize, Layer),
+        event_proxy: EventLoopProxy<CustomEvent>,
     ) -> Self {
-        let window = WindowBuilder::new()
-            .with_shell(false)
-            .disable_input_region(true)
-            .build(&event_loop)
-            .unwrap();
         let surface = wgpu::Surface::create(&window);
-        let swap_chain = create_swap_chain(
-            device,
-            &surface,
-            window.inner_size().to_physical(window.hidpi_factor()),
-        );
+        let size = window.inner_size().to_physical(window.hidpi_factor());
+        let swap_chain = create_swap_chain(device, &surface, size);
 
         let pipeline_window = Self {
             window,
+            monitor,
+            output_name,
+            layer,
+            screen,
+            size: physical_extent(size),
             swap_chain,
             surface,
+            transition_targets: None,
+            post_targets: None,
         };
 
-        crate::platform::put_to_background(monitor, event_loop, &pipeline_window);
+        platform::put_to_background(
+            &pipeline_window.monitor,
+            event_proxy,
+            &pipeline_window,
+            layer,
+        );
         pipeline_window
     }
 
     pub fn resize(&mut self, size: LogicalSize, pipeline: &Pipeline) {
         let size = size.to_physical(self.window.hidpi_factor());
+        self.size = physical_extent(size);
         self.swap_chain = create_swap_chain(&pipeline.device, &self.surface, size);
         self.window.request_redraw();
     }
 
     fn render(&mut self, pipeline: &mut Pipeline) {
+        let screen = match pipeline
+            .screens
+            .iter()
+            .find(|screen| screen.id == self.screen)
+        {
+            Some(screen) => screen,
+            None => return,
+        };
+
         let frame = self.swap_chain.get_next_texture();
         let mut encoder = pipeline
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
 
-        {
+        let post_targets = match (&screen.post_pipeline, self.post_targets.take()) {
+            (None, _) => None,
+            (Some(_), Some(targets)) if targets.size == self.size => Some(targets),
+            (Some(_), _) => Some(pipeline.create_post_targets(self.size)),
+        };
+        let target = post_targets
+            .as_ref()
+            .map_or(&frame.view, |targets| &targets.input);
+
+        match &screen.previous_scene {
+            None => {
+                self.transition_targets = None;
+                screen.render_scene(&screen.scene, &mut encoder, target, self.size);
+            }
+            Some(previous_scene) => {
+                let targets = match self.transition_targets.take() {
+                    Some(targets) if targets.size == self.size => targets,
+                    _ => pipeline.create_transition_targets(self.size),
+                };
+
+                pipeline.update_transition_globals(
+                    &targets,
+                    screen.transition_progress(),
+                    &mut encoder,
+                );
+                screen.render_scene(previous_scene, &mut encoder, &targets.from, self.size);
+                screen.render_scene(&screen.scene, &mut encoder, &targets.to, self.size);
+
+                {
+                    let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                            attachment: target,
+                            resolve_target: None,
+                            load_op: wgpu::LoadOp::Clear,
+                            store_op: wgpu::StoreOp::Store,
+                            clear_color: wgpu::Color::BLACK,
+                        }],
+                        depth_stencil_attachment: None,
+                    });
+                    rpass.set_pipeline(&pipeline.transition_pipeline);
+                    rpass.set_bind_group(0, &targets.bind_group, &[]);
+                    rpass.draw(0..6, 0..1);
+                }
+
+                self.transition_targets = Some(targets);
+            }
+        }
+
+        if let (Some(post_pipeline), Some(targets)) = (&screen.post_pipeline, &post_targets) {
+            pipeline.update_post_globals(
+                targets,
+                screen.started_at.elapsed().as_secs_f32(),
+                &mut encoder,
+            );
+
             let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
                     attachment: &frame.view,
@@ -513,11 +3723,220 @@ impl PipelineWindow {
                 }],
                 depth_stencil_attachment: None,
             });
-            rpass.set_pipeline(&pipeline.render_pipeline);
-            rpass.set_bind_group(0, &pipeline.bind_group, &[]);
+            rpass.set_pipeline(post_pipeline);
+            rpass.set_bind_group(0, &targets.bind_group, &[]);
             rpass.draw(0..6, 0..1);
         }
+        self.post_targets = post_targets;
 
         pipeline.queue.submit(&[encoder.finish()]);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_with_their_gutter() {
+        let limits = TextureLimits::default();
+        assert_eq!(limits.tile_grid((4096, 4096)), (1, 1));
+        assert_eq!(limits.tile_grid((4097, 100)), (2, 1));
+        // Each tile holds 4094 texels of the frame along with its gutter.
+        assert_eq!(limits.tile_grid((8188, 8189)), (2, 3));
+        assert_eq!(tile_texture_size((4096, 100), (1, 1)), (4096, 100));
+        assert_eq!(tile_texture_size((8188, 100), (2, 1)), (4096, 102));
+    }
+
+    #[test]
+    fn capacity_within_the_limits() {
+        let limits = TextureLimits::default();
+        let video = Texels::Yuv(y4m::Format {
+            width: 100,
+            height: 100,
+            chroma: (50, 50),
+            full_range: false,
+            bt709: false,
+        });
+        assert_eq!(frame_capacity((100, 100), Texels::Rgba, limits), 4 * 256);
+        assert_eq!(
+            frame_capacity((100, 100), Texels::Rgba, limits.sampled_by(true)),
+            256
+        );
+        assert_eq!(frame_capacity((100, 100), video, limits), 256);
+        assert_eq!(frame_capacity((5000, 100), video, limits), 0);
+        assert_eq!(
+            frame_capacity((5000, 5000), Texels::Rgba, limits),
+            4 * 256 / 4
+        );
+        let raised = TextureLimits {
+            dimension: 8192,
+            array_layers: 2048,
+            arrays: FRAME_ARRAYS,
+        };
+        assert_eq!(frame_capacity((5000, 5000), Texels::Rgba, raised), 4 * 2048);
+        // The palettes of all the layers are rows of a single texture.
+        assert_eq!(frame_capacity((100, 100), Texels::Indexed, limits), 4 * 256);
+        assert_eq!(
+            frame_capacity((100, 100), Texels::Indexed, raised),
+            4 * 2048
+        );
+        let deeper = TextureLimits {
+            dimension: 4096,
+            ..raised
+        };
+        assert_eq!(frame_capacity((100, 100), Texels::Rgba, deeper), 4 * 2048);
+        assert_eq!(frame_capacity((100, 100), Texels::Indexed, deeper), 4096);
+    }
+
+    #[test]
+    fn crop_with_a_gutter() {
+        // 3x2 texels numbered in order, one byte each.
+        let data = [0, 1, 2, 3, 4, 5];
+        assert_eq!(crop(&data, (3, 2), 1, (1, 0), (2, 1), 0), [1, 2]);
+        // Texels past the edges repeat the ones on them.
+        assert_eq!(
+            crop(&data, (3, 2), 1, (1, 0), (2, 1), 1),
+            [0, 1, 2, 2, 0, 1, 2, 2, 3, 4, 5, 5]
+        );
+    }
+
+    #[test]
+    fn memory_of_a_layer() {
+        assert_eq!(layer_memory((100, 50), Texels::Rgba), 20_000);
+        assert_eq!(
+            layer_memory((100, 50), Texels::Indexed),
+            5_000 + 4 * u64::from(PALETTE_SIZE)
+        );
+        let format = y4m::Format {
+            width: 100,
+            height: 50,
+            chroma: (50, 25),
+            full_range: false,
+            bt709: false,
+        };
+        assert_eq!(
+            layer_memory((100, 50), Texels::Yuv(format)),
+            5_000 + 2 * 1_250
+        );
+    }
+
+    #[test]
+    fn downscaled_within_the_budget() {
+        let limits = TextureLimits::default();
+        let target = |fit| Target {
+            output: (1920, 1080),
+            fit,
+        };
+        // 10 frames of 1000x1000 take 40 MB as colors.
+        let cases = [
+            (FitMode::Fill, 10_000_000, Some((500, 500))),
+            (FitMode::Fit, 22_500_000, Some((750, 750))),
+            (FitMode::Fill, 40_000_000, None),
+            (FitMode::Fill, 9_000_000, None),
+            (FitMode::Center, 10_000_000, None),
+            (FitMode::Tile, 10_000_000, None),
+        ];
+        for (fit, available, size) in &cases {
+            let downscaled =
+                downscaled_target(target(*fit), (1000, 1000), (10, *available), limits);
+            let expected = size.map(|output| Target { output, fit: *fit });
+            assert_eq!(downscaled, expected, "{:?} within {}", fit, available);
+        }
+        // Downscaled frames would still be too many for the textures.
+        assert_eq!(
+            downscaled_target(
+                target(FitMode::Fill),
+                (100, 100),
+                (2000, 40_000_000),
+                limits
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn scenes_prepared_for_every_screen() {
+        let path =
+            std::env::temp_dir().join(format!("swaynimated-prepared-{}.png", std::process::id()));
+        RgbaImage::from_pixel(8, 8, image::Rgba([255, 0, 0, 255]))
+            .save(&path)
+            .unwrap();
+        let screen = |id, output| ScreenTarget {
+            id,
+            target: Target {
+                output,
+                fit: FitMode::Fit,
+            },
+            backdrop: false,
+            fps: 60,
+        };
+        let options = SceneOptions {
+            playback: PlaybackMode::Loop,
+            interpolation: None,
+            motion_compensation: false,
+            stream: None,
+            palette: false,
+            limits: TextureLimits::default(),
+            backdrop: false,
+            staging_memory: 1 << 20,
+            video_memory: 1 << 30,
+            memory_usage: MemoryUsage::default(),
+        };
+        let displayed = Displayed {
+            screens: vec![screen(0, (4, 4)), screen(1, (16, 16))],
+            swap_chain_memory: 0,
+        };
+        let loader = SceneLoader {
+            options: options.clone(),
+            displayed: Arc::new(Mutex::new(displayed)),
+        };
+
+        // Frames are resampled for every screen, and counted until the
+        // scenes are created.
+        let prepared = loader.prepare(&Source::from_path(&path).unwrap()).unwrap();
+        let sizes: Vec<_> = prepared
+            .scenes
+            .iter()
+            .map(|(_, scene)| {
+                let frames = scene.as_ref().unwrap().decoded.frames.as_ref().unwrap();
+                (frames.width, frames.height)
+            })
+            .collect();
+        assert_eq!(sizes, vec![(4, 4), (8, 8)]);
+        assert_eq!(options.memory_usage.total(), 4 * 4 * 4 + 8 * 8 * 4);
+        drop(prepared);
+        assert_eq!(options.memory_usage.total(), 0);
+
+        // Fragments take no frames.
+        let prepared = loader
+            .prepare(&Source::from_path(Path::new("solid:#ff0000")).unwrap())
+            .unwrap();
+        assert_eq!(prepared.scenes.len(), 2);
+        assert_eq!(options.memory_usage.total(), 0);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn memory_released_when_compacted_and_dropped() {
+        let usage = MemoryUsage::default();
+        let mut scene = usage.allocate(100);
+        let targets = usage.allocate(20);
+        scene.grow(30);
+        assert_eq!(usage.total(), 150);
+        // Compacted frames give back the layers they no longer use.
+        scene.shrink(50);
+        assert_eq!(usage.total(), 100);
+        scene.shrink(500);
+        assert_eq!(usage.total(), 20);
+        scene.grow(80);
+        assert_eq!(usage.total(), 100);
+        // Replaced scenes are left out until they are dropped.
+        scene.release();
+        assert_eq!(usage.total(), 20);
+        drop(scene);
+        assert_eq!(usage.total(), 20);
+        drop(targets);
+        assert_eq!(usage.total(), 0);
+    }
+}
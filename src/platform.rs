@@ -3,16 +3,25 @@ use wayland_client::{
     sys::client::wl_proxy,
     GlobalManager, Proxy,
 };
+use wayland_protocols::unstable::xdg_output::v1::client::{
+    zxdg_output_manager_v1::ZxdgOutputManagerV1, zxdg_output_v1,
+};
 use wayland_protocols::wlr::unstable::layer_shell::v1::client::{
     zwlr_layer_shell_v1, zwlr_layer_surface_v1,
 };
 
-use crate::pipeline::PipelineWindow;
+use crate::{
+    config::Layer,
+    ipc::{Command, Reply},
+    pipeline::PipelineWindow,
+    pipeline::Prepared,
+};
 
+use std::{cell::RefCell, rc::Rc};
 use winit::dpi::LogicalSize;
-use winit::window::WindowId;
+use winit::window::{Window, WindowId};
 use winit::{
-    event_loop::EventLoop,
+    event_loop::EventLoopProxy,
     platform::unix::{MonitorHandleExtUnix, WindowExtUnix},
 };
 
@@ -22,12 +31,61 @@ pub enum CustomEvent {
         window_id: WindowId,
         new_size: LogicalSize,
     },
+    /// Commands from the socket come with where to reply.
+    Command(Command, Option<Reply>),
+    /// A source decoded off the event loop, its scenes prepared, ready to be
+    /// displayed.
+    SourcePrepared(Prepared),
+}
+
+/// Connector name of the output, such as `DP-1`, as advertised by
+/// xdg-output. `None` when the compositor does not support it.
+pub fn output_name(
+    monitor_handle: &winit::monitor::MonitorHandle,
+    window: &Window,
+) -> Option<String> {
+    let display_ptr = window.wayland_display()? as _;
+    let display: WlDisplay = unsafe { Proxy::from_c_ptr(display_ptr) }.into();
+
+    let output_ptr = monitor_handle.wayland_output()? as _;
+    let output: WlOutput = unsafe { Proxy::from_c_ptr(output_ptr) }.into();
+
+    let manager = GlobalManager::new(&display);
+
+    unsafe { (wayland_sys::client::WAYLAND_CLIENT_HANDLE.wl_display_roundtrip)(display_ptr as _) };
+
+    // The name event only exists since the second version.
+    let output_manager: ZxdgOutputManagerV1 = manager
+        .instantiate_range(2, 3, |p| p.implement_dummy())
+        .ok()?;
+
+    let name = Rc::new(RefCell::new(None));
+    let name_handle = name.clone();
+    let xdg_output = output_manager
+        .get_xdg_output(&output, move |p| {
+            p.implement_closure(
+                move |e, _| {
+                    if let zxdg_output_v1::Event::Name { name } = e {
+                        *name_handle.borrow_mut() = Some(name);
+                    }
+                },
+                (),
+            )
+        })
+        .ok()?;
+
+    unsafe { (wayland_sys::client::WAYLAND_CLIENT_HANDLE.wl_display_roundtrip)(display_ptr as _) };
+
+    xdg_output.destroy();
+    output_manager.destroy();
+    name.replace(None)
 }
 
 pub fn put_to_background(
     monitor_handle: &winit::monitor::MonitorHandle,
-    event_loop: &EventLoop<CustomEvent>,
+    event_proxy: EventLoopProxy<CustomEvent>,
     pipeline_window: &PipelineWindow,
+    layer: Layer,
 ) {
     let sfc: WlSurface = match pipeline_window.window.wayland_surface() {
         Some(wayland_surface) => unsafe {
@@ -52,13 +110,18 @@ pub fn put_to_background(
         .unwrap();
 
     let window_id = pipeline_window.window.id();
-    let event_proxy = event_loop.create_proxy();
+    let layer = match layer {
+        Layer::Background => zwlr_layer_shell_v1::Layer::Background,
+        Layer::Bottom => zwlr_layer_shell_v1::Layer::Bottom,
+        Layer::Top => zwlr_layer_shell_v1::Layer::Top,
+        Layer::Overlay => zwlr_layer_shell_v1::Layer::Overlay,
+    };
 
     let layer_surface = shell
         .get_layer_surface(
             &sfc,
             Some(&output),
-            zwlr_layer_shell_v1::Layer::Background,
+            layer,
             "wallpaper".into(),
             move |p| {
                 p.implement_closure(
@@ -0,0 +1,241 @@
+use crate::random::Random;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlaybackMode {
+    Loop,
+    PingPong,
+    Reverse,
+    Once,
+    Random,
+}
+
+impl FromStr for PlaybackMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "loop" => Ok(PlaybackMode::Loop),
+            "ping-pong" | "boomerang" => Ok(PlaybackMode::PingPong),
+            "reverse" => Ok(PlaybackMode::Reverse),
+            "once" => Ok(PlaybackMode::Once),
+            "random" => Ok(PlaybackMode::Random),
+            _ => Err(format!(
+                "unknown playback mode '{}' (expected loop, ping-pong, reverse, once or random)",
+                s
+            )),
+        }
+    }
+}
+
+/// Keeps track of the two frames currently blended together and of how far
+/// the blend went, so the shader no longer has to assume that the next frame
+/// is always `position + 1`.
+#[derive(Clone)]
+pub struct Playback {
+    mode: PlaybackMode,
+    total_frame: u32,
+    repeat: Option<u32>,
+    loops_left: Option<u32>,
+    forward: bool,
+    previous: u32,
+    next: u32,
+    mix_value: f32,
+    random: Random,
+}
+
+impl Playback {
+    /// `repeat` is the number of extra loops done before holding the last
+    /// frame in `Once` mode (the GIF loop count, 0 when there is none), `None`
+    /// looping forever as a GIF loop count of 0 does.
+    pub fn new(mode: PlaybackMode, total_frame: u32, repeat: Option<u32>) -> Self {
+        let mut playback = Self {
+            mode,
+            total_frame: total_frame.max(1),
+            repeat,
+            loops_left: repeat,
+            forward: true,
+            previous: 0,
+            next: 0,
+            mix_value: 0.0,
+            random: Random::new(),
+        };
+        playback.restart();
+        playback
+    }
+
+    pub fn set_mode(&mut self, mode: PlaybackMode) {
+        self.mode = mode;
+        self.restart();
+    }
+
+    pub fn previous(&self) -> u32 {
+        self.previous
+    }
+
+    pub fn next(&self) -> u32 {
+        self.next
+    }
+
+    pub fn mix_value(&self) -> f32 {
+        self.mix_value
+    }
+
+    /// Whether frames are played from the first one on, one after the other,
+    /// so that playing can start before the last ones are loaded.
+    pub fn in_order(&self) -> bool {
+        matches!(
+            self.mode,
+            PlaybackMode::Loop | PlaybackMode::PingPong | PlaybackMode::Once
+        )
+    }
+
+    /// Position as a fractional frame index, as the shaders used to get it.
+    pub fn position(&self) -> f32 {
+        self.previous as f32 + self.mix_value
+    }
+
+    pub fn advance(&mut self, increment: f32) {
+        if self.previous == self.next {
+            // Holding a frame, either because there is a single one or
+            // because a `Once` playback reached its end.
+            return;
+        }
+
+        self.mix_value += increment;
+        while self.mix_value >= 1.0 {
+            self.mix_value -= 1.0;
+            self.previous = self.next;
+            self.next = self.successor(self.previous);
+
+            if self.previous == self.next {
+                self.mix_value = 0.0;
+                break;
+            }
+        }
+    }
+
+    fn restart(&mut self) {
+        self.loops_left = self.repeat;
+        self.forward = true;
+        self.mix_value = 0.0;
+        self.previous = match self.mode {
+            PlaybackMode::Reverse => self.total_frame - 1,
+            _ => 0,
+        };
+        self.next = self.successor(self.previous);
+    }
+
+    fn successor(&mut self, frame: u32) -> u32 {
+        let total = self.total_frame;
+        if total == 1 {
+            return 0;
+        }
+
+        match self.mode {
+            PlaybackMode::Loop => (frame + 1) % total,
+            PlaybackMode::Reverse => (frame + total - 1) % total,
+            PlaybackMode::PingPong => {
+                if self.forward && frame + 1 == total {
+                    self.forward = false;
+                } else if !self.forward && frame == 0 {
+                    self.forward = true;
+                }
+
+                if self.forward {
+                    frame + 1
+                } else {
+                    frame - 1
+                }
+            }
+            PlaybackMode::Once => {
+                if frame + 1 < total {
+                    return frame + 1;
+                }
+                match &mut self.loops_left {
+                    None => 0,
+                    Some(0) => frame,
+                    Some(loops_left) => {
+                        *loops_left -= 1;
+                        0
+                    }
+                }
+            }
+            PlaybackMode::Random => {
+                // Never pick the current frame again, there would be nothing
+                // to crossfade.
+                let offset = 1 + self.random.below(total as usize - 1) as u32;
+                (frame + offset) % total
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frames shown over `count` frame intervals, the first one included.
+    fn frames(mode: PlaybackMode, total_frame: u32, repeat: Option<u32>, count: usize) -> Vec<u32> {
+        let mut playback = Playback::new(mode, total_frame, repeat);
+        let mut frames = vec![playback.previous()];
+        for _ in 1..count {
+            playback.advance(1.0);
+            frames.push(playback.previous());
+        }
+        frames
+    }
+
+    #[test]
+    fn frame_sequences() {
+        let cases = [
+            (PlaybackMode::Loop, 3, Some(0), vec![0, 1, 2, 0, 1]),
+            (
+                PlaybackMode::PingPong,
+                3,
+                Some(0),
+                vec![0, 1, 2, 1, 0, 1, 2],
+            ),
+            (PlaybackMode::PingPong, 2, Some(0), vec![0, 1, 0, 1, 0]),
+            (PlaybackMode::PingPong, 1, Some(0), vec![0, 0, 0]),
+            (PlaybackMode::Reverse, 3, Some(0), vec![2, 1, 0, 2, 1]),
+            (PlaybackMode::Reverse, 1, Some(0), vec![0, 0, 0]),
+            (PlaybackMode::Once, 3, Some(0), vec![0, 1, 2, 2, 2]),
+            (PlaybackMode::Once, 3, Some(1), vec![0, 1, 2, 0, 1, 2, 2, 2]),
+            (PlaybackMode::Once, 3, None, vec![0, 1, 2, 0, 1, 2, 0]),
+        ];
+        for (mode, total_frame, repeat, expected) in &cases {
+            let frames = frames(*mode, *total_frame, *repeat, expected.len());
+            assert_eq!(&frames, expected, "{:?} of {} frames", mode, total_frame);
+        }
+    }
+
+    #[test]
+    fn once_holds_the_last_frame() {
+        let mut playback = Playback::new(PlaybackMode::Once, 3, Some(0));
+        playback.advance(10.0);
+        assert_eq!((playback.previous(), playback.next()), (2, 2));
+        assert!((playback.position() - 2.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn random_never_repeats_a_frame() {
+        let frames = frames(PlaybackMode::Random, 4, Some(0), 100);
+        assert!(frames.iter().all(|frame| *frame < 4));
+        assert!(
+            frames.windows(2).all(|pair| pair[0] != pair[1]),
+            "{:?}",
+            frames
+        );
+        assert_eq!(self::frames(PlaybackMode::Random, 1, Some(0), 3), [0, 0, 0]);
+    }
+
+    #[test]
+    fn restart_on_a_new_mode() {
+        let mut playback = Playback::new(PlaybackMode::Loop, 3, Some(0));
+        playback.advance(1.5);
+        playback.set_mode(PlaybackMode::Reverse);
+        assert_eq!((playback.previous(), playback.next()), (2, 1));
+        assert!(playback.mix_value().abs() < f32::EPSILON);
+    }
+}
@@ -0,0 +1,109 @@
+use crate::config::FitMode;
+use image::{
+    imageops::{self, FilterType},
+    Rgba, RgbaImage,
+};
+
+/// The largest output displaying a source and how it is laid out there,
+/// which bounds the size frames are useful at.
+#[derive(Debug, Clone, Copy, PartialEq, Hash)]
+pub struct Target {
+    pub output: (u32, u32),
+    pub fit: FitMode,
+}
+
+impl Target {
+    /// Size frames of `size` are resampled to, `None` when they are not
+    /// larger than displayed. Centered and tiled sources are shown at their
+    /// own size.
+    pub fn resized(self, (width, height): (u32, u32)) -> Option<(u32, u32)> {
+        let (output_width, output_height) = self.output;
+        // Outputs whose size is not known yet.
+        if output_width == 0 || output_height == 0 {
+            return None;
+        }
+        let scale = |scale: f64| {
+            (
+                ((f64::from(width) * scale).round() as u32).max(1),
+                ((f64::from(height) * scale).round() as u32).max(1),
+            )
+        };
+        let horizontal = f64::from(output_width) / f64::from(width);
+        let vertical = f64::from(output_height) / f64::from(height);
+
+        let size = match self.fit {
+            FitMode::Stretch => (width.min(output_width), height.min(output_height)),
+            FitMode::Fill => scale(horizontal.max(vertical)),
+            FitMode::Fit => scale(horizontal.min(vertical)),
+            FitMode::Center | FitMode::Tile => return None,
+        };
+        if size.0 < width || size.1 < height {
+            Some((size.0.min(width), size.1.min(height)))
+        } else {
+            None
+        }
+    }
+}
+
+/// Resamples `frame` when it is larger than displayed on `target`.
+pub fn frame(frame: RgbaImage, target: Option<Target>) -> RgbaImage {
+    match target.and_then(|target| target.resized(frame.dimensions())) {
+        Some((width, height)) => resample(&frame, width, height),
+        None => frame,
+    }
+}
+
+/// Fits `frame` in `size`, centered between black borders, for frames of a
+/// directory that do not have the size of the first one.
+pub fn letterbox(frame: RgbaImage, (width, height): (u32, u32)) -> RgbaImage {
+    if frame.dimensions() == (width, height) {
+        return frame;
+    }
+
+    let scale = (f64::from(width) / f64::from(frame.width()))
+        .min(f64::from(height) / f64::from(frame.height()));
+    let fitted_width = ((f64::from(frame.width()) * scale).round() as u32)
+        .max(1)
+        .min(width);
+    let fitted_height = ((f64::from(frame.height()) * scale).round() as u32)
+        .max(1)
+        .min(height);
+    let fitted = resample(&frame, fitted_width, fitted_height);
+
+    let mut letterboxed = RgbaImage::from_pixel(width, height, Rgba([0, 0, 0, 255]));
+    imageops::overlay(
+        &mut letterboxed,
+        &fitted,
+        (width - fitted_width) / 2,
+        (height - fitted_height) / 2,
+    );
+    letterboxed
+}
+
+/// Resamples with premultiplied alpha, so that the colors under transparent
+/// pixels do not bleed into the others.
+fn resample(frame: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+    if frame.pixels().all(|pixel| pixel[3] == 255) {
+        return imageops::resize(frame, width, height, FilterType::Lanczos3);
+    }
+
+    let mut premultiplied = frame.clone();
+    for pixel in premultiplied.pixels_mut() {
+        let alpha = u16::from(pixel[3]);
+        for channel in 0..3 {
+            pixel[channel] = ((u16::from(pixel[channel]) * alpha + 127) / 255) as u8;
+        }
+    }
+
+    let mut resized = imageops::resize(&premultiplied, width, height, FilterType::Lanczos3);
+    for pixel in resized.pixels_mut() {
+        let alpha = u16::from(pixel[3]);
+        if alpha == 0 {
+            continue;
+        }
+        for channel in 0..3 {
+            pixel[channel] = ((u16::from(pixel[channel]) * 255 + alpha / 2) / alpha).min(255) as u8;
+        }
+    }
+    resized
+}
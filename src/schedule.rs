@@ -0,0 +1,476 @@
+use crate::{
+    pipeline::{Prepared, SceneLoader},
+    source::{Decoded, Source, SourceError},
+};
+use log::{info, warn};
+use std::{
+    error::Error,
+    f64::consts::PI,
+    fs,
+    path::Path,
+    str::FromStr,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+const MINUTES_PER_DAY: f64 = 24.0 * 60.0;
+
+/// Delay before decoding again an entry that failed to load.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// When a schedule entry starts, possibly relative to the sun.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TimeOfDay {
+    /// Minutes since midnight.
+    Clock(f64),
+    /// Offset in minutes from the sunrise.
+    Sunrise(f64),
+    /// Offset in minutes from the sunset.
+    Sunset(f64),
+}
+
+/// `H:MM` in minutes, less than a day whether it is a time or an offset.
+fn parse_hours_minutes(s: &str) -> Result<f64, String> {
+    let invalid = || format!("invalid time '{}'", s);
+    let mut parts = s.splitn(2, ':');
+    let hours: u32 = parts
+        .next()
+        .and_then(|h| h.parse().ok())
+        .ok_or_else(invalid)?;
+    let minutes: u32 = match parts.next() {
+        Some(m) => m.parse().map_err(|_| invalid())?,
+        None => 0,
+    };
+
+    if minutes >= 60 {
+        return Err(invalid());
+    }
+    match hours
+        .checked_mul(60)
+        .and_then(|hours| hours.checked_add(minutes))
+    {
+        Some(total) if f64::from(total) < MINUTES_PER_DAY => Ok(f64::from(total)),
+        _ => Err(invalid()),
+    }
+}
+
+impl FromStr for TimeOfDay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        for (event, sunrise) in &[("sunrise", true), ("sunset", false)] {
+            if !s.starts_with(event) {
+                continue;
+            }
+
+            let offset = &s[event.len()..];
+            let offset = if offset.is_empty() {
+                0.0
+            } else if offset.starts_with('+') {
+                parse_hours_minutes(&offset[1..])?
+            } else if offset.starts_with('-') {
+                -parse_hours_minutes(&offset[1..])?
+            } else {
+                return Err(format!("invalid time '{}'", s));
+            };
+
+            return Ok(if *sunrise {
+                TimeOfDay::Sunrise(offset)
+            } else {
+                TimeOfDay::Sunset(offset)
+            });
+        }
+
+        parse_hours_minutes(s).map(TimeOfDay::Clock)
+    }
+}
+
+struct Entry {
+    at: TimeOfDay,
+    source: Source,
+}
+
+/// Local time, as needed to resolve schedule entries.
+struct Now {
+    day_of_year: u32,
+    minutes: f64,
+    /// Offset from UTC in minutes.
+    utc_offset: f64,
+}
+
+impl Now {
+    fn get() -> Self {
+        unsafe {
+            let time = libc::time(std::ptr::null_mut());
+            let mut tm: libc::tm = std::mem::zeroed();
+            libc::localtime_r(&time, &mut tm);
+
+            Self {
+                day_of_year: tm.tm_yday as u32 + 1,
+                minutes: f64::from(tm.tm_hour * 60 + tm.tm_min) + f64::from(tm.tm_sec) / 60.0,
+                utc_offset: tm.tm_gmtoff as f64 / 60.0,
+            }
+        }
+    }
+}
+
+/// Sunrise and sunset in minutes since midnight UTC, following the NOAA
+/// approximation. `None` during polar days and nights.
+fn sun_events(day_of_year: u32, latitude: f64, longitude: f64) -> Option<(f64, f64)> {
+    let gamma = 2.0 * PI / 365.0 * (f64::from(day_of_year) - 1.0);
+    let equation_of_time = 229.18
+        * (0.000_075 + 0.001_868 * gamma.cos()
+            - 0.032_077 * gamma.sin()
+            - 0.014_615 * (2.0 * gamma).cos()
+            - 0.040_849 * (2.0 * gamma).sin());
+    let declination = 0.006_918 - 0.399_912 * gamma.cos() + 0.070_257 * gamma.sin()
+        - 0.006_758 * (2.0 * gamma).cos()
+        + 0.000_907 * (2.0 * gamma).sin()
+        - 0.002_697 * (3.0 * gamma).cos()
+        + 0.001_48 * (3.0 * gamma).sin();
+
+    let latitude = latitude.to_radians();
+    let cos_hour_angle = 90.833_f64.to_radians().cos() / (latitude.cos() * declination.cos())
+        - latitude.tan() * declination.tan();
+    if cos_hour_angle.abs() > 1.0 {
+        return None;
+    }
+
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+    let sunrise = 720.0 - 4.0 * (longitude + hour_angle) - equation_of_time;
+    let sunset = 720.0 - 4.0 * (longitude - hour_angle) - equation_of_time;
+    Some((sunrise, sunset))
+}
+
+/// Switches between sources depending on the time of day.
+///
+/// Like the slideshow, the upcoming entry is decoded and its scenes prepared
+/// in the background so that it is ready when its time comes.
+pub struct Schedule {
+    entries: Vec<Entry>,
+    location: Option<(f64, f64)>,
+    transition: Duration,
+    current: usize,
+    /// Prepares the entries once there are screens to display them.
+    loader: Option<SceneLoader>,
+    preload: Option<(usize, Receiver<Result<Prepared, SourceError>>)>,
+    /// When the active entry failed to load, it is decoded again then.
+    retry_at: Option<Instant>,
+}
+
+impl Schedule {
+    /// Reads a schedule file, made of `<time> <source>` lines where the time
+    /// is either `HH:MM`, `sunrise` or `sunset`, the latter two optionally
+    /// followed by an offset such as `sunset-0:30`. Relative sources are
+    /// resolved from the directory of the schedule file.
+    pub fn load(
+        path: &Path,
+        location: Option<(f64, f64)>,
+        transition: Duration,
+    ) -> Result<Self, Box<dyn Error>> {
+        let base = path.parent().unwrap_or_else(|| Path::new("."));
+        let mut entries = Vec::new();
+
+        for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, char::is_whitespace);
+            let at: TimeOfDay = parts.next().unwrap_or("").parse()?;
+            let source_path = base.join(parts.next().unwrap_or("").trim());
+            let source = Source::from_path(&source_path).ok_or_else(|| {
+                format!(
+                    "{}:{}: unsupported source {}",
+                    path.display(),
+                    number + 1,
+                    source_path.display()
+                )
+            })?;
+
+            match at {
+                TimeOfDay::Sunrise(_) | TimeOfDay::Sunset(_) if location.is_none() => {
+                    return Err(format!(
+                        "{}:{}: sunrise and sunset need a latitude and a longitude",
+                        path.display(),
+                        number + 1
+                    )
+                    .into());
+                }
+                _ => entries.push(Entry { at, source }),
+            }
+        }
+
+        if entries.is_empty() {
+            return Err(format!("{} has no entry", path.display()).into());
+        }
+
+        let mut schedule = Self {
+            entries,
+            location,
+            transition,
+            current: 0,
+            loader: None,
+            preload: None,
+            retry_at: None,
+        };
+        schedule.current = schedule.active_entry(&Now::get()).unwrap_or(0);
+        Ok(schedule)
+    }
+
+    /// Minutes since local midnight at which the entry starts today, `None`
+    /// when it is relative to a sunrise or a sunset that does not happen.
+    fn resolve(&self, at: TimeOfDay, now: &Now) -> Option<f64> {
+        let minutes = match at {
+            TimeOfDay::Clock(minutes) => return Some(minutes),
+            TimeOfDay::Sunrise(offset) | TimeOfDay::Sunset(offset) => {
+                let (latitude, longitude) = self.location?;
+                let (sunrise, sunset) = sun_events(now.day_of_year, latitude, longitude)?;
+                let event = if let TimeOfDay::Sunrise(_) = at {
+                    sunrise
+                } else {
+                    sunset
+                };
+                event + now.utc_offset + offset
+            }
+        };
+
+        Some(((minutes % MINUTES_PER_DAY) + MINUTES_PER_DAY) % MINUTES_PER_DAY)
+    }
+
+    /// The entry that started last at `now`, wrapping around midnight.
+    fn active_entry(&self, now: &Now) -> Option<usize> {
+        let starts: Vec<_> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| self.resolve(entry.at, now).map(|start| (i, start)))
+            .collect();
+
+        let started = starts
+            .iter()
+            .filter(|(_, start)| *start <= now.minutes)
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        let latest = starts.iter().max_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+        started.or(latest).map(|(i, _)| *i)
+    }
+
+    /// The entry following the current one in time, on the day of `now`.
+    fn upcoming_entry(&self, now: &Now) -> Option<usize> {
+        let current_start = self.resolve(self.entries[self.current].at, now)?;
+        let starts: Vec<_> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != self.current)
+            .filter_map(|(i, entry)| self.resolve(entry.at, now).map(|start| (i, start)))
+            .collect();
+
+        // Time left before each entry starts, counted from the current one.
+        starts
+            .iter()
+            .min_by(|a, b| {
+                let a = (a.1 - current_start + MINUTES_PER_DAY) % MINUTES_PER_DAY;
+                let b = (b.1 - current_start + MINUTES_PER_DAY) % MINUTES_PER_DAY;
+                a.partial_cmp(&b).unwrap()
+            })
+            .map(|(i, _)| *i)
+    }
+
+    /// Source of the entry being displayed.
+    pub fn current(&self) -> &Source {
+        &self.entries[self.current].source
+    }
+
+    pub fn first(&mut self) -> Result<Decoded, Box<dyn Error>> {
+        self.entries[self.current]
+            .source
+            .decode()
+            .map_err(|e| e as Box<dyn Error>)
+    }
+
+    /// Preloads the entries for the screens of `loader` from now on.
+    pub fn start_preloading(&mut self, loader: SceneLoader) {
+        self.loader = Some(loader);
+        self.start_preload();
+    }
+
+    /// Returns the source to switch to and the duration of the transition
+    /// once an entry starts. An entry that fails to load is decoded again
+    /// after `RETRY_DELAY`, the current one being displayed meanwhile.
+    pub fn poll(&mut self) -> Option<(Prepared, Duration)> {
+        let active = self.active_entry(&Now::get())?;
+        if active == self.current {
+            return None;
+        }
+
+        let result = match &self.preload {
+            Some((index, preload)) if *index == active => match preload.try_recv() {
+                Ok(result) => result,
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => Err("decoding thread panicked".into()),
+            },
+            _ => {
+                if self
+                    .retry_at
+                    .map_or(false, |retry_at| Instant::now() < retry_at)
+                {
+                    return None;
+                }
+                // The sun moved past another entry, the clock jumped, or the
+                // entry failed to load.
+                self.preload_entry(active);
+                return None;
+            }
+        };
+
+        match result {
+            Ok(prepared) => {
+                info!("Schedule: switching to {:?}", self.entries[active].source);
+                self.current = active;
+                self.retry_at = None;
+                self.start_preload();
+                Some((prepared, self.transition))
+            }
+            Err(e) => {
+                warn!(
+                    "Schedule: cannot load {:?}: {}",
+                    self.entries[active].source, e
+                );
+                self.preload = None;
+                self.retry_at = Some(Instant::now() + RETRY_DELAY);
+                None
+            }
+        }
+    }
+
+    fn start_preload(&mut self) {
+        match self.upcoming_entry(&Now::get()) {
+            Some(upcoming) => self.preload_entry(upcoming),
+            None => self.preload = None,
+        }
+    }
+
+    fn preload_entry(&mut self, index: usize) {
+        let loader = match &self.loader {
+            Some(loader) => loader.clone(),
+            None => return,
+        };
+        let source = self.entries[index].source.clone();
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            // The receiver is gone when the schedule moved on meanwhile.
+            let _ = sender.send(loader.prepare(&source));
+        });
+        self.preload = Some((index, receiver));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(times: &[&str]) -> Schedule {
+        let entries = times
+            .iter()
+            .map(|at| Entry {
+                at: at.parse().unwrap(),
+                source: Source::from_path(Path::new("solid:#000000")).unwrap(),
+            })
+            .collect();
+        Schedule {
+            entries,
+            location: None,
+            transition: Duration::from_secs(0),
+            current: 0,
+            loader: None,
+            preload: None,
+            retry_at: None,
+        }
+    }
+
+    fn at(minutes: f64) -> Now {
+        Now {
+            day_of_year: 1,
+            minutes,
+            utc_offset: 0.0,
+        }
+    }
+
+    #[test]
+    fn parse_time_of_day() {
+        let cases = [
+            ("7:30", Some(TimeOfDay::Clock(450.0))),
+            ("0:00", Some(TimeOfDay::Clock(0.0))),
+            ("23:59", Some(TimeOfDay::Clock(1439.0))),
+            ("7", Some(TimeOfDay::Clock(420.0))),
+            ("sunrise", Some(TimeOfDay::Sunrise(0.0))),
+            ("sunset-0:30", Some(TimeOfDay::Sunset(-30.0))),
+            ("sunrise+1:15", Some(TimeOfDay::Sunrise(75.0))),
+            ("24:00", None),
+            ("7:60", None),
+            ("7:", None),
+            ("-7:00", None),
+            ("sunset~1:00", None),
+            ("sunset+24:00", None),
+            ("sunset+99999999:00", None),
+            ("noon", None),
+        ];
+        for (s, expected) in &cases {
+            assert_eq!(s.parse::<TimeOfDay>().ok(), *expected, "{}", s);
+        }
+    }
+
+    #[test]
+    fn sun_events_in_paris() {
+        // June 21st, sunrise at 3:46 and sunset at 19:58 UTC.
+        let (sunrise, sunset) = sun_events(172, 48.8566, 2.3522).unwrap();
+        assert!((sunrise - 226.0).abs() < 2.0, "{}", sunrise);
+        assert!((sunset - 1198.0).abs() < 2.0, "{}", sunset);
+    }
+
+    #[test]
+    fn no_sun_events_in_polar_days_and_nights() {
+        assert_eq!(sun_events(172, 69.65, 18.96), None);
+        assert_eq!(sun_events(355, 69.65, 18.96), None);
+    }
+
+    #[test]
+    fn entries_wrap_around_midnight() {
+        let mut schedule = schedule(&["22:00", "6:00", "12:00"]);
+        let cases = [(60.0, 0), (360.0, 1), (719.0, 1), (720.0, 2), (1380.0, 0)];
+        for (minutes, active) in &cases {
+            assert_eq!(
+                schedule.active_entry(&at(*minutes)),
+                Some(*active),
+                "{}",
+                minutes
+            );
+        }
+
+        let now = at(1380.0);
+        for (current, upcoming) in &[(0, 1), (1, 2), (2, 0)] {
+            schedule.current = *current;
+            assert_eq!(
+                schedule.upcoming_entry(&now),
+                Some(*upcoming),
+                "{}",
+                current
+            );
+        }
+    }
+
+    #[test]
+    fn sun_entries_need_the_sun() {
+        let mut schedule = schedule(&["sunrise", "12:00"]);
+        assert_eq!(schedule.active_entry(&at(60.0)), Some(1));
+        schedule.location = Some((69.65, 18.96));
+        assert_eq!(schedule.active_entry(&at(60.0)), Some(1));
+        schedule.location = Some((0.0, 0.0));
+        assert_eq!(schedule.active_entry(&at(600.0)), Some(0));
+    }
+}
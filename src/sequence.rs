@@ -0,0 +1,206 @@
+use crate::source::SourceError;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Frames named with a printf-style number, as `renders/frame_%04d.png`,
+/// optionally followed by `:first-last` and `:step`.
+#[derive(Debug)]
+pub struct Pattern {
+    prefix: String,
+    suffix: String,
+    /// Minimum number of digits, padded with zeros.
+    digits: usize,
+    range: Option<(u32, u32)>,
+    step: u32,
+}
+
+impl Pattern {
+    /// `None` when `spec` has no `%d` conversion.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let start = spec.find('%')?;
+        let (prefix, conversion) = (&spec[..start], &spec[start + 1..]);
+        let end = conversion.find('d')?;
+        let digits = match &conversion[..end] {
+            "" => 0,
+            width if width.starts_with('0') => width.parse().ok()?,
+            _ => return None,
+        };
+
+        let mut parts = conversion[end + 1..].splitn(3, ':');
+        let suffix = parts.next()?;
+        let range = match parts.next() {
+            Some(range) => {
+                let mut bounds = range.splitn(2, '-');
+                let first: u32 = bounds.next()?.trim().parse().ok()?;
+                let last: u32 = bounds.next()?.trim().parse().ok()?;
+                if first > last {
+                    return None;
+                }
+                Some((first, last))
+            }
+            None => None,
+        };
+        let step = match parts.next() {
+            Some(step) => step.trim().parse().ok().filter(|step| *step > 0)?,
+            None => 1,
+        };
+
+        Some(Self {
+            prefix: prefix.to_string(),
+            suffix: suffix.to_string(),
+            digits,
+            range,
+            step,
+        })
+    }
+
+    fn path(&self, number: u32) -> PathBuf {
+        PathBuf::from(format!(
+            "{}{:0digits$}{}",
+            self.prefix,
+            number,
+            self.suffix,
+            digits = self.digits
+        ))
+    }
+
+    /// Every frame of the range, or without one the frames from 0 or 1 until
+    /// one is missing.
+    pub fn paths(&self) -> Result<Vec<PathBuf>, SourceError> {
+        let step = self.step as usize;
+        if let Some((first, last)) = self.range {
+            return Ok((first..=last)
+                .step_by(step)
+                .map(|number| self.path(number))
+                .collect());
+        }
+
+        let first = if self.path(0).is_file() { 0 } else { 1 };
+        let paths: Vec<_> = (first..)
+            .step_by(step)
+            .map(|number| self.path(number))
+            .take_while(|path| path.is_file())
+            .collect();
+        if paths.is_empty() {
+            return Err(format!("no frame named {}", self.path(first).display()).into());
+        }
+        Ok(paths)
+    }
+}
+
+/// Frame lists are text files with this extension.
+pub fn is_list(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |extension| extension == "list")
+}
+
+/// Images listed one per line, relative to the list, each optionally
+/// followed by the number of frames it is shown for. Empty lines and lines
+/// starting with `#` are ignored.
+pub fn read_list(path: &Path) -> Result<Vec<PathBuf>, SourceError> {
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let paths = list_paths(&fs::read_to_string(path)?, dir);
+
+    if paths.is_empty() {
+        return Err(format!("no frame listed in {}", path.display()).into());
+    }
+    Ok(paths)
+}
+
+/// Frames of the lines of a list in `dir`, repeated as many times as shown.
+fn list_paths(list: &str, dir: &Path) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+
+    for line in list.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.rsplitn(2, char::is_whitespace);
+        let (frame, count) = match (parts.next(), parts.next().map(str::trim_end)) {
+            (Some(count), Some(frame)) => count.parse().map_or((line, 1), |count| (frame, count)),
+            _ => (line, 1),
+        };
+        let frame = dir.join(frame);
+        paths.extend(std::iter::repeat(frame).take(count));
+    }
+
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern_with_padding() {
+        let pattern = Pattern::parse("renders/frame_%04d.png").unwrap();
+        assert_eq!(pattern.prefix, "renders/frame_");
+        assert_eq!(pattern.suffix, ".png");
+        assert_eq!(pattern.digits, 4);
+        assert_eq!(pattern.range, None);
+        assert_eq!(pattern.step, 1);
+        assert_eq!(pattern.path(7), PathBuf::from("renders/frame_0007.png"));
+        assert_eq!(
+            pattern.path(12345),
+            PathBuf::from("renders/frame_12345.png")
+        );
+    }
+
+    #[test]
+    fn pattern_with_range_and_step() {
+        let paths = |spec| Pattern::parse(spec).unwrap().paths().unwrap();
+        assert_eq!(
+            paths("f%d.png:1-3"),
+            vec![PathBuf::from("f1.png"), "f2.png".into(), "f3.png".into()]
+        );
+        assert_eq!(
+            paths("f%d.png:1-9:4"),
+            vec![PathBuf::from("f1.png"), "f5.png".into(), "f9.png".into()]
+        );
+        assert_eq!(paths("f%02d.png: 5 - 5"), vec![PathBuf::from("f05.png")]);
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        for spec in &[
+            "frame.png",
+            "frame%s.png",
+            "f%4d.png",
+            "f%d.png:3-1",
+            "f%d.png:1",
+            "f%d.png:a-b",
+            "f%d.png:1-3:0",
+            "f%d.png:1-3:-1",
+        ] {
+            assert!(Pattern::parse(spec).is_none(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn list_repeat_counts() {
+        let list = "a.png\nb.png 3\n\n# c.png 2\n  d e.png 2  \nf.png x\ng.png 0\n";
+        let paths = list_paths(list, Path::new("dir"));
+        let names: Vec<_> = paths.iter().map(|path| path.to_str().unwrap()).collect();
+        assert_eq!(
+            names,
+            [
+                "dir/a.png",
+                "dir/b.png",
+                "dir/b.png",
+                "dir/b.png",
+                "dir/d e.png",
+                "dir/d e.png",
+                "dir/f.png x"
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_list() {
+        assert!(list_paths("# nothing\n\n", Path::new("")).is_empty());
+    }
+}
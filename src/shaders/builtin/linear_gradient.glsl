@@ -0,0 +1,16 @@
+#version 450
+
+layout(location = 0) in vec2 v_TexCoord;
+layout(location = 0) out vec4 outColor;
+
+// PARAMETERS
+
+void main() {
+    // As in CSS, an angle of 0 goes up and 90 degrees goes right, the
+    // corners being at both ends of the gradient.
+    vec2 direction = vec2(sin(angle), -cos(angle));
+    float extent = 0.5 * (abs(direction.x) + abs(direction.y));
+    float t = dot(v_TexCoord - 0.5, direction) / extent * 0.5 + 0.5;
+
+    outColor = toLinear(gradient(t));
+}
@@ -0,0 +1,26 @@
+#version 450
+
+layout(location = 0) in vec2 v_TexCoord;
+layout(location = 0) out vec4 outColor;
+layout(set = 0, binding = 2) uniform Locals {
+    uint total;
+    float position;
+};
+
+// PARAMETERS
+
+// Color of the palette at a phase going from 0 to count, wrapping.
+vec4 paletteAt(float phase) {
+    float wrapped = mod(phase, float(count));
+    int index = int(wrapped);
+    return mix(colors[index], colors[(index + 1) % count], smoothstep(0.0, 1.0, wrapped - float(index)));
+}
+
+void main() {
+    // Position is the time in seconds, each color takes its turn at the top
+    // left corner during the period.
+    float phase = position / period * float(count);
+    float t = 0.5 * (v_TexCoord.x + v_TexCoord.y);
+
+    outColor = toLinear(mix(paletteAt(phase), paletteAt(phase + 1.0), t));
+}
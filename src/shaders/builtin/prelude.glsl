@@ -0,0 +1,14 @@
+// Colors are interpolated in sRGB, like CSS gradients, then converted for the
+// sRGB render target.
+vec4 toLinear(vec4 color) {
+    vec3 low = color.rgb / 12.92;
+    vec3 high = pow((color.rgb + 0.055) / 1.055, vec3(2.4));
+    return vec4(mix(low, high, step(0.04045, color.rgb)), color.a);
+}
+
+// Color at t, from 0 to 1, of the colors evenly spread.
+vec4 gradient(float t) {
+    float scaled = clamp(t, 0.0, 1.0) * float(count - 1);
+    int index = min(int(scaled), count - 2);
+    return mix(colors[index], colors[index + 1], scaled - float(index));
+}
@@ -0,0 +1,13 @@
+#version 450
+
+layout(location = 0) in vec2 v_TexCoord;
+layout(location = 0) out vec4 outColor;
+
+// PARAMETERS
+
+void main() {
+    // From the center to the corners.
+    float t = length(v_TexCoord - 0.5) / length(vec2(0.5));
+
+    outColor = toLinear(gradient(t));
+}
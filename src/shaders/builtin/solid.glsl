@@ -0,0 +1,10 @@
+#version 450
+
+layout(location = 0) in vec2 v_TexCoord;
+layout(location = 0) out vec4 outColor;
+
+// PARAMETERS
+
+void main() {
+    outColor = color;
+}
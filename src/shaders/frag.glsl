@@ -7,22 +7,151 @@ layout(set = 0, binding = 1) uniform texture2DArray t_Color;
 layout(set = 0, binding = 2) uniform Locals {
     uint total;
     float position;
+    uint previousLayer;
+    uint nextLayer;
+    float mixValue;
 };
+layout(set = 0, binding = 3) uniform texture2DArray t_Flow;
+layout(set = 0, binding = 4) uniform sampler s_Flow;
+layout(set = 0, binding = 5) uniform texture2DArray t_Palette;
+layout(set = 0, binding = 6) uniform texture2DArray t_ChromaU;
+layout(set = 0, binding = 7) uniform texture2DArray t_ChromaV;
+layout(set = 0, binding = 8) uniform texture2DArray t_Color1;
+layout(set = 0, binding = 9) uniform texture2DArray t_Color2;
+layout(set = 0, binding = 10) uniform texture2DArray t_Color3;
+
+// INTERPOLATION
+
+// Motion from the previous frame to the next one, in texture coordinates.
+// Layer i of t_Flow goes from frame i to frame i + 1, going backward is
+// approximated by the opposite motion.
+vec2 flowAt(vec2 coord) {
+#ifdef MOTION_COMPENSATION
+    vec2 texel = 1.0 / vec2(textureSize(sampler2DArray(t_Color, s_Color), 0).xy);
+
+    if (nextLayer == (previousLayer + 1) % total) {
+        vec2 flow = texture(sampler2DArray(t_Flow, s_Flow), vec3(coord, previousLayer)).rg;
+        return (flow * 255.0 - 128.0) * texel;
+    }
+
+    if (previousLayer == (nextLayer + 1) % total) {
+        vec2 flow = texture(sampler2DArray(t_Flow, s_Flow), vec3(coord, nextLayer)).rg;
+        return -(flow * 255.0 - 128.0) * texel;
+    }
+#endif
+
+    return vec2(0.0);
+}
+
+#ifdef YUV
+// Video colors are gamma encoded, the target expects linear ones.
+vec3 srgbToLinear(vec3 color) {
+    return mix(color / 12.92, pow((color + 0.055) / 1.055, vec3(2.4)), step(0.04045, color));
+}
+#endif
+
+// Frames are split across t_Color to t_Color3, layer i being layer i % n of
+// array i / n, n being the number of layers of the first one. Without
+// mipmaps, the arrays can be sampled out of uniform control flow.
+vec4 sampleFrames(sampler s, vec2 coord, uint layer) {
+    uint layers = uint(textureSize(sampler2DArray(t_Color, s), 0).z);
+    vec3 at = vec3(coord, layer % layers);
+    switch (layer / layers) {
+    case 0u: return textureLod(sampler2DArray(t_Color, s), at, 0.0);
+    case 1u: return textureLod(sampler2DArray(t_Color1, s), at, 0.0);
+    case 2u: return textureLod(sampler2DArray(t_Color2, s), at, 0.0);
+    default: return textureLod(sampler2DArray(t_Color3, s), at, 0.0);
+    }
+}
+
+vec4 fetchFrames(ivec2 texel, uint layer) {
+    uint layers = uint(textureSize(sampler2DArray(t_Color, s_Color), 0).z);
+    ivec3 at = ivec3(texel, layer % layers);
+    switch (layer / layers) {
+    case 0u: return texelFetch(sampler2DArray(t_Color, s_Color), at, 0);
+    case 1u: return texelFetch(sampler2DArray(t_Color1, s_Color), at, 0);
+    case 2u: return texelFetch(sampler2DArray(t_Color2, s_Color), at, 0);
+    default: return texelFetch(sampler2DArray(t_Color3, s_Color), at, 0);
+    }
+}
+
+#ifdef PALETTE
+// Palette color of a texel of layer `layer` of the frame arrays, its colors
+// being row `frameLayer` of t_Palette.
+vec4 paletteColor(ivec2 texel, uint layer, uint frameLayer) {
+    ivec2 size = textureSize(sampler2DArray(t_Color, s_Color), 0).xy;
+    float index = fetchFrames(clamp(texel, ivec2(0), size - 1), layer).r;
+    return texelFetch(sampler2DArray(t_Palette, s_Color), ivec3(int(index * 255.0 + 0.5), frameLayer, 0), 0);
+}
+#endif
+
+// Color of a frame. With TILES, frames larger than the textures are cut
+// into a grid of tiles on consecutive layers, TILE_SCALE being their size
+// in tiles. Tiles of TILE_TEXELS are surrounded by TILE_GUTTER texels of
+// their neighbours, so that no seam shows between them.
+// With PALETTE, t_Color holds palette indices, and row i of t_Palette holds
+// the colors of layer i. With YUV, t_Color holds the luma of a video, and
+// t_ChromaU and t_ChromaV hold its chroma.
+vec4 frameColor(vec2 coord, uint layer) {
+    uint frameLayer = layer;
+#ifdef TILES
+    vec2 scaled = coord * TILE_SCALE;
+    uvec2 tile = min(uvec2(scaled), TILES - 1u);
+    coord = (TILE_GUTTER + (scaled - vec2(tile)) * TILE_TEXELS) / (TILE_TEXELS + 2.0 * TILE_GUTTER);
+    layer = layer * TILES.x * TILES.y + tile.y * TILES.x + tile.x;
+#endif
+#if defined(PALETTE)
+    // Indices cannot be filtered, their colors are: the nearest one when
+    // magnified, and the four around blended when minified, like the
+    // sampler does with RGBA frames.
+    vec2 position = coord * vec2(textureSize(sampler2DArray(t_Color, s_Color), 0).xy);
+    vec2 footprint = fwidth(position);
+    if (max(footprint.x, footprint.y) <= 1.0) {
+        return paletteColor(ivec2(position), layer, frameLayer);
+    }
+    vec2 corner = position - 0.5;
+    ivec2 texel = ivec2(floor(corner));
+    vec2 weight = fract(corner);
+    return mix(
+        mix(paletteColor(texel, layer, frameLayer), paletteColor(texel + ivec2(1, 0), layer, frameLayer), weight.x),
+        mix(paletteColor(texel + ivec2(0, 1), layer, frameLayer), paletteColor(texel + ivec2(1, 1), layer, frameLayer), weight.x),
+        weight.y
+    );
+#elif defined(YUV)
+    float y = sampleFrames(s_Flow, coord, layer).r;
+    vec2 uv = vec2(
+        texture(sampler2DArray(t_ChromaU, s_Flow), vec3(coord, frameLayer)).r,
+        texture(sampler2DArray(t_ChromaV, s_Flow), vec3(coord, frameLayer)).r
+    ) - 128.0 / 255.0;
+#ifndef YUV_FULL_RANGE
+    y = (y - 16.0 / 255.0) * 255.0 / 219.0;
+    uv *= 255.0 / 224.0;
+#endif
+#ifdef YUV_BT709
+    vec3 rgb = y + vec3(1.5748 * uv.y, -0.1873 * uv.x - 0.4681 * uv.y, 1.8556 * uv.x);
+#else
+    vec3 rgb = y + vec3(1.402 * uv.y, -0.3441 * uv.x - 0.7141 * uv.y, 1.772 * uv.x);
+#endif
+    return vec4(srgbToLinear(clamp(rgb, 0.0, 1.0)), 1.0);
+#else
+    return sampleFrames(s_Color, coord, layer);
+#endif
+}
+
+// Frames are blended with premultiplied alpha, so that the colors under
+// transparent texels do not bleed into the others.
+vec4 premultiplied(vec4 color) {
+    return vec4(color.rgb * color.a, color.a);
+}
 
 void main() {
-    float mixValue = position - floor(position);
-    uint previousLayer = int(floor(position));
-    uint layer = int(mod(ceil(position), total));
-
-    outColor = mix(
-        texture(
-            sampler2DArray(t_Color, s_Color),
-            vec3(v_TexCoord, previousLayer)
-        ),
-        texture(
-            sampler2DArray(t_Color, s_Color),
-            vec3(v_TexCoord, layer)
-        ),
+    vec2 flow = flowAt(v_TexCoord);
+
+    vec4 color = interpolate(
+        premultiplied(frameColor(v_TexCoord - mixValue * flow, previousLayer)),
+        premultiplied(frameColor(v_TexCoord + (1.0 - mixValue) * flow, nextLayer)),
         mixValue
     );
+    // Straight alpha again, the pipeline blends it over the backdrop.
+    outColor = color.a > 0.0 ? vec4(color.rgb / color.a, color.a) : vec4(0.0);
 }
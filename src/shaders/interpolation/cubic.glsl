@@ -0,0 +1,7 @@
+vec4 interpolate(vec4 previousColor, vec4 nextColor, float mixValue) {
+    float t = mixValue < 0.5
+        ? 4.0 * mixValue * mixValue * mixValue
+        : 1.0 - pow(-2.0 * mixValue + 2.0, 3.0) / 2.0;
+
+    return mix(previousColor, nextColor, t);
+}
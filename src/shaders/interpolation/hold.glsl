@@ -0,0 +1,7 @@
+// Keep the previous frame on screen for most of its duration and only fade
+// during the last quarter.
+const float holdFor = 0.75;
+
+vec4 interpolate(vec4 previousColor, vec4 nextColor, float mixValue) {
+    return mix(previousColor, nextColor, clamp((mixValue - holdFor) / (1.0 - holdFor), 0.0, 1.0));
+}
@@ -0,0 +1,3 @@
+vec4 interpolate(vec4 previousColor, vec4 nextColor, float mixValue) {
+    return mix(previousColor, nextColor, smoothstep(0.0, 1.0, mixValue));
+}
@@ -0,0 +1,22 @@
+#version 450
+
+layout(location = 0) in vec2 v_TexCoord;
+layout(location = 0) out vec4 outColor;
+layout(set = 0, binding = 0) uniform sampler s_Scene;
+layout(set = 0, binding = 1) uniform texture2D t_Scene;
+layout(set = 0, binding = 2) uniform PostProcessing {
+    vec2 resolution;
+    float time;
+};
+
+// Color of the output before post-processing, the origin being at the top
+// left.
+vec4 getColor(vec2 uv) {
+    return texture(sampler2D(t_Scene, s_Scene), uv);
+}
+
+// POST_PROCESSING
+
+void main() {
+    outColor = postProcess(v_TexCoord);
+}
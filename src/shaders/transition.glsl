@@ -0,0 +1,27 @@
+#version 450
+
+layout(location = 0) in vec2 v_TexCoord;
+layout(location = 0) out vec4 outColor;
+layout(set = 0, binding = 0) uniform sampler s_Transition;
+layout(set = 0, binding = 1) uniform texture2D t_From;
+layout(set = 0, binding = 2) uniform texture2D t_To;
+layout(set = 0, binding = 3) uniform Transition {
+    float progress;
+    float ratio;
+};
+
+// Transitions follow the gl-transitions conventions, where the origin of the
+// texture coordinates is at the bottom left.
+vec4 getFromColor(vec2 uv) {
+    return texture(sampler2D(t_From, s_Transition), vec2(uv.x, 1.0 - uv.y));
+}
+
+vec4 getToColor(vec2 uv) {
+    return texture(sampler2D(t_To, s_Transition), vec2(uv.x, 1.0 - uv.y));
+}
+
+// TRANSITION
+
+void main() {
+    outColor = transition(vec2(v_TexCoord.x, 1.0 - v_TexCoord.y));
+}
@@ -0,0 +1,10 @@
+const float cellsPerLine = 150.0;
+
+float random(vec2 co) {
+    return fract(sin(dot(co, vec2(12.9898, 78.233))) * 43758.5453);
+}
+
+vec4 transition(vec2 uv) {
+    float threshold = random(floor(uv * vec2(ratio, 1.0) * cellsPerLine));
+    return mix(getFromColor(uv), getToColor(uv), step(threshold, progress));
+}
@@ -0,0 +1,6 @@
+const float smoothness = 0.1;
+
+vec4 transition(vec2 uv) {
+    float edge = progress * (1.0 + smoothness);
+    return mix(getFromColor(uv), getToColor(uv), 1.0 - smoothstep(edge - smoothness, edge, uv.x));
+}
@@ -0,0 +1,6 @@
+const float strength = 0.4;
+
+vec4 transition(vec2 uv) {
+    vec2 zoomed = 0.5 + (uv - 0.5) / (1.0 + strength * progress);
+    return mix(getFromColor(zoomed), getToColor(uv), smoothstep(0.0, 1.0, progress));
+}
@@ -0,0 +1,298 @@
+use crate::{
+    dirs,
+    pipeline::{Prepared, SceneLoader},
+    random::Random,
+    source::{Decoded, Source, SourceError},
+};
+use log::{info, warn};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Order {
+    Sequential,
+    Shuffle,
+    /// Shuffled once, then the order and the current item are saved so the
+    /// slideshow resumes where it stopped.
+    Persisted,
+}
+
+impl FromStr for Order {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sequential" => Ok(Order::Sequential),
+            "shuffle" => Ok(Order::Shuffle),
+            "persisted" => Ok(Order::Persisted),
+            _ => Err(format!(
+                "unknown slideshow order '{}' (expected sequential, shuffle or persisted)",
+                s
+            )),
+        }
+    }
+}
+
+/// Rotates through the animations, shaders and images of a directory.
+///
+/// The next item is decoded on a background thread as soon as the current
+/// one is displayed, its scenes prepared there too, so switching never
+/// stalls the render loop.
+pub struct Slideshow {
+    items: Vec<Source>,
+    order: Order,
+    index: usize,
+    interval: Duration,
+    switch_at: Instant,
+    state_path: Option<PathBuf>,
+    random: Random,
+    /// Prepares the next items once there are screens to display them.
+    loader: Option<SceneLoader>,
+    preload: Option<Receiver<Result<Prepared, SourceError>>>,
+}
+
+impl Slideshow {
+    pub fn new(directory: &Path, order: Order, interval: Duration) -> Result<Self, Box<dyn Error>> {
+        let mut paths: Vec<_> = fs::read_dir(directory)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                let supported = Source::from_path(path).is_some();
+                if !supported {
+                    info!("Slideshow: skipping unsupported {}", path.display());
+                }
+                supported
+            })
+            .collect();
+        paths.sort_by(|a, b| natord::compare(&a.to_string_lossy(), &b.to_string_lossy()));
+
+        if paths.is_empty() {
+            return Err(format!("nothing to display in {}", directory.display()).into());
+        }
+
+        let mut random = Random::new();
+        let mut index = 0;
+        let state_path = match order {
+            Order::Persisted => dirs::state_dir().map(|dir| {
+                dir.join(format!(
+                    "slideshow{}",
+                    directory.to_string_lossy().replace('/', "%")
+                ))
+            }),
+            _ => None,
+        };
+
+        match order {
+            Order::Sequential => {}
+            Order::Shuffle => random.shuffle(&mut paths),
+            Order::Persisted => {
+                index = restore_order(state_path.as_deref(), &mut paths, &mut random);
+            }
+        }
+
+        let items = paths
+            .iter()
+            .filter_map(|path| Source::from_path(path))
+            .collect();
+
+        Ok(Self {
+            items,
+            order,
+            index,
+            interval,
+            switch_at: Instant::now() + interval,
+            state_path,
+            random,
+            loader: None,
+            preload: None,
+        })
+    }
+
+    /// Decodes the item to display first, skipping the ones that fail.
+    pub fn first(&mut self) -> Result<Decoded, Box<dyn Error>> {
+        for _ in 0..self.items.len() {
+            match self.items[self.index].decode() {
+                Ok(decoded) => {
+                    self.displayed();
+                    return Ok(decoded);
+                }
+                Err(e) => {
+                    warn!("Slideshow: cannot load {:?}: {}", self.items[self.index], e);
+                    self.advance();
+                }
+            }
+        }
+
+        Err("Slideshow: no item could be loaded".into())
+    }
+
+    /// Preloads the next items for the screens of `loader` from now on.
+    pub fn start_preloading(&mut self, loader: SceneLoader) {
+        self.loader = Some(loader);
+        self.start_preload();
+    }
+
+    /// Returns the next item once it is time to switch and it is prepared.
+    pub fn poll(&mut self) -> Option<Prepared> {
+        if Instant::now() < self.switch_at {
+            return None;
+        }
+
+        let result = match &self.preload {
+            Some(preload) => match preload.try_recv() {
+                Ok(result) => result,
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => Err("decoding thread panicked".into()),
+            },
+            None => return None,
+        };
+
+        self.advance();
+        match result {
+            Ok(prepared) => {
+                info!("Slideshow: switching to {:?}", self.items[self.index]);
+                self.displayed();
+                Some(prepared)
+            }
+            Err(e) => {
+                warn!("Slideshow: cannot load {:?}: {}", self.items[self.index], e);
+                self.start_preload();
+                None
+            }
+        }
+    }
+
+    /// Item being displayed.
+    pub fn current(&self) -> &Source {
+        &self.items[self.index]
+    }
+
+    fn advance(&mut self) {
+        self.index = (self.index + 1) % self.items.len();
+    }
+
+    fn displayed(&mut self) {
+        self.switch_at = Instant::now() + self.interval;
+        self.save_order();
+        self.start_preload();
+    }
+
+    fn start_preload(&mut self) {
+        if self.index + 1 == self.items.len() && self.order == Order::Shuffle {
+            // Every item was shown, pick a new order for the next round. The
+            // current item comes first, so that it stays the displayed one
+            // and is not shown again right away.
+            self.items.swap(0, self.index);
+            self.random.shuffle(&mut self.items[1..]);
+            self.index = 0;
+        }
+
+        let loader = match &self.loader {
+            Some(loader) => loader.clone(),
+            None => return,
+        };
+        let source = self.items[(self.index + 1) % self.items.len()].clone();
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            // The receiver is gone when the slideshow was dropped meanwhile.
+            let _ = sender.send(loader.prepare(&source));
+        });
+        self.preload = Some(receiver);
+    }
+
+    fn save_order(&self) {
+        let state_path = match &self.state_path {
+            Some(state_path) => state_path,
+            None => return,
+        };
+
+        let mut state = format!("{}\n", self.index);
+        for item in &self.items {
+            if let Some(path) = item.frames.as_ref().or(item.fragment.as_ref()) {
+                state.push_str(&format!("{}\n", path.display()));
+            }
+        }
+
+        let saved = state_path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(state_path, state));
+        if let Err(e) = saved {
+            warn!(
+                "Slideshow: cannot save order to {}: {}",
+                state_path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Orders `paths` as saved in the state file, shuffling the ones that were
+/// not there yet to the end. Returns the saved index.
+fn restore_order(
+    state_path: Option<&Path>,
+    paths: &mut Vec<PathBuf>,
+    random: &mut Random,
+) -> usize {
+    let state = state_path.and_then(|path| fs::read_to_string(path).ok());
+    let mut lines = state.as_deref().unwrap_or("").lines();
+    let index = lines.next().and_then(|line| line.parse().ok()).unwrap_or(0);
+
+    let mut ordered: Vec<PathBuf> = lines
+        .map(PathBuf::from)
+        .filter(|path| paths.contains(path))
+        .collect();
+    let mut added: Vec<PathBuf> = paths
+        .iter()
+        .filter(|path| !ordered.contains(path))
+        .cloned()
+        .collect();
+    random.shuffle(&mut added);
+    ordered.append(&mut added);
+
+    *paths = ordered;
+    if index < paths.len() {
+        index
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reshuffle_keeps_the_current_item() {
+        let items: Vec<_> = (1..=5)
+            .map(|index| Source::from_path(Path::new(&format!("solid:#00000{}", index))).unwrap())
+            .collect();
+        let mut slideshow = Slideshow {
+            items,
+            order: Order::Shuffle,
+            index: 0,
+            interval: Duration::from_secs(1),
+            switch_at: Instant::now(),
+            state_path: None,
+            random: Random::new(),
+            loader: None,
+            preload: None,
+        };
+
+        for _ in 0..20 {
+            slideshow.index = slideshow.items.len() - 1;
+            let current = slideshow.current().clone();
+            slideshow.start_preload();
+            assert_eq!(slideshow.current(), &current);
+            assert_ne!(&slideshow.items[slideshow.index + 1], &current);
+            assert_eq!(slideshow.items.len(), 5);
+        }
+    }
+}
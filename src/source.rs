@@ -0,0 +1,1322 @@
+use crate::{
+    builtin::Builtin,
+    bundle::{self, Bundle, Kind},
+    cache, flow,
+    interpolation::Interpolation,
+    live,
+    resize::{self, Target},
+    sequence::{self, Pattern},
+    sprites::SpriteSheet,
+    y4m,
+};
+use image::{gif::GifDecoder, AnimationDecoder, ImageDecoder, RgbaImage};
+use log::{info, warn};
+use once_cell::sync::OnceCell;
+use rayon::prelude::*;
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt, fs,
+    fs::File,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+/// Sources are decoded on background threads, so their errors need to cross
+/// thread boundaries.
+pub type SourceError = Box<dyn Error + Send + Sync>;
+
+/// Something to display: frames, a fragment shader, or both when the custom
+/// fragment samples the frames. Built-in sources replace the fragment shader.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+    pub frames: Option<PathBuf>,
+    pub fragment: Option<PathBuf>,
+    pub builtin: Option<Builtin>,
+}
+
+/// Frames decoded on the CPU, ready to be uploaded.
+pub struct DecodedFrames {
+    pub width: u32,
+    pub height: u32,
+    pub frames: Vec<RgbaImage>,
+    /// Extra loops of a GIF, 0 without a loop count, `None` looping forever.
+    pub repeat: Option<u32>,
+    /// Frames come from a GIF, with 256 colors at most each.
+    pub paletted: bool,
+    /// Frames mapped from the cache, `frames` is then empty.
+    pub cached: Option<cache::Entry>,
+    /// First frame of a video, converted to colors by the GPU. Videos are
+    /// streamed, `frames` is then empty.
+    pub video: Option<(y4m::Format, y4m::Planes)>,
+    /// Only the first frame of frames the cache cannot take, which would all
+    /// be in memory: they are decoded again once displayed, a few at a time.
+    pub deferred: bool,
+}
+
+impl DecodedFrames {
+    fn from_cache(entry: cache::Entry) -> Self {
+        Self {
+            width: entry.width,
+            height: entry.height,
+            frames: Vec::new(),
+            repeat: entry.repeat,
+            paletted: entry.paletted,
+            cached: Some(entry),
+            video: None,
+            deferred: false,
+        }
+    }
+
+    fn from_video(format: y4m::Format, planes: y4m::Planes) -> Self {
+        Self {
+            width: format.width,
+            height: format.height,
+            frames: Vec::new(),
+            repeat: Some(0),
+            paletted: false,
+            cached: None,
+            video: Some((format, planes)),
+            deferred: false,
+        }
+    }
+
+    pub fn total_frame(&self) -> usize {
+        match (&self.cached, &self.video) {
+            (Some(entry), _) => entry.frame_layers.len(),
+            (None, Some(_)) => 1,
+            (None, None) => self.frames.len(),
+        }
+    }
+
+    /// Pixels of every frame, from memory or from the cache.
+    pub fn pixels(&self) -> Vec<&[u8]> {
+        match &self.cached {
+            Some(entry) => (0..entry.frame_layers.len())
+                .map(|frame| entry.frame(frame))
+                .collect(),
+            None => self.frames.iter().map(|frame| &**frame).collect(),
+        }
+    }
+}
+
+pub struct Decoded {
+    pub frames: Option<DecodedFrames>,
+    pub fragment: Option<String>,
+    /// The interpolation of a bundle, used unless one is given on the
+    /// command line.
+    pub interpolation: Option<Interpolation>,
+}
+
+impl Decoded {
+    /// Splits frames decoded whole into the first one and the other ones,
+    /// sent from a background thread along with the motion between them when
+    /// `estimate_flow`, as when decoding them progressively. Frames larger
+    /// than displayed on `target` are resampled there, the first one apart.
+    /// No `Loading` without other frames, `None` without frames and for
+    /// videos.
+    pub fn load_progressively(
+        decoded: &Arc<Self>,
+        target: Target,
+        (estimate_flow, staging_memory): (bool, usize),
+    ) -> Option<(Self, Option<Loading>)> {
+        let frames = decoded
+            .frames
+            .as_ref()
+            .filter(|frames| frames.video.is_none())?;
+        let total_frame = frames.total_frame() as u32;
+        let (width, height) = (frames.width, frames.height);
+        if let Some((resized_width, resized_height)) = target.resized((width, height)) {
+            info!(
+                "Resampling frames from {}x{} to {}x{}",
+                width, height, resized_width, resized_height
+            );
+        }
+        let paletted = frames.paletted && keeps_palette((width, height), Some(target));
+        let first = RgbaImage::from_raw(width, height, frames.pixels()[0].to_vec())?;
+        let first = resize::frame(first, Some(target));
+
+        let loading = if total_frame > 1 {
+            let remaining = Arc::clone(decoded);
+            let background = (estimate_flow, None, staging_memory);
+            Some(load_in_background(
+                &first,
+                total_frame,
+                background,
+                move |send| {
+                    let pixels = remaining
+                        .frames
+                        .as_ref()
+                        .map(DecodedFrames::pixels)
+                        .unwrap_or_default();
+                    pixels
+                        .par_iter()
+                        .enumerate()
+                        .skip(1)
+                        .try_for_each(|(index, pixels)| {
+                            let frame = RgbaImage::from_raw(width, height, pixels.to_vec())
+                                .ok_or("frame of the wrong size")?;
+                            send(index as u32, resize::frame(frame, Some(target)))
+                        })
+                },
+            ))
+        } else {
+            None
+        };
+
+        let first = Self {
+            frames: Some(single_frame(first, frames.repeat, paletted)),
+            fragment: decoded.fragment.clone(),
+            interpolation: decoded.interpolation.clone(),
+        };
+        Some((first, loading))
+    }
+}
+
+impl fmt::Debug for Decoded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Decoded")
+            .field(
+                "frames",
+                &self.frames.as_ref().map(DecodedFrames::total_frame),
+            )
+            .field("fragment", &self.fragment.is_some())
+            .field("interpolation", &self.interpolation)
+            .finish()
+    }
+}
+
+/// What the background decoding of a source sends.
+pub enum Loaded {
+    /// A frame with its index, frames decoded in parallel arriving in any
+    /// order.
+    Frame(u32, RgbaImage),
+    /// Motion between consecutive frames, sent once they are all decoded.
+    Flow(Vec<Vec<u8>>),
+}
+
+/// Frames of a source still being decoded, after the first one.
+pub struct Loading {
+    pub total_frame: u32,
+    pub receiver: Receiver<Result<Loaded, SourceError>>,
+}
+
+/// Frames of `size` that `budget` bytes hold, one at least.
+pub fn frames_within(budget: usize, (width, height): (u32, u32)) -> usize {
+    (budget / (4 * width as usize * height as usize).max(1)).max(1)
+}
+
+/// A frame read when streaming.
+pub enum Frame {
+    Rgba(RgbaImage),
+    /// The planes of a video frame.
+    Yuv(y4m::Planes),
+}
+
+impl Frame {
+    /// Resamples colors to `target`, videos are shown at their size.
+    fn resized(self, target: Option<Target>) -> Self {
+        match self {
+            Frame::Rgba(frame) => Frame::Rgba(resize::frame(frame, target)),
+            Frame::Yuv(planes) => Frame::Yuv(planes),
+        }
+    }
+}
+
+/// Frames decoded on demand, in the order they are requested.
+pub struct Stream {
+    pub total_frame: u32,
+    pub requests: Sender<u32>,
+    /// Every requested frame comes back, or the error reading it.
+    pub receiver: Receiver<(u32, Result<Frame, SourceError>)>,
+}
+
+/// Where the frames of a source are read from when streaming them.
+enum FrameReader {
+    /// Images with the size frames are letterboxed to.
+    Directory(Vec<PathBuf>, (u32, u32)),
+    /// The image of a sprite sheet, frames being cut out when read.
+    Sprites(SpriteSheet, RgbaImage),
+    Video(y4m::Reader),
+    Gif {
+        path: PathBuf,
+        total_frame: u32,
+        repeat: Option<u32>,
+        /// Frames a cursor keeps to go backwards.
+        kept: usize,
+    },
+}
+
+/// Bytes of GIF frames kept when streaming, so that going backwards only
+/// decodes from the start again once per that many frames.
+const GIF_KEPT_MEMORY: usize = 128 << 20;
+
+/// GIF frames being read, with the index of the next one.
+struct GifCursor {
+    frames: image::Frames<'static>,
+    next: u32,
+    /// The latest frames read, up to frame `next - 1`.
+    kept: VecDeque<RgbaImage>,
+}
+
+impl GifCursor {
+    fn kept(&self, index: u32) -> Option<&RgbaImage> {
+        let back = self.next.checked_sub(index)? as usize;
+        self.kept
+            .len()
+            .checked_sub(back)
+            .and_then(|position| self.kept.get(position))
+    }
+}
+
+impl FrameReader {
+    fn open(frames_path: &Path) -> Result<Self, SourceError> {
+        if let Some(sheet) = SpriteSheet::parse(frames_path) {
+            let image = open_frame(&sheet.path)?;
+            Ok(FrameReader::Sprites(sheet, image))
+        } else if is_video(frames_path) {
+            Ok(FrameReader::Video(y4m::Reader::open(frames_path)?))
+        } else if let Some(mut paths) = sequence_paths(frames_path)? {
+            let size = first_readable(&mut paths)?.dimensions();
+            Ok(FrameReader::Directory(paths, size))
+        } else if is_gif(frames_path) {
+            let bytes = fs::read(frames_path)?;
+            let size = GifDecoder::new(&bytes[..])?.dimensions();
+            Ok(FrameReader::Gif {
+                path: frames_path.to_path_buf(),
+                total_frame: gif_frame_count(&bytes).ok_or("cannot read the GIF")?,
+                repeat: gif_repeat_count(&bytes),
+                kept: frames_within(GIF_KEPT_MEMORY, size),
+            })
+        } else {
+            let size = frame_dimensions(frames_path)?;
+            Ok(FrameReader::Directory(
+                vec![frames_path.to_path_buf()],
+                size,
+            ))
+        }
+    }
+
+    fn total_frame(&self) -> u32 {
+        match self {
+            FrameReader::Directory(paths, _) => paths.len() as u32,
+            FrameReader::Sprites(sheet, _) => sheet.frame_count(),
+            FrameReader::Video(video) => video.total_frame(),
+            FrameReader::Gif { total_frame, .. } => *total_frame,
+        }
+    }
+
+    /// GIF frames depend on the previous ones, they are read from the start
+    /// again when going backwards past the ones the cursor keeps.
+    fn read(&mut self, index: u32, cursor: &mut Option<GifCursor>) -> Result<Frame, SourceError> {
+        let (path, kept) = match self {
+            FrameReader::Directory(paths, size) => {
+                let path = paths.get(index as usize).ok_or("no such frame")?;
+                return Ok(Frame::Rgba(resize::letterbox(open_frame(path)?, *size)));
+            }
+            FrameReader::Sprites(sheet, image) => {
+                return sheet.frame(image, index).map(Frame::Rgba)
+            }
+            FrameReader::Video(video) => return video.read(index).map(Frame::Yuv),
+            FrameReader::Gif { path, kept, .. } => (path, *kept),
+        };
+
+        if let Some(frame) = cursor.as_ref().and_then(|cursor| cursor.kept(index)) {
+            return Ok(Frame::Rgba(frame.clone()));
+        }
+        if cursor.as_ref().map_or(true, |cursor| index < cursor.next) {
+            *cursor = Some(GifCursor {
+                frames: GifDecoder::new(File::open(path)?)?.into_frames(),
+                next: 0,
+                kept: VecDeque::with_capacity(kept),
+            });
+        }
+        let cursor = cursor.as_mut().unwrap();
+        loop {
+            let frame = cursor.frames.next().ok_or("no such frame")??.into_buffer();
+            cursor.next += 1;
+            if cursor.kept.len() == kept {
+                cursor.kept.pop_front();
+            }
+            cursor.kept.push_back(frame);
+            if cursor.next > index {
+                return Ok(Frame::Rgba(cursor.kept.back().unwrap().clone()));
+            }
+        }
+    }
+}
+
+impl Source {
+    /// Guesses the kind of source from a path: GLSL files are fragment
+    /// shaders, directories are frames, and so are GIFs, still images, frame
+    /// lists and printf-style patterns. `-` and named pipes are live frames,
+    /// `.swaynim` files bundles.
+    /// Built-in sources are recognized too. Returns `None` for anything else.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        if let Some(builtin) = path.to_str().and_then(Builtin::parse) {
+            return Some(Self {
+                frames: None,
+                fragment: None,
+                builtin: Some(builtin),
+            });
+        }
+
+        let pattern = path.to_str().and_then(Pattern::parse);
+        let sprites = SpriteSheet::parse(path).filter(|sheet| is_image(&sheet.path));
+        let sequence =
+            path.is_dir() || sequence::is_list(path) || pattern.is_some() || sprites.is_some();
+        if sequence || live::is_live(path) {
+            return Some(Self {
+                frames: Some(path.to_path_buf()),
+                fragment: None,
+                builtin: None,
+            });
+        }
+
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "glsl" | "frag" => Some(Self {
+                frames: None,
+                fragment: Some(path.to_path_buf()),
+                builtin: None,
+            }),
+            _ if is_image(path) || is_video(path) || bundle::is_bundle(path) => Some(Self {
+                frames: Some(path.to_path_buf()),
+                fragment: None,
+                builtin: None,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Videos are streamed rather than decoded whole.
+    pub fn is_video(&self) -> bool {
+        self.frames.as_deref().map_or(false, is_video)
+    }
+
+    /// Live frames are read as they arrive rather than decoded.
+    pub fn is_live(&self) -> bool {
+        self.frames.as_deref().map_or(false, live::is_live)
+    }
+
+    /// The bundle of the frames, opened once for both its frames and its
+    /// shaders. `None` for other sources.
+    fn bundle(&self) -> Result<Option<Bundle>, SourceError> {
+        match self
+            .frames
+            .as_deref()
+            .filter(|path| bundle::is_bundle(path))
+        {
+            Some(path) => {
+                let bundle = Bundle::open(path)?;
+                if let Some(credits) = bundle.credits() {
+                    info!("{}: {}", path.display(), credits);
+                }
+                Ok(Some(bundle))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Frames to decode, `None` for live frames and bundles without any.
+    fn decoded_frames(&self, bundle: Option<&Bundle>) -> Option<&Path> {
+        match self.frames.as_deref() {
+            Some(path) if live::is_live(path) => None,
+            Some(_) if bundle.map_or(false, |bundle| !bundle.has_frames()) => None,
+            path => path,
+        }
+    }
+
+    /// The fragment shader and the interpolation replacing the one of the
+    /// options, the ones of `bundle` unless a fragment is given.
+    fn shaders(
+        &self,
+        bundle: Option<&mut Bundle>,
+    ) -> Result<(Option<String>, Option<Interpolation>), SourceError> {
+        match (&self.fragment, &self.builtin, bundle) {
+            (Some(path), _, _) => Ok((Some(fs::read_to_string(path)?), None)),
+            (None, Some(builtin), _) => Ok((Some(builtin.fragment()), None)),
+            (None, None, Some(bundle)) => Ok((bundle.fragment()?, bundle.interpolation()?)),
+            (None, None, None) => Ok((None, None)),
+        }
+    }
+
+    /// Live frames are left to the pipeline, which follows them.
+    pub fn decode(&self) -> Result<Decoded, SourceError> {
+        let mut bundle = self.bundle()?;
+        let frames = match self.decoded_frames(bundle.as_ref()) {
+            Some(path) => Some(decode_cached(path, bundle.as_mut())?),
+            None => None,
+        };
+        let (fragment, interpolation) = self.shaders(bundle.as_mut())?;
+
+        Ok(Decoded {
+            frames,
+            fragment,
+            interpolation,
+        })
+    }
+
+    /// Decodes the first frame only, so that it can be displayed right away.
+    /// The other ones follow through the returned `Loading`, along with the
+    /// motion between frames when `motion_compensation` applies, frames read
+    /// whole from the cache, a bundle or a sprite sheet too. Frames are
+    /// resampled to `target`, and wait to be received within `staging_memory`
+    /// bytes.
+    pub fn decode_progressively(
+        &self,
+        motion_compensation: bool,
+        target: Option<Target>,
+        staging_memory: usize,
+    ) -> Result<(Decoded, Option<Loading>), SourceError> {
+        let mut bundle = self.bundle()?;
+        let (fragment, interpolation) = self.shaders(bundle.as_mut())?;
+
+        let estimate_flow = motion_compensation && fragment.is_none();
+        let (frames, loading) = match self.decoded_frames(bundle.as_ref()) {
+            Some(path) => {
+                let background = (estimate_flow, staging_memory);
+                let (frames, loading) =
+                    decode_first_frame(path, bundle.as_mut(), background, target)?;
+                (Some(frames), loading)
+            }
+            None => (None, None),
+        };
+
+        let whole = loading.is_none()
+            && frames
+                .as_ref()
+                .map_or(false, |frames| frames.video.is_none());
+        let decoded = Decoded {
+            frames,
+            fragment,
+            interpolation,
+        };
+        match target.filter(|_| whole) {
+            Some(target) => Decoded::load_progressively(
+                &Arc::new(decoded),
+                target,
+                (estimate_flow, staging_memory),
+            )
+            .ok_or_else(|| "frames of the wrong size".into()),
+            None => Ok((decoded, loading)),
+        }
+    }
+
+    /// Decodes the first frame only, a background thread decoding the other
+    /// ones when requested. `None` when there are no frames to stream.
+    /// Frames are resampled to `target`.
+    /// Bundles are read whole.
+    pub fn stream(&self, target: Option<Target>) -> Result<Option<(Decoded, Stream)>, SourceError> {
+        let path = match &self.frames {
+            Some(path) if !bundle::is_bundle(path) => path,
+            _ => return Ok(None),
+        };
+
+        let mut reader = FrameReader::open(path)?;
+        let frames = match (reader.read(0, &mut None)?, &reader) {
+            (Frame::Yuv(planes), FrameReader::Video(video)) => {
+                DecodedFrames::from_video(video.format, planes)
+            }
+            (Frame::Yuv(_), _) => return Err("planes outside of a video".into()),
+            (Frame::Rgba(first), _) => {
+                let paletted = keeps_palette(first.dimensions(), target);
+                let first = resize::frame(first, target);
+                match reader {
+                    FrameReader::Gif { repeat, .. } => single_frame(first, repeat, paletted),
+                    _ => single_frame(first, Some(0), false),
+                }
+            }
+        };
+        let total_frame = reader.total_frame();
+
+        let (requests, requested) = mpsc::channel();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut cursor = None;
+            for index in requested {
+                let frame = reader
+                    .read(index, &mut cursor)
+                    .map(|frame| frame.resized(target));
+                if sender.send((index, frame)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        let (fragment, interpolation) = self.shaders(None)?;
+        let decoded = Decoded {
+            frames: Some(frames),
+            fragment,
+            interpolation,
+        };
+
+        Ok(Some((
+            decoded,
+            Stream {
+                total_frame,
+                requests,
+                receiver,
+            },
+        )))
+    }
+}
+
+/// Images of a directory, a printf-style pattern or a frame list, `None`
+/// for other sources.
+fn sequence_paths(frames_path: &Path) -> Result<Option<Vec<PathBuf>>, SourceError> {
+    if frames_path.is_dir() {
+        sorted_entries(frames_path).map(Some)
+    } else if sequence::is_list(frames_path) {
+        sequence::read_list(frames_path).map(Some)
+    } else {
+        match frames_path.to_str().and_then(Pattern::parse) {
+            Some(pattern) => pattern.paths().map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Animated sources are worth caching, still images decode quickly enough.
+fn cache_path(
+    frames_path: &Path,
+    sequence: Option<&[PathBuf]>,
+    target: Option<Target>,
+) -> Option<PathBuf> {
+    match sequence {
+        Some(paths) => cache::path(frames_path, paths, target),
+        None if is_gif(frames_path) || bundle::is_bundle(frames_path) => {
+            cache::path(frames_path, &[], target)
+        }
+        None => None,
+    }
+}
+
+/// Frames from the cache, decoded and cached on a miss. The frames of a
+/// bundle are read from `bundle`, opened already.
+fn decode_cached(
+    frames_path: &Path,
+    bundle: Option<&mut Bundle>,
+) -> Result<DecodedFrames, SourceError> {
+    let sequence = sequence_paths(frames_path)?;
+    let cache_path = cache_path(frames_path, sequence.as_deref(), None);
+    if let Some(entry) = cache_path.as_deref().and_then(cache::Entry::open) {
+        return Ok(DecodedFrames::from_cache(entry));
+    }
+    if let Some(cache_path) = &cache_path {
+        match decode_to_cache(frames_path, sequence.clone(), cache_path) {
+            Ok(Some(entry)) => return Ok(DecodedFrames::from_cache(entry)),
+            Ok(None) => {}
+            Err(e) => warn!("Cannot decode frames to the cache: {}", e),
+        }
+    }
+    // Sequences and GIFs too large to be cached, or that could not be, are
+    // decoded progressively instead, within the staging memory.
+    if sequence.is_some() || is_gif(frames_path) {
+        return decode_deferred(frames_path, sequence);
+    }
+
+    let decoded = match bundle {
+        Some(bundle) => decode_bundle(bundle)?,
+        None => decode_frames(frames_path)?,
+    };
+    let writer = cache_path.and_then(|path| {
+        let size = (decoded.width, decoded.height);
+        cache::Writer::create(
+            path,
+            size,
+            decoded.frames.len() as u32,
+            decoded.repeat,
+            decoded.paletted,
+        )
+    });
+    if let Some(writer) = writer {
+        writer.store(&decoded.frames);
+    }
+
+    Ok(decoded)
+}
+
+/// The first frame of a sequence or a GIF, the other ones being left to
+/// `Source::decode_progressively`.
+fn decode_deferred(
+    frames_path: &Path,
+    sequence: Option<Vec<PathBuf>>,
+) -> Result<DecodedFrames, SourceError> {
+    let (first, total_frame, repeat, paletted) = if let Some(mut paths) = sequence {
+        (
+            first_readable(&mut paths)?,
+            paths.len() as u32,
+            Some(0),
+            false,
+        )
+    } else {
+        let bytes = fs::read(frames_path)?;
+        let total_frame = gif_frame_count(&bytes).ok_or("cannot read the GIF")?;
+        (
+            first_gif_frame(&bytes)?,
+            total_frame,
+            gif_repeat_count(&bytes),
+            true,
+        )
+    };
+
+    Ok(DecodedFrames {
+        deferred: total_frame > 1,
+        ..single_frame(first, repeat, paletted)
+    })
+}
+
+/// Decodes a sequence or a GIF to the cache at `cache_path`, a batch of
+/// frames at a time, so that they are never all in memory, then maps it.
+/// `None` for other sources and frames too large to be cached.
+fn decode_to_cache(
+    frames_path: &Path,
+    sequence: Option<Vec<PathBuf>>,
+    cache_path: &Path,
+) -> Result<Option<cache::Entry>, SourceError> {
+    let writer = match sequence {
+        Some(mut paths) => {
+            let first = first_readable(&mut paths)?;
+            let size = first.dimensions();
+            let writer = match cache::Writer::create(
+                cache_path.to_path_buf(),
+                size,
+                paths.len() as u32,
+                Some(0),
+                false,
+            ) {
+                Some(writer) => writer,
+                None => return Ok(None),
+            };
+            writer.add(0, &first)?;
+            // One frame per thread.
+            let batch_size = rayon::current_num_threads();
+            for (batch, batch_paths) in paths[1..].chunks(batch_size).enumerate() {
+                let frames: Vec<RgbaImage> = batch_paths
+                    .par_iter()
+                    .map(|path| match open_frame(path) {
+                        Ok(frame) => resize::letterbox(frame, size),
+                        Err(e) => {
+                            warn!("Replacing unreadable frame {} with the first one", e);
+                            first.clone()
+                        }
+                    })
+                    .collect();
+                for (index, frame) in (1 + batch * batch_size..).zip(&frames) {
+                    writer.add(index as u32, frame)?;
+                }
+            }
+            writer
+        }
+        None if is_gif(frames_path) => {
+            let bytes = fs::read(frames_path)?;
+            let total_frame = gif_frame_count(&bytes).ok_or("cannot read the GIF")?;
+            let decoder = GifDecoder::new(&bytes[..])?;
+            let size = decoder.dimensions();
+            let repeat = gif_repeat_count(&bytes);
+            let writer = match cache::Writer::create(
+                cache_path.to_path_buf(),
+                size,
+                total_frame,
+                repeat,
+                true,
+            ) {
+                Some(writer) => writer,
+                None => return Ok(None),
+            };
+            for (index, frame) in decoder.into_frames().enumerate() {
+                writer.add(index as u32, &frame?.into_buffer())?;
+            }
+            writer
+        }
+        None => return Ok(None),
+    };
+
+    writer.finish()?;
+    Ok(cache::Entry::open(cache_path))
+}
+
+/// Unreadable frames are dropped until a readable one, the frame count is
+/// known after that and they are replaced by the first frame.
+fn first_readable(paths: &mut Vec<PathBuf>) -> Result<RgbaImage, SourceError> {
+    loop {
+        let path = paths.first().ok_or("no readable frame")?;
+        match open_frame(path) {
+            Ok(frame) => return Ok(frame),
+            Err(e) => {
+                warn!("Skipping unreadable frame {}", e);
+                paths.remove(0);
+            }
+        }
+    }
+}
+
+fn decode_first_frame(
+    frames_path: &Path,
+    bundle: Option<&mut Bundle>,
+    (estimate_flow, staging_memory): (bool, usize),
+    target: Option<Target>,
+) -> Result<(DecodedFrames, Option<Loading>), SourceError> {
+    // Bundles are read whole, then resampled like frames decoded without
+    // knowing the target.
+    if bundle.is_some() {
+        return Ok((decode_cached(frames_path, bundle)?, None));
+    }
+    let sequence = sequence_paths(frames_path)?;
+    let cache_path = cache_path(frames_path, sequence.as_deref(), target);
+    if let Some(entry) = cache_path.as_deref().and_then(cache::Entry::open) {
+        return Ok((DecodedFrames::from_cache(entry), None));
+    }
+    let writer = |first: &RgbaImage, total_frame, repeat, paletted| {
+        let size = first.dimensions();
+        cache_path
+            .clone()
+            .and_then(|path| cache::Writer::create(path, size, total_frame, repeat, paletted))
+    };
+
+    if let Some(sheet) = SpriteSheet::parse(frames_path) {
+        Ok((decode_sprites(&sheet, target)?, None))
+    } else if is_video(frames_path) {
+        Ok((decode_video(frames_path)?, None))
+    } else if let Some(mut paths) = sequence {
+        let first = resize::frame(first_readable(&mut paths)?, target);
+        let total_frame = paths.len() as u32;
+        let loading = if paths.len() > 1 {
+            let writer = writer(&first, total_frame, Some(0), false);
+            let replacement = first.clone();
+            let background = (estimate_flow, writer, staging_memory);
+            Some(load_in_background(
+                &first,
+                total_frame,
+                background,
+                move |send| {
+                    let size = replacement.dimensions();
+                    paths[1..]
+                        .par_iter()
+                        .enumerate()
+                        .try_for_each(|(index, path)| {
+                            let frame = match open_frame(path) {
+                                Ok(frame) => resize::letterbox(resize::frame(frame, target), size),
+                                Err(e) => {
+                                    warn!("Replacing unreadable frame {} with the first one", e);
+                                    replacement.clone()
+                                }
+                            };
+                            send(index as u32 + 1, frame)
+                        })
+                },
+            ))
+        } else {
+            None
+        };
+
+        Ok((single_frame(first, Some(0), false), loading))
+    } else if is_gif(frames_path) {
+        let bytes = fs::read(frames_path)?;
+        let total_frame = gif_frame_count(&bytes).ok_or("cannot read the GIF")?;
+        let repeat = gif_repeat_count(&bytes);
+        let first = first_gif_frame(&bytes)?;
+        let paletted = keeps_palette(first.dimensions(), target);
+        let first = resize::frame(first, target);
+        let loading = if total_frame > 1 {
+            let writer = writer(&first, total_frame, repeat, paletted);
+            let background = (estimate_flow, writer, staging_memory);
+            Some(load_in_background(
+                &first,
+                total_frame,
+                background,
+                move |send| {
+                    // The frames iterator cannot move to another thread, the GIF
+                    // read once is decoded again there.
+                    let decoder = GifDecoder::new(&bytes[..])?;
+                    for (index, frame) in decoder.into_frames().enumerate().skip(1) {
+                        send(index as u32, resize::frame(frame?.into_buffer(), target))?;
+                    }
+                    Ok(())
+                },
+            ))
+        } else {
+            None
+        };
+
+        Ok((single_frame(first, repeat, paletted), loading))
+    } else {
+        let frame = resize::frame(image::open(frames_path)?.to_rgba(), target);
+        Ok((single_frame(frame, Some(0), false), None))
+    }
+}
+
+/// The first frame of a video only, the other ones are streamed.
+fn decode_video(path: &Path) -> Result<DecodedFrames, SourceError> {
+    let mut video = y4m::Reader::open(path)?;
+    let planes = video.read(0)?;
+    Ok(DecodedFrames::from_video(video.format, planes))
+}
+
+/// Sprite sheets are one image, every frame is there once it is decoded.
+fn decode_sprites(
+    sheet: &SpriteSheet,
+    target: Option<Target>,
+) -> Result<DecodedFrames, SourceError> {
+    let frames: Vec<_> = sheet
+        .frames(&open_frame(&sheet.path)?)?
+        .into_par_iter()
+        .map(|frame| resize::frame(frame, target))
+        .collect();
+    let (width, height) = frames[0].dimensions();
+
+    Ok(DecodedFrames {
+        width,
+        height,
+        frames,
+        repeat: Some(0),
+        paletted: false,
+        cached: None,
+        video: None,
+        deferred: false,
+    })
+}
+
+/// Whether GIF frames of `size` keep their palette on `target`, resampled
+/// frames getting more than 256 colors.
+fn keeps_palette(size: (u32, u32), target: Option<Target>) -> bool {
+    target.and_then(|target| target.resized(size)).is_none()
+}
+
+fn single_frame(frame: RgbaImage, repeat: Option<u32>, paletted: bool) -> DecodedFrames {
+    DecodedFrames {
+        width: frame.width(),
+        height: frame.height(),
+        frames: vec![frame],
+        repeat,
+        paletted,
+        cached: None,
+        video: None,
+        deferred: false,
+    }
+}
+
+/// Runs `decode` on a background thread, sending the frames it gives as
+/// they come, then the motion between them when `estimate_flow`. The frames
+/// are cached along the way with a `writer`.
+///
+/// Decoding waits while `staging_memory` bytes of frames are not received,
+/// so that memory stays flat whatever the frame count, apart from the frames
+/// kept to estimate the motion. `send` fails once the receiver is dropped,
+/// `decode` then stops. It runs on the threads of `decode_pool`, the
+/// ones of the global pool being left to the receiving side.
+fn load_in_background<F>(
+    first: &RgbaImage,
+    total_frame: u32,
+    (estimate_flow, writer, staging_memory): (bool, Option<cache::Writer>, usize),
+    decode: F,
+) -> Loading
+where
+    F: FnOnce(
+            &(dyn Fn(u32, RgbaImage) -> Result<(), SourceError> + Sync),
+        ) -> Result<(), SourceError>
+        + Send
+        + 'static,
+{
+    let (sender, receiver) = mpsc::sync_channel(frames_within(staging_memory, first.dimensions()));
+    let writer = writer.and_then(|writer| match writer.add(0, first) {
+        Ok(()) => Some(writer),
+        Err(e) => {
+            warn!("Cannot cache frames: {}", e);
+            None
+        }
+    });
+    let first = if estimate_flow {
+        Some(first.clone())
+    } else {
+        None
+    };
+
+    thread::spawn(move || {
+        let pool = match decode_pool() {
+            Ok(pool) => pool,
+            Err(e) => {
+                let _ = sender.send(Err(e));
+                return;
+            }
+        };
+        let kept = Mutex::new(Vec::new());
+        // Threads write their frames to the cache without waiting for each
+        // other, the first error giving it up.
+        let cache_failed = AtomicBool::new(false);
+        let result = pool.install(|| {
+            decode(&|index, frame| {
+                if first.is_some() {
+                    kept.lock().unwrap().push((index, frame.clone()));
+                }
+                if let Some(writer) = writer
+                    .as_ref()
+                    .filter(|_| !cache_failed.load(Ordering::Relaxed))
+                {
+                    if let Err(e) = writer.add(index, &frame) {
+                        if !cache_failed.swap(true, Ordering::Relaxed) {
+                            warn!("Cannot cache frames: {}", e);
+                        }
+                    }
+                }
+                sender
+                    .send(Ok(Loaded::Frame(index, frame)))
+                    .map_err(|_| "the frames are not received anymore".into())
+            })
+        });
+
+        if let Err(e) = result {
+            let _ = sender.send(Err(e));
+            return;
+        }
+        let writer = writer.filter(|_| !cache_failed.into_inner());
+        if let Some(Err(e)) = writer.map(cache::Writer::finish) {
+            warn!("Cannot cache frames: {}", e);
+        }
+
+        if let Some(first) = first {
+            let mut frames = kept.into_inner().unwrap();
+            frames.push((0, first));
+            frames.sort_by_key(|(index, _)| *index);
+            let frames: Vec<_> = frames.iter().map(|(_, frame)| frame).collect();
+            let _ = sender.send(Ok(Loaded::Flow(flow::estimate_loop(&frames))));
+        }
+    });
+
+    Loading {
+        total_frame,
+        receiver,
+    }
+}
+
+/// Threads every load decodes on, as many as the global pool has, so that
+/// loading several sources at once does not take more.
+fn decode_pool() -> Result<&'static rayon::ThreadPool, SourceError> {
+    static POOL: OnceCell<rayon::ThreadPool> = OnceCell::new();
+    POOL.get_or_try_init(|| {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(rayon::current_num_threads())
+            .thread_name(|index| format!("decode-{}", index))
+            .build()
+            .map_err(Into::into)
+    })
+}
+
+/// Frames of a sprite sheet, a video or a still image.
+fn decode_frames(frames_path: &Path) -> Result<DecodedFrames, SourceError> {
+    if let Some(sheet) = SpriteSheet::parse(frames_path) {
+        decode_sprites(&sheet, None)
+    } else if is_video(frames_path) {
+        decode_video(frames_path)
+    } else {
+        Ok(single_frame(
+            image::open(frames_path)?.to_rgba(),
+            Some(0),
+            false,
+        ))
+    }
+}
+
+/// Images that can be frames, in a directory or on their own.
+fn is_image(path: &Path) -> bool {
+    const EXTENSIONS: &[&str] = &["gif", "png", "jpg", "jpeg", "bmp", "webp", "tif", "tiff"];
+
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map_or(false, |e| {
+            EXTENSIONS
+                .iter()
+                .any(|extension| e.eq_ignore_ascii_case(extension))
+        })
+}
+
+/// Errors name the frame, so that a broken one can be found in a directory.
+fn open_frame(path: &Path) -> Result<RgbaImage, SourceError> {
+    image::open(path)
+        .map(|image| image.to_rgba())
+        .map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+fn frame_dimensions(path: &Path) -> Result<(u32, u32), SourceError> {
+    image::image_dimensions(path).map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+fn is_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map_or(false, |e| e.eq_ignore_ascii_case("y4m"))
+}
+
+fn is_gif(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map_or(false, |e| e.eq_ignore_ascii_case("gif"))
+}
+
+/// Reads the loop count from the NETSCAPE2.0 application extension, which
+/// the image crate does not expose. Without the extension the GIF plays
+/// once, `Some(0)`, and a loop count of 0 means forever, `None`.
+fn gif_repeat_count(bytes: &[u8]) -> Option<u32> {
+    const NETSCAPE: &[u8] = b"NETSCAPE2.0";
+
+    bytes
+        .windows(NETSCAPE.len() + 4)
+        .find(|w| w.starts_with(NETSCAPE) && w[NETSCAPE.len()..NETSCAPE.len() + 2] == [3, 1])
+        .map_or(Some(0), |w| {
+            match u16::from_le_bytes([w[NETSCAPE.len() + 2], w[NETSCAPE.len() + 3]]) {
+                0 => None,
+                count => Some(u32::from(count)),
+            }
+        })
+}
+
+fn gif_color_table_size(flags: u8) -> usize {
+    if flags & 0x80 == 0 {
+        0
+    } else {
+        3 << ((flags & 0x07) + 1)
+    }
+}
+
+/// Offset following the data sub-blocks starting at `offset`.
+fn skip_gif_sub_blocks(bytes: &[u8], mut offset: usize) -> Option<usize> {
+    loop {
+        let length = usize::from(*bytes.get(offset)?);
+        offset += 1 + length;
+        if length == 0 {
+            return Some(offset);
+        }
+    }
+}
+
+/// Counts the images of a GIF by walking its blocks without decoding them,
+/// so that the texture can be allocated before the frames are decoded.
+/// Decoders stop at the trailer as well as at the end of the data, so do
+/// the complete images counted; `None` without any.
+fn gif_frame_count(bytes: &[u8]) -> Option<u32> {
+    // Header and logical screen descriptor, then the global color table.
+    let mut offset = 13 + gif_color_table_size(*bytes.get(10)?);
+    let mut count = 0;
+
+    loop {
+        let next = match bytes.get(offset) {
+            // Extension: introducer, label, then sub-blocks.
+            Some(0x21) => skip_gif_sub_blocks(bytes, offset + 2),
+            // Image: descriptor, local color table, LZW code size, then
+            // sub-blocks.
+            Some(0x2C) => bytes.get(offset + 9).and_then(|flags| {
+                let table = gif_color_table_size(*flags);
+                let end = skip_gif_sub_blocks(bytes, offset + 10 + table + 1)?;
+                count += 1;
+                Some(end)
+            }),
+            _ => None,
+        };
+        match next {
+            Some(next) => offset = next,
+            None => return Some(count).filter(|count| *count > 0),
+        }
+    }
+}
+
+fn first_gif_frame(bytes: &[u8]) -> Result<RgbaImage, SourceError> {
+    let frame = GifDecoder::new(bytes)?
+        .into_frames()
+        .next()
+        .ok_or("no frame in the GIF")??;
+    Ok(frame.into_buffer())
+}
+
+/// Frames are composited as displayed: the decoder draws each one over the
+/// previous ones, clearing its area to transparency after it when disposed
+/// to the background, and restoring what was under it when disposed to the
+/// previous frame.
+fn decode_gif(bytes: &[u8]) -> Result<DecodedFrames, SourceError> {
+    let decoder = GifDecoder::new(bytes)?;
+    let (width, height) = decoder.dimensions();
+    let frames = decoder.into_frames().collect_frames()?;
+    let frames = frames.into_iter().map(image::Frame::into_buffer).collect();
+
+    Ok(DecodedFrames {
+        width,
+        height,
+        frames,
+        repeat: gif_repeat_count(bytes),
+        paletted: true,
+        cached: None,
+        video: None,
+        deferred: false,
+    })
+}
+
+/// Images of a directory in natural order, subdirectories and other files
+/// being skipped.
+fn sorted_entries(frames_path: &Path) -> Result<Vec<PathBuf>, SourceError> {
+    let mut dir = Vec::new();
+    for entry in std::fs::read_dir(frames_path)? {
+        let path = entry?.path();
+        if path.is_file() && is_image(&path) {
+            dir.push(path);
+        } else {
+            info!("Skipping {}, not an image", path.display());
+        }
+    }
+
+    let name = |path: &PathBuf| {
+        path.file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    };
+    dir.sort_by(|a, b| natord::compare(&name(a), &name(b)));
+
+    Ok(dir)
+}
+
+/// Frames stored in a bundle, read from the archive without extracting it.
+/// Unreadable frames of a directory are skipped.
+fn decode_bundle(bundle: &mut Bundle) -> Result<DecodedFrames, SourceError> {
+    let entry = bundle
+        .manifest
+        .frames
+        .clone()
+        .ok_or("the bundle has no frames")?;
+    match bundle.manifest.kind {
+        Kind::Gif => decode_gif(&bundle.read(&entry)?),
+        Kind::Image => Ok(single_frame(
+            image::load_from_memory(&bundle.read(&entry)?)?.to_rgba(),
+            Some(0),
+            false,
+        )),
+        Kind::Frames => {
+            // The archive is read from one thread, the images are decoded
+            // in parallel.
+            let mut encoded = Vec::new();
+            for name in bundle.entries(&entry) {
+                if is_image(Path::new(&name)) {
+                    encoded.push((bundle.read(&name)?, name));
+                } else {
+                    info!("Skipping {}, not an image", name);
+                }
+            }
+            let frames: Vec<RgbaImage> = encoded
+                .par_iter()
+                .filter_map(|(bytes, name)| match image::load_from_memory(bytes) {
+                    Ok(frame) => Some(frame.to_rgba()),
+                    Err(e) => {
+                        warn!("Skipping unreadable frame {}: {}", name, e);
+                        None
+                    }
+                })
+                .collect();
+            letterbox_sequence(frames)
+        }
+        Kind::Fragment => Err("the bundle has no frames".into()),
+    }
+}
+
+/// Frames whose size is not the one of the first frame are letterboxed to it.
+fn letterbox_sequence(frames: Vec<RgbaImage>) -> Result<DecodedFrames, SourceError> {
+    let (width, height) = frames.first().ok_or("no readable frame")?.dimensions();
+    let frames = frames
+        .into_par_iter()
+        .map(|frame| resize::letterbox(frame, (width, height)))
+        .collect();
+
+    Ok(DecodedFrames {
+        width,
+        height,
+        frames,
+        repeat: Some(0),
+        paletted: false,
+        cached: None,
+        video: None,
+        deferred: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gif::{DisposalMethod, Encoder, Frame};
+    use image::Rgba;
+    use std::borrow::Cow;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const GREEN: Rgba<u8> = Rgba([0, 255, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+    const TRANSPARENT: Rgba<u8> = Rgba([0, 0, 0, 0]);
+
+    /// A GIF of 2x1 pixels with a palette of red, green, blue and a
+    /// transparent color, frames being given by their left edge, indices
+    /// and disposal.
+    fn gif(frames: &[(u16, &[u8], DisposalMethod)]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0];
+            let mut encoder = Encoder::new(&mut bytes, 2, 1, &palette).unwrap();
+            for (left, indices, dispose) in frames {
+                let frame = Frame {
+                    left: *left,
+                    width: indices.len() as u16,
+                    height: 1,
+                    dispose: *dispose,
+                    transparent: Some(3),
+                    buffer: Cow::Borrowed(indices),
+                    ..Frame::default()
+                };
+                encoder.write_frame(&frame).unwrap();
+            }
+        }
+        bytes
+    }
+
+    fn pixels(frames: &DecodedFrames) -> Vec<Vec<Rgba<u8>>> {
+        frames
+            .frames
+            .iter()
+            .map(|frame| frame.pixels().copied().collect())
+            .collect()
+    }
+
+    #[test]
+    fn gif_frame_count_and_disposal() {
+        let bytes = gif(&[
+            (0, &[0, 0], DisposalMethod::Keep),
+            (0, &[1], DisposalMethod::Previous),
+            (1, &[2], DisposalMethod::Background),
+            (0, &[3, 3], DisposalMethod::Keep),
+        ]);
+        assert_eq!(gif_frame_count(&bytes), Some(4));
+
+        let frames = decode_gif(&bytes).unwrap();
+        assert_eq!((frames.width, frames.height), (2, 1));
+        assert_eq!(
+            pixels(&frames),
+            [
+                vec![RED, RED],
+                // Drawn over the first frame, then removed.
+                vec![GREEN, RED],
+                // Its area is cleared after it.
+                vec![RED, BLUE],
+                vec![RED, TRANSPARENT],
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated_gif() {
+        let bytes = gif(&[
+            (0, &[0, 0], DisposalMethod::Keep),
+            (0, &[1, 1], DisposalMethod::Keep),
+        ]);
+        assert_eq!(gif_frame_count(&bytes[..bytes.len() - 1]), Some(2));
+        assert_eq!(gif_frame_count(&bytes[..13]), None);
+    }
+}
@@ -0,0 +1,183 @@
+use crate::source::SourceError;
+use image::{imageops, RgbaImage};
+use std::path::{Path, PathBuf};
+
+/// Frames laid out on a grid in one image, read row by row. Written as the
+/// image followed by `:<columns>x<rows>`, then optionally the frame count,
+/// the margin around the grid and the spacing between frames, in pixels:
+/// `walk.png:8x4,30,1,2`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpriteSheet {
+    pub path: PathBuf,
+    columns: u32,
+    rows: u32,
+    count: u32,
+    margin: u32,
+    spacing: u32,
+}
+
+impl SpriteSheet {
+    /// `None` when `path` is not a sprite sheet.
+    pub fn parse(path: &Path) -> Option<Self> {
+        let mut parts = path.to_str()?.rsplitn(2, ':');
+        let (grid, image) = (parts.next()?, parts.next()?);
+
+        let mut values = grid.split(',').map(str::trim);
+        let mut size = values.next()?.splitn(2, 'x');
+        let columns: u32 = size.next()?.parse().ok()?;
+        let rows: u32 = size.next()?.parse().ok()?;
+        let mut value = |default| match values.next() {
+            Some(value) => value.parse().ok(),
+            None => Some(default),
+        };
+        // Grids of more than `u32::MAX` frames are not sprite sheets.
+        let cells = columns.checked_mul(rows)?;
+        let count = value(cells)?;
+        let margin = value(0)?;
+        let spacing = value(0)?;
+        if columns == 0 || rows == 0 || count == 0 || count > cells {
+            return None;
+        }
+
+        Some(Self {
+            path: PathBuf::from(image),
+            columns,
+            rows,
+            count,
+            margin,
+            spacing,
+        })
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        self.count
+    }
+
+    /// Size of a frame of `sheet`.
+    fn frame_size(&self, sheet: &RgbaImage) -> Result<(u32, u32), SourceError> {
+        let size = |length: u32, frames: u32| {
+            let gaps = (frames - 1)
+                .checked_mul(self.spacing)?
+                .checked_add(self.margin.checked_mul(2)?)?;
+            length
+                .checked_sub(gaps)
+                .map(|length| length / frames)
+                .filter(|length| *length > 0)
+        };
+
+        match (
+            size(sheet.width(), self.columns),
+            size(sheet.height(), self.rows),
+        ) {
+            (Some(width), Some(height)) => Ok((width, height)),
+            _ => Err(format!("{} is too small for its grid", self.path.display()).into()),
+        }
+    }
+
+    /// Frame `index` of `sheet`, the image of the sprite sheet.
+    pub fn frame(&self, sheet: &RgbaImage, index: u32) -> Result<RgbaImage, SourceError> {
+        if index >= self.count {
+            return Err("no such frame".into());
+        }
+
+        let (width, height) = self.frame_size(sheet)?;
+        let (column, row) = (index % self.columns, index / self.columns);
+        let x = self.margin + column * (width + self.spacing);
+        let y = self.margin + row * (height + self.spacing);
+        Ok(imageops::crop_imm(sheet, x, y, width, height).to_image())
+    }
+
+    /// Every frame of `sheet`.
+    pub fn frames(&self, sheet: &RgbaImage) -> Result<Vec<RgbaImage>, SourceError> {
+        (0..self.count)
+            .map(|index| self.frame(sheet, index))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn sheet(spec: &str) -> Option<SpriteSheet> {
+        SpriteSheet::parse(Path::new(spec))
+    }
+
+    #[test]
+    fn grid_only() {
+        let sheet = sheet("walk.png:8x4").unwrap();
+        assert_eq!(sheet.path, PathBuf::from("walk.png"));
+        assert_eq!((sheet.columns, sheet.rows), (8, 4));
+        assert_eq!(sheet.frame_count(), 32);
+        assert_eq!((sheet.margin, sheet.spacing), (0, 0));
+    }
+
+    #[test]
+    fn count_margin_and_spacing() {
+        let sheet = sheet("sprites/walk.png: 8x4, 30, 1, 2").unwrap();
+        assert_eq!(sheet.path, PathBuf::from("sprites/walk.png"));
+        assert_eq!(sheet.frame_count(), 30);
+        assert_eq!((sheet.margin, sheet.spacing), (1, 2));
+    }
+
+    #[test]
+    fn colons_in_the_path() {
+        assert_eq!(sheet("a:b.png:2x1").unwrap().path, PathBuf::from("a:b.png"));
+    }
+
+    #[test]
+    fn invalid_sheets() {
+        for spec in &[
+            "walk.png",
+            "walk.png:8",
+            "walk.png:8x",
+            "walk.png:ax4",
+            "walk.png:0x4",
+            "walk.png:8x0",
+            "walk.png:8x4,0",
+            "walk.png:8x4,33",
+            "walk.png:8x4,30,x",
+            "walk.png:65536x65536",
+        ] {
+            assert!(sheet(spec).is_none(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn frames_between_margin_and_spacing() {
+        // 2x2 frames of 3x3 pixels, a margin of 1 and a spacing of 2.
+        let sheet = sheet("sheet.png:2x2,4,1,2").unwrap();
+        let mut image = RgbaImage::new(10, 10);
+        image.put_pixel(6, 6, Rgba([255, 0, 0, 255]));
+        let frame = sheet.frame(&image, 3).unwrap();
+        assert_eq!(frame.dimensions(), (3, 3));
+        assert_eq!(frame.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(frame.get_pixel(1, 1), &Rgba([0, 0, 0, 0]));
+        assert_eq!(sheet.frames(&image).unwrap().len(), 4);
+        assert!(sheet.frame(&image, 4).is_err());
+    }
+
+    #[test]
+    fn image_too_small_for_the_grid() {
+        let sheet = sheet("sheet.png:4x1,4,1,2").unwrap();
+        assert!(sheet.frame(&RgbaImage::new(10, 10), 0).is_err());
+    }
+
+    #[test]
+    fn margin_and_spacing_too_large() {
+        let image = RgbaImage::new(10, 10);
+        assert!(sheet("sheet.png:1x1,1,2147483648,0")
+            .unwrap()
+            .frame(&image, 0)
+            .is_err());
+        assert!(sheet("sheet.png:3x1,3,0,2147483648")
+            .unwrap()
+            .frame(&image, 0)
+            .is_err());
+        assert!(sheet("sheet.png:2x1,2,1,4294967295")
+            .unwrap()
+            .frame(&image, 0)
+            .is_err());
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{color::Color, config::FitMode};
+use std::{path::PathBuf, process};
+
+const USAGE: &str = "Usage: swaybg <options...>
+
+  -c, --color            Set the background color.
+  -h, --help             Show help message and quit.
+  -i, --image            Set the image to display.
+  -m, --mode             Set the mode to use for the image.
+  -o, --output           Set the output to operate on or * for all.
+  -v, --version          Show the version number and quit.
+
+Background Modes:
+  stretch, fit, fill, center, tile, or solid_color
+";
+
+/// Modes of swaybg's `-m` option.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Fit(FitMode),
+    SolidColor,
+}
+
+fn parse_mode(s: &str) -> Result<Mode, String> {
+    match s {
+        "stretch" => Ok(Mode::Fit(FitMode::Stretch)),
+        "fill" => Ok(Mode::Fit(FitMode::Fill)),
+        "fit" => Ok(Mode::Fit(FitMode::Fit)),
+        "center" => Ok(Mode::Fit(FitMode::Center)),
+        "tile" => Ok(Mode::Fit(FitMode::Tile)),
+        "solid_color" => Ok(Mode::SolidColor),
+        _ => Err(format!("invalid mode: {}", s)),
+    }
+}
+
+/// What swaybg displays on one output, `*` standing for the outputs without
+/// their own arguments.
+#[derive(Debug, Clone)]
+pub struct Output {
+    pub name: String,
+    pub image: Option<PathBuf>,
+    pub mode: Option<Mode>,
+    pub color: Option<Color>,
+}
+
+impl Output {
+    fn new(name: &str) -> Self {
+        Self {
+            name: String::from(name),
+            image: None,
+            mode: None,
+            color: None,
+        }
+    }
+}
+
+/// Whether the arguments follow the swaybg interface rather than ours: when
+/// called through a link named `swaybg`, with `--swaybg` first, or with the
+/// `-o` sway always starts its `swaybg_command` arguments with.
+pub fn is_invocation(args: &[String]) -> bool {
+    let called_as_swaybg = args
+        .first()
+        .and_then(|program| program.rsplit('/').next())
+        .map_or(false, |name| name == "swaybg");
+    let first = args.get(1).map(String::as_str);
+
+    called_as_swaybg
+        || first == Some("--swaybg")
+        || first == Some("-o")
+        || first == Some("--output")
+}
+
+/// Parses swaybg arguments the way swaybg does: `-i`, `-m` and `-c` apply to
+/// the output of the last `-o`, or to `*` before any.
+pub fn parse(args: &[String]) -> Result<Vec<Output>, String> {
+    let mut outputs: Vec<Output> = Vec::new();
+    let mut args = args.iter().skip(1).map(String::as_str).peekable();
+    if args.peek() == Some(&"--swaybg") {
+        args.next();
+    }
+
+    while let Some(arg) = args.next() {
+        let (option, inline_value) = match arg.find('=') {
+            Some(i) if arg.starts_with("--") => (&arg[..i], Some(&arg[i + 1..])),
+            _ => (arg, None),
+        };
+
+        match option {
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            "-v" | "--version" => {
+                println!("swaynimated version {}", env!("CARGO_PKG_VERSION"));
+                process::exit(0);
+            }
+            _ => {}
+        }
+
+        let value = inline_value
+            .or_else(|| args.next())
+            .ok_or_else(|| format!("{} needs a value\n\n{}", option, USAGE))?;
+
+        if let "-o" | "--output" = option {
+            match outputs.iter().position(|output| output.name == value) {
+                // The output is now the last one the options apply to.
+                Some(i) => {
+                    let output = outputs.remove(i);
+                    outputs.push(output);
+                }
+                None => outputs.push(Output::new(value)),
+            }
+            continue;
+        }
+
+        if outputs.is_empty() {
+            outputs.push(Output::new("*"));
+        }
+        let output = outputs.last_mut().unwrap();
+
+        match option {
+            "-i" | "--image" => output.image = Some(PathBuf::from(value)),
+            "-m" | "--mode" => output.mode = Some(parse_mode(value)?),
+            "-c" | "--color" => output.color = Some(value.parse()?),
+            _ => return Err(format!("unknown option {}\n\n{}", option, USAGE)),
+        }
+    }
+
+    Ok(outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        std::iter::once("swaybg")
+            .chain(args.iter().copied())
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn options_of_an_output() {
+        let outputs = parse(&args(&["-o", "DP-1", "-i", "a.png", "-m", "fill"])).unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].name, "DP-1");
+        assert_eq!(outputs[0].image, Some(PathBuf::from("a.png")));
+        assert_eq!(outputs[0].mode, Some(Mode::Fit(FitMode::Fill)));
+        assert_eq!(outputs[0].color, None);
+    }
+
+    #[test]
+    fn options_before_any_output() {
+        let outputs = parse(&args(&[
+            "--swaybg",
+            "--color=#000000",
+            "--mode",
+            "solid_color",
+        ]))
+        .unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].name, "*");
+        assert_eq!(outputs[0].color, Some(Color::BLACK));
+        assert_eq!(outputs[0].mode, Some(Mode::SolidColor));
+    }
+
+    #[test]
+    fn repeated_output() {
+        let outputs = parse(&args(&[
+            "-o", "A", "-m", "tile", "-o", "B", "-o", "A", "-i", "a.png",
+        ]))
+        .unwrap();
+        let names: Vec<_> = outputs.iter().map(|output| output.name.as_str()).collect();
+        assert_eq!(names, ["B", "A"]);
+        assert_eq!(outputs[1].mode, Some(Mode::Fit(FitMode::Tile)));
+        assert_eq!(outputs[1].image, Some(PathBuf::from("a.png")));
+    }
+
+    #[test]
+    fn no_arguments() {
+        assert!(parse(&args(&[])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        for invalid in &[
+            &["-i"][..],
+            &["-o", "A", "-m"],
+            &["-m", "zoom"],
+            &["-c", "white"],
+            &["-x", "value"],
+            &["--image"],
+        ] {
+            assert!(parse(&args(invalid)).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn invocations() {
+        let invocation = |args: &[&str]| {
+            is_invocation(&args.iter().copied().map(String::from).collect::<Vec<_>>())
+        };
+        assert!(invocation(&["/usr/bin/swaybg", "-i", "a.png"]));
+        assert!(invocation(&["swaynimated", "--swaybg"]));
+        assert!(invocation(&["swaynimated", "-o", "*"]));
+        assert!(invocation(&["swaynimated", "--output", "*"]));
+        assert!(!invocation(&["swaynimated", "a.gif"]));
+        assert!(!invocation(&["swaynimated"]));
+        assert!(!invocation(&[]));
+    }
+}
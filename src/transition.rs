@@ -0,0 +1,136 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Marker of `shaders/transition.glsl` replaced by the transition function.
+const MARKER: &str = "// TRANSITION";
+
+/// How the previous source is replaced by the next one.
+///
+/// Custom transitions follow the gl-transitions API: they define
+/// `vec4 transition(vec2 uv)` and can use `getFromColor`, `getToColor`,
+/// `progress` and `ratio`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Transition {
+    Fade,
+    Wipe,
+    Dissolve,
+    Zoom,
+    Custom(PathBuf),
+}
+
+impl FromStr for Transition {
+    type Err = String;
+
+    /// Anything else than a built-in transition is a GLSL file, which must
+    /// exist so that a misspelled name is not taken for one.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fade" | "crossfade" => Ok(Transition::Fade),
+            "wipe" => Ok(Transition::Wipe),
+            "dissolve" => Ok(Transition::Dissolve),
+            "zoom" => Ok(Transition::Zoom),
+            path if Path::new(path).is_file() => Ok(Transition::Custom(PathBuf::from(path))),
+            _ => Err(format!(
+                "unknown transition '{}' (expected fade, wipe, dissolve, zoom or a GLSL file)",
+                s
+            )),
+        }
+    }
+}
+
+impl Transition {
+    fn glsl(&self) -> io::Result<String> {
+        Ok(match self {
+            Transition::Fade => String::from(include_str!("shaders/transition/fade.glsl")),
+            Transition::Wipe => String::from(include_str!("shaders/transition/wipe.glsl")),
+            Transition::Dissolve => String::from(include_str!("shaders/transition/dissolve.glsl")),
+            Transition::Zoom => String::from(include_str!("shaders/transition/zoom.glsl")),
+            Transition::Custom(path) => with_constant_parameters(&fs::read_to_string(path)?),
+        })
+    }
+
+    /// Splices the transition function into a fragment shader source.
+    pub fn splice(&self, fragment: &str) -> io::Result<String> {
+        Ok(fragment.replacen(MARKER, &self.glsl()?, 1))
+    }
+}
+
+/// gl-transitions declare their parameters as loose uniforms documenting
+/// their default value (`uniform float smoothness; // = 0.5`), which Vulkan
+/// GLSL does not allow. They are turned into constants set to that default.
+fn with_constant_parameters(code: &str) -> String {
+    code.lines()
+        .map(|line| {
+            let trimmed = line.trim();
+            if !trimmed.starts_with("uniform ") {
+                return String::from(line);
+            }
+
+            let mut parts = trimmed.splitn(2, "//");
+            let declaration = parts.next().unwrap_or("").trim().trim_end_matches(';');
+            let default = parts.next().map(str::trim).filter(|c| c.starts_with('='));
+
+            match default {
+                Some(default) => format!(
+                    "const {} = {};",
+                    declaration.trim_start_matches("uniform ").trim(),
+                    default[1..].trim().trim_end_matches(';')
+                ),
+                None => String::from(line),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_transition() {
+        let cases = [
+            ("fade", Transition::Fade),
+            ("crossfade", Transition::Fade),
+            ("wipe", Transition::Wipe),
+            ("dissolve", Transition::Dissolve),
+            ("zoom", Transition::Zoom),
+        ];
+        for (s, expected) in &cases {
+            assert_eq!(s.parse::<Transition>().as_ref(), Ok(expected), "{}", s);
+        }
+        let file = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/shaders/transition/wipe.glsl"
+        );
+        assert_eq!(file.parse(), Ok(Transition::Custom(PathBuf::from(file))));
+        assert!("slide".parse::<Transition>().is_err());
+    }
+
+    #[test]
+    fn constant_parameters() {
+        let code = [
+            "uniform float smoothness; // = 0.5",
+            "  uniform vec2 direction;// = vec2(1.0, -1.0);",
+            "uniform ivec2 squares;// = ivec2(10,10)",
+            // Without a default, left for the compiler to report.
+            "uniform float unknown;",
+            "uniform float documented; // a ratio",
+            "float progressOf(float p) { return p; } // = p",
+        ]
+        .join("\n");
+        let expected = [
+            "const float smoothness = 0.5;",
+            "const vec2 direction = vec2(1.0, -1.0);",
+            "const ivec2 squares = ivec2(10,10);",
+            "uniform float unknown;",
+            "uniform float documented; // a ratio",
+            "float progressOf(float p) { return p; } // = p",
+        ]
+        .join("\n");
+        assert_eq!(with_constant_parameters(&code), expected);
+    }
+}
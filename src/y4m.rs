@@ -0,0 +1,227 @@
+use crate::source::SourceError;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+const SIGNATURE: &str = "YUV4MPEG2";
+
+/// How the planes of a video are laid out and converted to colors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Format {
+    pub width: u32,
+    pub height: u32,
+    /// Size of the U and V planes.
+    pub chroma: (u32, u32),
+    /// Luma from 0 to 255 rather than 16 to 235.
+    pub full_range: bool,
+    /// BT.709 colors rather than BT.601. Y4M does not tell, HD videos are
+    /// assumed to use the former as players do.
+    pub bt709: bool,
+}
+
+impl Format {
+    fn parse(header: &str) -> Result<Self, SourceError> {
+        let mut parameters = header.split_whitespace();
+        if parameters.next() != Some(SIGNATURE) {
+            return Err("not a YUV4MPEG2 file".into());
+        }
+
+        let (mut width, mut height) = (None, None);
+        let mut colorspace = "420jpeg";
+        let mut full_range = false;
+        for parameter in parameters {
+            let mut chars = parameter.chars();
+            let tag = chars.next();
+            let value = chars.as_str();
+            match tag {
+                Some('W') => width = value.parse().ok(),
+                Some('H') => height = value.parse().ok(),
+                Some('C') => colorspace = value,
+                Some('X') => full_range = value == "COLORRANGE=FULL",
+                _ => {}
+            }
+        }
+
+        let (width, height): (u32, u32) = match (width, height) {
+            (Some(width), Some(height)) if width > 0 && height > 0 => (width, height),
+            _ => return Err("missing frame size".into()),
+        };
+        let half = |length: u32| length / 2 + length % 2;
+        let chroma = match colorspace {
+            "420" | "420jpeg" | "420paldv" | "420mpeg2" => (half(width), half(height)),
+            "422" => (half(width), height),
+            "444" => (width, height),
+            // Gray, with neutral chroma planes of one texel.
+            "mono" => (1, 1),
+            _ => {
+                return Err(format!(
+                    "unsupported colorspace C{}, only 8-bit ones are",
+                    colorspace
+                )
+                .into())
+            }
+        };
+
+        Ok(Self {
+            width,
+            height,
+            chroma,
+            full_range,
+            bt709: height >= 720,
+        })
+    }
+
+    fn luma_size(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+
+    fn chroma_size(&self) -> usize {
+        self.chroma.0 as usize * self.chroma.1 as usize
+    }
+}
+
+/// Planes of a frame, one byte per texel.
+pub struct Planes {
+    pub y: Vec<u8>,
+    pub u: Vec<u8>,
+    pub v: Vec<u8>,
+}
+
+/// A YUV4MPEG2 file, read one frame at a time.
+pub struct Reader {
+    file: BufReader<File>,
+    pub format: Format,
+    /// Where the planes of every frame start.
+    offsets: Vec<u64>,
+    mono: bool,
+}
+
+impl Reader {
+    /// Reads the header, then the header of every frame to find them.
+    pub fn open(path: &Path) -> Result<Self, SourceError> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut header = String::new();
+        file.read_line(&mut header)?;
+        let format = Format::parse(&header)?;
+        let mono = header
+            .split_whitespace()
+            .any(|parameter| parameter == "Cmono");
+
+        let plane_size = if mono {
+            format.luma_size()
+        } else {
+            format.luma_size() + 2 * format.chroma_size()
+        } as u64;
+        let length = file.get_ref().metadata()?.len();
+        let mut offsets = Vec::new();
+        let mut position = header.len() as u64;
+        let mut line = String::new();
+        loop {
+            file.seek(SeekFrom::Start(position))?;
+            line.clear();
+            let read = file.read_line(&mut line)? as u64;
+            if !line.starts_with("FRAME") || position + read + plane_size > length {
+                break;
+            }
+            offsets.push(position + read);
+            position += read + plane_size;
+        }
+
+        if offsets.is_empty() {
+            return Err("no frame in the video".into());
+        }
+        Ok(Self {
+            file,
+            format,
+            offsets,
+            mono,
+        })
+    }
+
+    pub fn total_frame(&self) -> u32 {
+        self.offsets.len() as u32
+    }
+
+    pub fn read(&mut self, index: u32) -> Result<Planes, SourceError> {
+        let offset = *self.offsets.get(index as usize).ok_or("no such frame")?;
+        self.file.seek(SeekFrom::Start(offset))?;
+
+        let (luma_size, chroma_size) = (self.format.luma_size(), self.format.chroma_size());
+        let file = &mut self.file;
+        let mut plane = |size| -> Result<Vec<u8>, SourceError> {
+            let mut plane = vec![0; size];
+            file.read_exact(&mut plane)?;
+            Ok(plane)
+        };
+        let y = plane(luma_size)?;
+        if self.mono {
+            return Ok(Planes {
+                y,
+                u: vec![128],
+                v: vec![128],
+            });
+        }
+        let u = plane(chroma_size)?;
+        let v = plane(chroma_size)?;
+        Ok(Planes { y, u, v })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_colorspace() {
+        let format = Format::parse("YUV4MPEG2 W640 H480 F30:1 Ip A1:1").unwrap();
+        assert_eq!(
+            format,
+            Format {
+                width: 640,
+                height: 480,
+                chroma: (320, 240),
+                full_range: false,
+                bt709: false,
+            }
+        );
+    }
+
+    #[test]
+    fn colorspaces() {
+        let chroma = |header: &str| Format::parse(header).unwrap().chroma;
+        assert_eq!(chroma("YUV4MPEG2 W5 H3 C420mpeg2"), (3, 2));
+        assert_eq!(chroma("YUV4MPEG2 W5 H3 C422"), (3, 3));
+        assert_eq!(chroma("YUV4MPEG2 W5 H3 C444"), (5, 3));
+        assert_eq!(chroma("YUV4MPEG2 W5 H3 Cmono"), (1, 1));
+    }
+
+    #[test]
+    fn full_range_hd() {
+        let format = Format::parse("YUV4MPEG2 C444 XCOLORRANGE=FULL W1280 H720\n").unwrap();
+        assert!(format.full_range);
+        assert!(format.bt709);
+    }
+
+    #[test]
+    fn largest_size() {
+        let format = Format::parse("YUV4MPEG2 W4294967295 H4294967295").unwrap();
+        assert_eq!(format.chroma, (2_147_483_648, 2_147_483_648));
+    }
+
+    #[test]
+    fn invalid_headers() {
+        for header in &[
+            "",
+            "YUV4MPEG W640 H480",
+            "YUV4MPEG2 W640",
+            "YUV4MPEG2 W0 H480",
+            "YUV4MPEG2 W-1 H480",
+            "YUV4MPEG2 Wx H480",
+            "YUV4MPEG2 W640 H480 C420p10",
+        ] {
+            assert!(Format::parse(header).is_err(), "{}", header);
+        }
+    }
+}